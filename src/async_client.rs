@@ -0,0 +1,561 @@
+//! Asynchronous client (requires `async` feature)
+//!
+//! [AsyncRParifClient](./struct.AsyncRParifClient.html) offers the same endpoints as
+//! [RParifClient](../client/struct.RParifClient.html) but relies on reqwest's asynchronous
+//! API, so it can also be compiled for `wasm32-unknown-unknown` where blocking HTTP isn't
+//! available. For WASM targets, build with `--no-default-features --features async`.
+//!
+//! ```rust,no_run
+//! use rparif::async_client::AsyncRParifClient;
+//!
+//! # async fn run() -> Result<(), rparif::error::RParifError> {
+//! let client = AsyncRParifClient::new("my-api-key");
+//! for index in client.index().await? {
+//!     println!("{}", index);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+use futures::stream::{self, StreamExt, TryStreamExt};
+use json::JsonValue;
+use reqwest::header::{RETRY_AFTER, USER_AGENT};
+use reqwest::{Client, StatusCode};
+
+use crate::client::{
+    covered_by_airparif, read_response, BodyBuffer, ConditionalCache, Config, RParifClientBuilder,
+    RequestUrl,
+};
+use crate::error::RParifError;
+use crate::objects::{Day, Episode, Index, Insee, Level};
+
+/// Target of log messages
+const LOG_TARGET: &str = "rparif::async_client";
+
+/// Asynchronous client to call HTTP API
+#[derive(Debug)]
+pub struct AsyncRParifClient<'a> {
+    /// HTTP client
+    client: Client,
+    /// URL and conversion settings
+    config: Config<'a>,
+    /// Last responses used for conditional requests, if enabled
+    conditional: Option<ConditionalCache>,
+}
+
+impl<'a> AsyncRParifClient<'a> {
+    /// Construct a new client
+    ///
+    /// # Arguments
+    ///
+    /// * `api_key` - [API key](https://www.airparif.asso.fr/rss/api) to authenticate call
+    ///
+    /// # Panics
+    ///
+    /// Like reqwest's `Client::new`, it panics if the HTTP client can't be initialized,
+    /// use [`builder`](#method.builder) to handle this error
+    pub fn new(api_key: &'a str) -> AsyncRParifClient<'a> {
+        RParifClientBuilder::new(api_key)
+            .build_async()
+            .expect("Failed to build HTTP client")
+    }
+
+    /// Construct a new client owning the API key, so that it is `'static` (see
+    /// [RParifClient::new_owned](../client/struct.RParifClient.html#method.new_owned))
+    ///
+    /// # Arguments
+    ///
+    /// * `api_key` - [API key](https://www.airparif.asso.fr/rss/api) to authenticate call
+    ///
+    /// # Panics
+    ///
+    /// Like reqwest's `Client::new`, it panics if the HTTP client can't be initialized,
+    /// use [`RParifClientBuilder::new_owned`](../client/struct.RParifClientBuilder.html#method.new_owned)
+    /// to handle this error
+    pub fn new_owned(api_key: String) -> AsyncRParifClient<'static> {
+        RParifClientBuilder::new_owned(api_key)
+            .build_async()
+            .expect("Failed to build HTTP client")
+    }
+
+    /// Return a [builder](../client/struct.RParifClientBuilder.html) to configure a client,
+    /// use its `build_async` method to get an asynchronous client
+    ///
+    /// # Arguments
+    ///
+    /// * `api_key` - [API key](https://www.airparif.asso.fr/rss/api) to authenticate call
+    pub fn builder(api_key: &'a str) -> RParifClientBuilder<'a> {
+        RParifClientBuilder::new(api_key)
+    }
+
+    /// Constructor used by [RParifClientBuilder](../client/struct.RParifClientBuilder.html#method.build_async)
+    ///
+    /// # Arguments
+    ///
+    /// * `client` - HTTP client
+    ///
+    /// * `config` - URL and conversion settings
+    ///
+    /// * `conditional_requests` - Send conditional requests
+    pub(crate) fn from_config(
+        client: Client,
+        config: Config<'a>,
+        conditional_requests: bool,
+    ) -> AsyncRParifClient<'a> {
+        AsyncRParifClient {
+            client,
+            config,
+            conditional: if conditional_requests {
+                Some(ConditionalCache::default())
+            } else {
+                None
+            },
+        }
+    }
+
+    /// Execute a query to HTTP AirParif endpoint and return the body as JSON
+    ///
+    /// # Arguments
+    ///
+    /// * `url` - URL to call
+    ///
+    /// # Errors
+    ///
+    /// See [RParifClient](../client/struct.RParifClient.html#method.index) errors
+    async fn execute_query(&self, url: &RequestUrl) -> Result<JsonValue, RParifError> {
+        // std::time::Instant isn't supported on WASM
+        #[cfg(not(target_arch = "wasm32"))]
+        let start = std::time::Instant::now();
+        let (status, result) = self.send_query(url).await;
+        #[cfg(not(target_arch = "wasm32"))]
+        let duration = start.elapsed();
+        #[cfg(target_arch = "wasm32")]
+        let duration = std::time::Duration::ZERO;
+        self.config
+            .record_metrics(url.as_str(), duration, status, result.is_ok());
+        result
+    }
+
+    /// Send a request to HTTP AirParif endpoint and return the HTTP status, if a response
+    /// was received, along with the body as JSON
+    ///
+    /// # Arguments
+    ///
+    /// * `url` - URL to call
+    async fn send_query(&self, url: &RequestUrl) -> (Option<u16>, Result<JsonValue, RParifError>) {
+        let mut request = self
+            .client
+            .get(url.as_str())
+            .header(USER_AGENT, self.config.user_agent.as_str());
+        if let Some(conditional) = &self.conditional {
+            for (name, value) in conditional.request_headers(url.as_str()) {
+                request = request.header(name, value);
+            }
+        }
+        let response = match request.send().await {
+            Ok(response) => response,
+            Err(err) => return (None, Err(err.into())),
+        };
+        let status = response.status();
+        if status == StatusCode::NOT_MODIFIED {
+            if let Some(body) = self.conditional.as_ref().and_then(|c| c.body(url.as_str())) {
+                debug!(target: LOG_TARGET, "Not modified, reusing last response");
+                let status = StatusCode::OK.as_u16();
+                return (
+                    Some(status),
+                    read_response(url.as_str(), status, true, None, &body),
+                );
+            }
+        }
+        let headers = response.headers().clone();
+        let retry_after = response
+            .headers()
+            .get(RETRY_AFTER)
+            .and_then(|value| value.to_str().ok())
+            .map(|value| value.to_string());
+        let result = match self.read_body(url, response).await {
+            Ok(body) => {
+                if let (Some(conditional), true) = (&self.conditional, status.is_success()) {
+                    conditional.store(url.as_str(), &headers, &body);
+                }
+                read_response(
+                    url.as_str(),
+                    status.as_u16(),
+                    status.is_success(),
+                    retry_after.as_deref(),
+                    &body,
+                )
+            }
+            Err(err) => Err(err),
+        };
+        (Some(status.as_u16()), result)
+    }
+
+    /// Read the body of a response, up to the maximum size
+    ///
+    /// # Arguments
+    ///
+    /// * `url` - URL called
+    ///
+    /// * `response` - HTTP response
+    ///
+    /// # Errors
+    ///
+    /// * [RParifError::RequestError](../error/enum.RParifError.html#variant.RequestError) when reqwest lib
+    ///   fails to read the body
+    ///
+    /// * [RParifError::ResponseTooLarge](../error/enum.RParifError.html#variant.ResponseTooLarge) when
+    ///   the body exceeds the maximum size
+    #[allow(unused_mut)]
+    async fn read_body(
+        &self,
+        url: &RequestUrl,
+        mut response: reqwest::Response,
+    ) -> Result<String, RParifError> {
+        let mut buffer = BodyBuffer::new(self.config.max_response_bytes, response.content_length());
+        // Body can't be read by chunks on WASM, it's checked once fully received
+        #[cfg(not(target_arch = "wasm32"))]
+        while !buffer.exceeded() {
+            match response.chunk().await? {
+                Some(chunk) => buffer.push(&chunk),
+                None => break,
+            };
+        }
+        #[cfg(target_arch = "wasm32")]
+        if !buffer.exceeded() {
+            buffer.push(&response.bytes().await?);
+        }
+        buffer.into_text(url.as_str())
+    }
+
+    /// Same as [RParifClient::index](../client/struct.RParifClient.html#method.index)
+    ///
+    /// # Errors
+    ///
+    /// See [RParifClient::index](../client/struct.RParifClient.html#method.index)
+    pub async fn index(&self) -> Result<Vec<Index>, RParifError> {
+        debug!(target: LOG_TARGET, "Querying indice endpoint");
+        let response = self.execute_query(&self.config.index_url()).await?;
+        self.config.index_to_index(response)
+    }
+
+    /// Same as [RParifClient::index_day](../client/struct.RParifClient.html#method.index_day)
+    ///
+    /// # Arguments
+    ///
+    /// * `day` - Which day to get indices pollution for
+    ///
+    /// # Errors
+    ///
+    /// See [RParifClient::index_day](../client/struct.RParifClient.html#method.index_day)
+    pub async fn index_day(&self, day: Day) -> Result<Vec<Index>, RParifError> {
+        debug!(target: LOG_TARGET, "Querying indiceJour endpoint");
+        let response = self.execute_query(&self.config.index_day_url(day)).await?;
+        self.config.index_day_to_index(response, None)
+    }
+
+    /// Same as [RParifClient::index_day_filtered](../client/struct.RParifClient.html#method.index_day_filtered)
+    ///
+    /// # Arguments
+    ///
+    /// * `day` - Which day to get indices pollution for
+    ///
+    /// * `pollutants` - Pollutants to keep
+    ///
+    /// # Errors
+    ///
+    /// See [RParifClient::index_day](../client/struct.RParifClient.html#method.index_day)
+    pub async fn index_day_filtered(
+        &self,
+        day: Day,
+        pollutants: &[&str],
+    ) -> Result<Vec<Index>, RParifError> {
+        debug!(target: LOG_TARGET, "Querying indiceJour endpoint");
+        let response = self.execute_query(&self.config.index_day_url(day)).await?;
+        self.config.index_day_to_index(response, Some(pollutants))
+    }
+
+    /// Same as [RParifClient::index_city](../client/struct.RParifClient.html#method.index_city),
+    /// up to [`max_concurrency`](../client/struct.RParifClientBuilder.html#method.max_concurrency)
+    /// batches are queried concurrently, results keep the order of `cities`
+    ///
+    /// # Arguments
+    ///
+    /// * `cities` - List of INSEE city code
+    ///
+    /// # Errors
+    ///
+    /// See [RParifClient::index_city](../client/struct.RParifClient.html#method.index_city)
+    pub async fn index_city<I, S>(&self, cities: I) -> Result<Vec<Index>, RParifError>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        debug!(target: LOG_TARGET, "Querying idxville endpoint");
+        let cities: Vec<Insee> = cities
+            .into_iter()
+            .map(|c| c.as_ref().parse())
+            .collect::<Result<_, RParifError>>()?;
+        if cities.is_empty() {
+            return Err(RParifError::EmptyCityList);
+        }
+        let batches: Vec<Vec<Index>> = stream::iter(cities.chunks(self.config.city_batch_size))
+            .map(|batch| async move {
+                debug!(target: LOG_TARGET, "Querying cities {:?}", batch);
+                let response = self
+                    .execute_query(&self.config.index_city_url(batch))
+                    .await?;
+                self.config.idxville_to_index(response)
+            })
+            .buffered(self.config.max_concurrency)
+            .try_collect()
+            .await?;
+        Ok(batches.into_iter().flatten().collect())
+    }
+
+    /// Same as [RParifClient::episode](../client/struct.RParifClient.html#method.episode)
+    ///
+    /// # Errors
+    ///
+    /// See [RParifClient::episode](../client/struct.RParifClient.html#method.episode)
+    pub async fn episode(&self) -> Result<Vec<Episode>, RParifError> {
+        debug!(target: LOG_TARGET, "Querying episode endpoint");
+        let response = self.execute_query(&self.config.episode_url()).await?;
+        self.config.episode_to_episode(response)
+    }
+
+    /// Same as [RParifClient::active_alerts](../client/struct.RParifClient.html#method.active_alerts)
+    ///
+    /// # Errors
+    ///
+    /// See [RParifClient::episode](../client/struct.RParifClient.html#method.episode)
+    pub async fn active_alerts(&self) -> Result<Vec<Episode>, RParifError> {
+        Ok(self
+            .episode()
+            .await?
+            .into_iter()
+            .filter(|episode| episode.max_level() >= Level::Info)
+            .collect())
+    }
+
+    /// Same as [RParifClient::episode_for_cities](../client/struct.RParifClient.html#method.episode_for_cities)
+    ///
+    /// # Arguments
+    ///
+    /// * `cities` - List of INSEE city code
+    ///
+    /// # Errors
+    ///
+    /// See [RParifClient::episode_for_cities](../client/struct.RParifClient.html#method.episode_for_cities)
+    pub async fn episode_for_cities(&self, cities: &[&str]) -> Result<Vec<Episode>, RParifError> {
+        if !covered_by_airparif(cities)? {
+            debug!(target: LOG_TARGET, "No city of {:?} in Île-de-France", cities);
+            return Ok(Vec::new());
+        }
+        self.episode().await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use httpmock::Method::GET;
+    use httpmock::MockServer;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_index() {
+        let server = MockServer::start_async().await;
+        let mock = server
+            .mock_async(|when, then| {
+                when.method(GET)
+                    .path("/indice")
+                    .query_param("key", "api-key");
+                then.status(200).body("[{\"date\":\"jour\",\"indice\":35}]");
+            })
+            .await;
+
+        let base_url = server.base_url();
+        let client = AsyncRParifClient::builder("api-key")
+            .base_url(&base_url)
+            .build_async()
+            .unwrap();
+        let result = client.index().await;
+
+        mock.assert_hits_async(1).await;
+        assert_eq!(result.unwrap()[0].index(), 35);
+    }
+
+    #[tokio::test]
+    async fn test_index_city_batches() {
+        let server = MockServer::start_async().await;
+        let mock = server
+            .mock_async(|when, then| {
+                when.method(GET).path("/idxville");
+                then.status(200).body(
+                    "[{\"ninsee\":\"75101\",\"jour\":{\"indice\":50,\"polluants\":[\"pm10\"]}}]",
+                );
+            })
+            .await;
+
+        let base_url = server.base_url();
+        let client = AsyncRParifClient::builder("api-key")
+            .base_url(&base_url)
+            .city_batch_size(1)
+            .build_async()
+            .unwrap();
+        let result = client.index_city(vec!["75101", "75102"]).await;
+
+        mock.assert_hits_async(2).await;
+        assert_eq!(result.unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_episode_call_error() {
+        let server = MockServer::start_async().await;
+        let _mock = server
+            .mock_async(|when, then| {
+                when.method(GET).path("/episode");
+                then.status(500).body("{\"erreur\":\"Erreur\"}");
+            })
+            .await;
+
+        let base_url = server.base_url();
+        let client = AsyncRParifClient::builder("api-key")
+            .base_url(&base_url)
+            .build_async()
+            .unwrap();
+
+        match client.episode().await.err().unwrap() {
+            RParifError::CallError {
+                status, message, ..
+            } => {
+                assert_eq!(status, 500);
+                assert_eq!(message, Some("Erreur".to_string()));
+            }
+            _ => panic!("Wrong error"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_index_city_concurrent() {
+        let server = MockServer::start_async().await;
+        let mut mocks = Vec::new();
+        for (city, index) in &[("75101", 50), ("75102", 40), ("75103", 30)] {
+            let mock = server
+                .mock_async(|when, then| {
+                    when.method(GET)
+                        .path("/idxville")
+                        .query_param("villes", *city);
+                    then.status(200).body(format!(
+                        "[{{\"ninsee\":\"{}\",\"jour\":{{\"indice\":{},\"polluants\":[\"pm10\"]}}}}]",
+                        city, index
+                    ));
+                })
+                .await;
+            mocks.push(mock);
+        }
+
+        let base_url = server.base_url();
+        let client = AsyncRParifClient::builder("api-key")
+            .base_url(&base_url)
+            .city_batch_size(1)
+            .max_concurrency(2)
+            .build_async()
+            .unwrap();
+        let result = client
+            .index_city(vec!["75101", "75102", "75103"])
+            .await
+            .unwrap();
+
+        for mock in mocks {
+            mock.assert_hits_async(1).await;
+        }
+        let indices: Vec<u32> = result.iter().map(|i| i.index()).collect();
+        assert_eq!(indices, vec![50, 40, 30]);
+    }
+
+    #[tokio::test]
+    async fn test_metrics() {
+        let server = MockServer::start_async().await;
+        server
+            .mock_async(|when, then| {
+                when.method(GET).path("/episode");
+                then.status(403).body("{\"erreur\":\"Clé invalide\"}");
+            })
+            .await;
+
+        let metrics = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let recorded = metrics.clone();
+        let base_url = server.base_url();
+        let client = AsyncRParifClient::builder("api-key")
+            .base_url(&base_url)
+            .metrics(move |m| recorded.lock().unwrap().push(m.clone()))
+            .build_async()
+            .unwrap();
+        assert!(client.episode().await.is_err());
+
+        let metrics = metrics.lock().unwrap();
+        assert_eq!(metrics.len(), 1);
+        assert_eq!(metrics[0].endpoint(), "episode");
+        assert_eq!(metrics[0].status(), Some(403));
+        assert!(!metrics[0].success());
+    }
+
+    #[tokio::test]
+    async fn test_conditional_requests() {
+        let server = MockServer::start_async().await;
+        let full = server
+            .mock_async(|when, then| {
+                when.method(GET).path("/indice");
+                then.status(200)
+                    .header("ETag", "\"v1\"")
+                    .body("[{\"date\":\"jour\",\"indice\":35}]");
+            })
+            .await;
+
+        let base_url = server.base_url();
+        let client = AsyncRParifClient::builder("api-key")
+            .base_url(&base_url)
+            .conditional_requests(true)
+            .build_async()
+            .unwrap();
+        let first = client.index().await.unwrap();
+        full.assert_hits_async(1).await;
+        full.delete_async().await;
+
+        let not_modified = server
+            .mock_async(|when, then| {
+                when.method(GET)
+                    .path("/indice")
+                    .header("If-None-Match", "\"v1\"");
+                then.status(304);
+            })
+            .await;
+        let second = client.index().await.unwrap();
+        not_modified.assert_hits_async(1).await;
+        assert_eq!(first, second);
+    }
+
+    #[tokio::test]
+    async fn test_max_response_bytes() {
+        let server = MockServer::start_async().await;
+        server
+            .mock_async(|when, then| {
+                when.method(GET).path("/episode");
+                then.status(200)
+                    .body("[{\"date\":\"jour\",\"detail\":\"\"}]");
+            })
+            .await;
+
+        let base_url = server.base_url();
+        let client = AsyncRParifClient::builder("api-key")
+            .base_url(&base_url)
+            .max_response_bytes(8)
+            .build_async()
+            .unwrap();
+        match client.episode().await.err().unwrap() {
+            RParifError::ResponseTooLarge { limit, .. } => assert_eq!(limit, 8),
+            _ => panic!("Wrong error"),
+        }
+    }
+}
@@ -0,0 +1,336 @@
+//! In-memory cache on top of [RParifClient](../client/struct.RParifClient.html)
+//!
+//! AirParif data are only updated a few times a day, so there is no need to call
+//! HTTP API each time. [CachedRParifClient](./struct.CachedRParifClient.html) keeps
+//! converted results per endpoint (and parameters) for a configurable duration.
+//! Expiration relies on the [clock](../clock/index.html) of the client.
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+
+use crate::client::RParifClient;
+use crate::error::RParifError;
+use crate::objects::{Day, Episode, Index};
+
+/// Target of log messages
+const LOG_TARGET: &str = "rparif::cache";
+
+/// Value stored in cache
+#[derive(Clone, Debug)]
+enum CachedValue {
+    /// Result of `indice`, `indiceJour` or `idxville` endpoints
+    Indices(Vec<Index>),
+    /// Result of `episode` endpoint
+    Episodes(Vec<Episode>),
+}
+
+/// Client that memoizes results of [RParifClient](../client/struct.RParifClient.html)
+/// for a given duration
+#[derive(Debug)]
+pub struct CachedRParifClient<'a> {
+    /// Client used on cache miss
+    client: RParifClient<'a>,
+    /// How long a result is kept
+    ttl: Duration,
+    /// Cached results per endpoint and parameters, with insertion time
+    cache: Mutex<HashMap<String, (DateTime<Utc>, CachedValue)>>,
+}
+
+impl<'a> CachedRParifClient<'a> {
+    /// Constructor
+    ///
+    /// # Arguments
+    ///
+    /// * `client` - Client used to call HTTP API when there's no valid cached result
+    ///
+    /// * `ttl` - How long a result is kept in cache
+    pub fn new(client: RParifClient<'a>, ttl: Duration) -> CachedRParifClient<'a> {
+        CachedRParifClient {
+            client,
+            ttl,
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Return the underlying client
+    pub fn client(&self) -> &RParifClient<'a> {
+        &self.client
+    }
+
+    /// Return how long a result is kept in cache
+    pub fn ttl(&self) -> Duration {
+        self.ttl
+    }
+
+    /// Remove all cached results
+    pub fn clear(&self) {
+        debug!(target: LOG_TARGET, "Clearing cache");
+        self.cache.lock().unwrap().clear();
+    }
+
+    /// Return cached value for `key` if it hasn't expired
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - endpoint and its parameters
+    fn get(&self, key: &str) -> Option<CachedValue> {
+        let now = self.client.clock().now();
+        let cache = self.cache.lock().unwrap();
+        cache
+            .get(key)
+            .filter(|(inserted, _)| {
+                // A clock going backward keeps the value
+                (now - *inserted)
+                    .to_std()
+                    .map(|elapsed| elapsed < self.ttl)
+                    .unwrap_or(true)
+            })
+            .map(|(_, value)| value.clone())
+    }
+
+    /// Store a value in cache
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - endpoint and its parameters
+    ///
+    /// * `value` - value to store
+    fn put(&self, key: String, value: CachedValue) {
+        let now = self.client.clock().now();
+        self.cache.lock().unwrap().insert(key, (now, value));
+    }
+
+    /// Return cached indices for `key` or call `fetch` and cache its result
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - endpoint and its parameters
+    ///
+    /// * `fetch` - function that call the HTTP API on cache miss
+    fn indices<F>(&self, key: String, fetch: F) -> Result<Vec<Index>, RParifError>
+    where
+        F: FnOnce() -> Result<Vec<Index>, RParifError>,
+    {
+        if let Some(CachedValue::Indices(result)) = self.get(&key) {
+            debug!(target: LOG_TARGET, "Cache hit : {}", key);
+            return Ok(result);
+        }
+        debug!(target: LOG_TARGET, "Cache miss : {}", key);
+        let result = fetch()?;
+        self.put(key, CachedValue::Indices(result.clone()));
+        Ok(result)
+    }
+
+    /// Same as [RParifClient::index](../client/struct.RParifClient.html#method.index) but
+    /// result is cached
+    ///
+    /// # Errors
+    ///
+    /// See [RParifClient::index](../client/struct.RParifClient.html#method.index)
+    pub fn index(&self) -> Result<Vec<Index>, RParifError> {
+        self.indices("indice".to_string(), || self.client.index())
+    }
+
+    /// Same as [RParifClient::index_day](../client/struct.RParifClient.html#method.index_day) but
+    /// result is cached per `day`
+    ///
+    /// # Arguments
+    ///
+    /// * `day` - Which day to get indices pollution for
+    ///
+    /// # Errors
+    ///
+    /// See [RParifClient::index_day](../client/struct.RParifClient.html#method.index_day)
+    pub fn index_day(&self, day: Day) -> Result<Vec<Index>, RParifError> {
+        self.indices(format!("indiceJour?date={:?}", day), || {
+            self.client.index_day(day)
+        })
+    }
+
+    /// Same as [RParifClient::index_city](../client/struct.RParifClient.html#method.index_city) but
+    /// result is cached per list of cities
+    ///
+    /// # Arguments
+    ///
+    /// * `cities` - List of INSEE city code
+    ///
+    /// # Errors
+    ///
+    /// See [RParifClient::index_city](../client/struct.RParifClient.html#method.index_city)
+    pub fn index_city<I, S>(&self, cities: I) -> Result<Vec<Index>, RParifError>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let cities: Vec<String> = cities.into_iter().map(|c| c.as_ref().to_string()).collect();
+        self.indices(format!("idxville?villes={}", cities.join(",")), || {
+            self.client.index_city(&cities)
+        })
+    }
+
+    /// Same as [RParifClient::episode](../client/struct.RParifClient.html#method.episode) but
+    /// result is cached
+    ///
+    /// # Errors
+    ///
+    /// See [RParifClient::episode](../client/struct.RParifClient.html#method.episode)
+    pub fn episode(&self) -> Result<Vec<Episode>, RParifError> {
+        let key = "episode";
+        if let Some(CachedValue::Episodes(result)) = self.get(key) {
+            debug!(target: LOG_TARGET, "Cache hit : {}", key);
+            return Ok(result);
+        }
+        debug!(target: LOG_TARGET, "Cache miss : {}", key);
+        let result = self.client.episode()?;
+        self.put(key.to_string(), CachedValue::Episodes(result.clone()));
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use chrono::TimeDelta;
+    use httpmock::Method::GET;
+    use httpmock::MockServer;
+
+    use crate::clock::Clock;
+    use crate::transport::MemoryTransport;
+
+    use super::*;
+
+    /// Clock that only moves forward when asked to sleep
+    #[derive(Debug)]
+    struct FakeClock(Mutex<DateTime<Utc>>);
+
+    impl Clock for FakeClock {
+        fn now(&self) -> DateTime<Utc> {
+            *self.0.lock().unwrap()
+        }
+
+        fn sleep(&self, duration: Duration) {
+            *self.0.lock().unwrap() += TimeDelta::from_std(duration).unwrap();
+        }
+    }
+
+    #[test]
+    // Second call must use cache
+    fn test_index_cached() {
+        let server = MockServer::start();
+        let mock = server.mock(|when, then| {
+            when.method(GET).path("/indice");
+            then.status(200).body("[{\"date\":\"jour\",\"indice\":35}]");
+        });
+
+        let base_url = server.base_url();
+        let client = CachedRParifClient::new(
+            RParifClient::new_test("api-key", &base_url),
+            Duration::from_secs(60),
+        );
+        let first = client.index();
+        let second = client.index();
+
+        mock.assert_hits(1);
+        assert!(first.is_ok());
+        assert_eq!(first.ok(), second.ok());
+    }
+
+    #[test]
+    // Each list of cities has its own entry
+    fn test_index_city_key() {
+        let server = MockServer::start();
+        let mock_1 = server.mock(|when, then| {
+            when.method(GET)
+                .path("/idxville")
+                .query_param("villes", "75101");
+            then.status(200)
+                .body("[{\"ninsee\":\"75101\",\"jour\":{\"indice\":50,\"polluants\":[\"pm10\"]}}]");
+        });
+        let mock_2 = server.mock(|when, then| {
+            when.method(GET)
+                .path("/idxville")
+                .query_param("villes", "94028");
+            then.status(200)
+                .body("[{\"ninsee\":\"94028\",\"jour\":{\"indice\":40,\"polluants\":[\"o3\"]}}]");
+        });
+
+        let base_url = server.base_url();
+        let client = CachedRParifClient::new(
+            RParifClient::new_test("api-key", &base_url),
+            Duration::from_secs(60),
+        );
+        let first = client.index_city(vec!["75101"]).unwrap();
+        let second = client.index_city(vec!["94028"]).unwrap();
+        let _ = client.index_city(vec!["75101"]).unwrap();
+
+        mock_1.assert_hits(1);
+        mock_2.assert_hits(1);
+        assert_eq!(first[0].insee(), "75101".parse().ok());
+        assert_eq!(second[0].insee(), "94028".parse().ok());
+    }
+
+    #[test]
+    // Cities must be given to the client as is
+    fn test_index_city_empty() {
+        let client = CachedRParifClient::new(
+            RParifClient::new_test("api-key", "http://localhost:5001"),
+            Duration::from_secs(60),
+        );
+
+        match client.index_city(Vec::<&str>::new()).err().unwrap() {
+            RParifError::EmptyCityList => (),
+            err => panic!("Wrong error : {:?}", err),
+        }
+    }
+
+    #[test]
+    // Expired or cleared entries trigger a new call
+    fn test_episode_expired_and_clear() {
+        let server = MockServer::start();
+        let mock = server.mock(|when, then| {
+            when.method(GET).path("/episode");
+            then.status(200)
+                .body("[{\"date\":\"jour\",\"detail\":\"\"}]");
+        });
+
+        let base_url = server.base_url();
+        let client = CachedRParifClient::new(
+            RParifClient::new_test("api-key", &base_url),
+            Duration::from_secs(0),
+        );
+        let _ = client.episode().unwrap();
+        let _ = client.episode().unwrap();
+        mock.assert_hits(2);
+
+        let client = CachedRParifClient::new(
+            RParifClient::new_test("api-key", &base_url),
+            Duration::from_secs(60),
+        );
+        let _ = client.episode().unwrap();
+        client.clear();
+        let _ = client.episode().unwrap();
+        mock.assert_hits(4);
+    }
+
+    #[test]
+    // Expiration follows the clock of the client
+    fn test_fake_clock() {
+        let clock = Arc::new(FakeClock(Mutex::new(Utc::now())));
+        let transport = MemoryTransport::new().with_response("indice", 200, "[]");
+        let client = CachedRParifClient::new(
+            RParifClient::builder("api-key")
+                .clock(clock.clone())
+                .build_with_transport(transport),
+            Duration::from_secs(60),
+        );
+        client.put("indice".to_string(), CachedValue::Indices(Vec::new()));
+
+        clock.sleep(Duration::from_secs(59));
+        assert!(client.get("indice").is_some());
+        clock.sleep(Duration::from_secs(1));
+        assert!(client.get("indice").is_none());
+    }
+}
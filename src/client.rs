@@ -1,1183 +1,4455 @@
 //! Client that allow to make request to AirParif services and
 //! convert JSON result into objects
-use chrono::{Date, Duration, NaiveDate, Utc};
+use std::borrow::Cow;
+#[cfg(feature = "blocking")]
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+#[cfg(feature = "blocking")]
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::sync::Mutex;
+#[cfg(feature = "blocking")]
+use std::sync::OnceLock;
+use std::time::Duration;
+#[cfg(feature = "blocking")]
+use std::time::Instant;
+
+use chrono::{DateTime, NaiveDate, TimeDelta, Utc};
 use json::JsonValue;
+#[cfg(feature = "blocking")]
 use reqwest::blocking::Client;
-use reqwest::blocking::Response;
+use reqwest::header::{
+    HeaderMap, HeaderName, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED,
+};
 
+#[cfg(feature = "async")]
+use crate::async_client::AsyncRParifClient;
+use crate::clock::{paris_date, Clock, SystemClock};
 use crate::error::RParifError;
-use crate::objects::{Criteria, Day, Episode, Index};
+#[cfg(feature = "blocking")]
+use crate::objects::{group_by_date, join_episodes, IndexAlert, Level};
+use crate::objects::{mark_global_drivers, normalize_pollutant, Day, Episode, Index, Insee};
+#[cfg(all(feature = "blocking", feature = "postal"))]
+use crate::postal::PostalCodes;
+#[cfg(feature = "blocking")]
+use crate::transport::{FixtureTransport, ReqwestTransport, Transport};
+
+/// Target of log messages. Flow is logged at debug level, JSON payloads and
+/// conversion details at trace level
+const LOG_TARGET: &str = "rparif::client";
+
+/// AirParif production API URL, without version
+pub const PROD_BASE_URL: &str = "https://www.airparif.asso.fr/services/api";
+
+/// Default AirParif API version
+const DEFAULT_API_VERSION: &str = "1.1";
+
+/// Default maximum number of cities per `idxville` request
+const DEFAULT_CITY_BATCH_SIZE: usize = 20;
+
+/// Default User-Agent header
+const DEFAULT_USER_AGENT: &str = concat!("r-parif/", env!("CARGO_PKG_VERSION"));
+
+/// Default maximum size of a response body, in bytes
+pub(crate) const DEFAULT_MAX_RESPONSE_BYTES: usize = 4 * 1024 * 1024;
+
+/// Formats accepted for `date` of `indiceJour` endpoint, tried in order
+const DAY_DATE_FORMATS: [&str; 6] = [
+    "%d/%m/%Y",
+    "%Y-%m-%d",
+    "%d/%m/%Y %H:%M",
+    "%d/%m/%Y %H:%M:%S",
+    "%Y-%m-%d %H:%M:%S",
+    "%Y-%m-%dT%H:%M:%S",
+];
+
+/// Default path of `indice` endpoint
+const INDICE_PATH: &str = "indice";
+
+/// Default path of `indiceJour` endpoint
+const INDICE_JOUR_PATH: &str = "indiceJour";
+
+/// Default path of `idxville` endpoint
+const IDXVILLE_PATH: &str = "idxville";
+
+/// Default path of `episode` endpoint
+const EPISODE_PATH: &str = "episode";
+
+/// Hide the value of `key` query parameter of an URL
+///
+/// # Arguments
+///
+/// * `url` - URL that may contain the API key
+pub(crate) fn redact_key(url: &str) -> String {
+    match url.split_once('?') {
+        Some((path, query)) => {
+            let query: Vec<&str> = query
+                .split('&')
+                .map(|p| {
+                    if p.starts_with("key=") {
+                        "key=REDACTED"
+                    } else {
+                        p
+                    }
+                })
+                .collect();
+            format!("{}?{}", path, query.join("&"))
+        }
+        None => url.to_string(),
+    }
+}
+
+/// Return the endpoint name of an URL (e.g. `indice` for `http://host/1.1/indice?key=...`)
+///
+/// # Arguments
+///
+/// * `url` - URL called
+pub(crate) fn endpoint_name(url: &str) -> &str {
+    let path = url.split('?').next().unwrap_or_default();
+    path.rsplit('/').next().unwrap_or_default()
+}
 
-/// Client to call HTTP API
+/// Response body read with a maximum size
 #[derive(Debug)]
-pub struct RParifClient<'a> {
-    /// HTTP client
-    client: Client,
-    /// API key
-    api_key: &'a str,
-    /// Base URL
-    base_url: &'a str,
+pub(crate) struct BodyBuffer {
+    /// Bytes read so far
+    bytes: Vec<u8>,
+    /// Maximum number of bytes
+    limit: usize,
+    /// Whether more than `limit` bytes were received
+    exceeded: bool,
 }
 
-impl RParifClient<'_> {
-    /// Construct a new client
+impl BodyBuffer {
+    /// Construct an empty buffer
     ///
     /// # Arguments
     ///
-    /// * `api_key` - [API key](https://www.airparif.asso.fr/rss/api) to authenticate call
+    /// * `limit` - Maximum number of bytes
     ///
-    pub fn new(api_key: &str) -> RParifClient {
-        RParifClient {
-            client: Client::new(),
-            api_key,
-            base_url: "https://www.airparif.asso.fr/services/api/1.1",
+    /// * `content_length` - `Content-Length` header, if any, to fail before reading
+    pub(crate) fn new(limit: usize, content_length: Option<u64>) -> BodyBuffer {
+        BodyBuffer {
+            bytes: Vec::new(),
+            limit,
+            exceeded: content_length.is_some_and(|length| length > limit as u64),
         }
     }
 
-    /// Constructor used for test with httpmock. It use `http://localhost:5000`
-    /// as URL to call mock instead of real services
+    /// Append a chunk of the body, return `false` if the maximum size is exceeded
     ///
     /// # Arguments
     ///
-    /// * `api_key` - any string
-    /// * `base_url` - base URL
-    ///
-    pub fn new_test<'a>(api_key: &'a str,base_url: &'a str) -> RParifClient<'a> {
-        RParifClient {
-            client: Client::new(),
-            api_key,
-            base_url,
+    /// * `chunk` - Bytes received
+    pub(crate) fn push(&mut self, chunk: &[u8]) -> bool {
+        if self.exceeded || self.bytes.len() + chunk.len() > self.limit {
+            self.exceeded = true;
+        } else {
+            self.bytes.extend_from_slice(chunk);
         }
+        !self.exceeded
+    }
+
+    /// Whether more than the maximum size was received
+    pub(crate) fn exceeded(&self) -> bool {
+        self.exceeded
     }
 
-    /// Convert a value into a date
+    /// Return the body as text, or an error if the maximum size was exceeded
     ///
     /// # Arguments
     ///
-    /// * `value` - `JsonValue::String` containing one of the following `hier`, `jour` or `demain`
+    /// * `url` - URL called
     ///
     /// # Errors
     ///
-    /// * [RParifError::UnexpectedDate](../error/enum.RParifError.html#variant.UnexpectedDate)
-    /// if the value doesn't match `hier`, `jour` or `demain`
-    ///
-    fn convert_json_to_date(&self, value: &JsonValue) -> Result<Date<Utc>, RParifError> {
-        let date = chrono::Utc::today();
+    /// * [RParifError::ResponseTooLarge](../error/enum.RParifError.html#variant.ResponseTooLarge)
+    ///   if the maximum size was exceeded
+    pub(crate) fn into_text(self, url: &str) -> Result<String, RParifError> {
+        if self.exceeded {
+            warn!(target: LOG_TARGET, "Response of {} exceeds {} bytes", redact_key(url), self.limit);
+            return Err(RParifError::ResponseTooLarge {
+                url: redact_key(url),
+                limit: self.limit,
+            });
+        }
+        Ok(String::from_utf8_lossy(&self.bytes).into_owned())
+    }
+}
 
-        if value == "hier" {
-            Ok(date.checked_sub_signed(Duration::days(1)).unwrap())
-        } else if value == "demain" {
-            Ok(date.checked_add_signed(Duration::days(1)).unwrap())
-        } else if value == "jour" {
-            Ok(date)
+#[cfg(feature = "blocking")]
+impl std::io::Write for BodyBuffer {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        if self.push(buf) {
+            Ok(buf.len())
         } else {
-            Err(RParifError::UnexpectedDate(value.dump()))
+            Err(std::io::Error::other("response too large"))
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Return the HTTP status carried by an error, if any
+///
+/// # Arguments
+///
+/// * `err` - Error raised while calling AirParif API
+#[cfg(feature = "blocking")]
+fn error_status(err: &RParifError) -> Option<u16> {
+    match err {
+        RParifError::CallError { status, .. } => Some(*status),
+        RParifError::RateLimited { .. } => Some(429),
+        RParifError::RequestError(err) => err.status().map(|s| s.as_u16()),
+        _ => None,
+    }
+}
+
+/// Convert a value into a date
+///
+/// # Arguments
+///
+/// * `value` - string containing one of the following `hier`, `jour` or `demain`
+///
+/// * `today` - date `jour` refers to
+///
+/// # Errors
+///
+/// * [RParifError::UnexpectedDate](../error/enum.RParifError.html#variant.UnexpectedDate)
+///   if the value doesn't match `hier`, `jour` or `demain`
+pub(crate) fn string_to_date(value: &str, today: NaiveDate) -> Result<NaiveDate, RParifError> {
+    let date = today;
+
+    if value == "hier" {
+        Ok(date.checked_sub_signed(TimeDelta::days(1)).unwrap())
+    } else if value == "demain" {
+        Ok(date.checked_add_signed(TimeDelta::days(1)).unwrap())
+    } else if value == "jour" {
+        Ok(date)
+    } else {
+        Err(RParifError::UnexpectedDate(value.to_string()))
+    }
+}
+
+/// Convert a JSON value into a date
+///
+/// # Arguments
+///
+/// * `value` - `JsonValue::String` containing one of the following `hier`, `jour` or `demain`
+///
+/// * `today` - date `jour` refers to
+///
+/// # Errors
+///
+/// * [RParifError::UnexpectedDate](../error/enum.RParifError.html#variant.UnexpectedDate)
+///   if the value doesn't match `hier`, `jour` or `demain`
+pub(crate) fn json_to_date(value: &JsonValue, today: NaiveDate) -> Result<NaiveDate, RParifError> {
+    match value.as_str() {
+        Some(v) => string_to_date(v, today).map_err(|_| RParifError::UnexpectedDate(value.dump())),
+        None => Err(RParifError::UnexpectedDate(value.dump())),
+    }
+}
+
+/// Parse an absolute date, as sent by `indiceJour` endpoint, trying each of `DAY_DATE_FORMATS` :
+/// `dd/mm/yyyy` or `yyyy-mm-dd`, optionally followed by a time which is ignored
+///
+/// # Arguments
+///
+/// * `value` - Date to parse
+///
+/// # Errors
+///
+/// * [RParifError::DateParseError](../error/enum.RParifError.html#variant.DateParseError) if no
+///   format matches, it contains the attempted formats and the error of the first one
+pub(crate) fn parse_day_date(value: &str) -> Result<NaiveDate, RParifError> {
+    let mut first_error = None;
+    for format in DAY_DATE_FORMATS.iter() {
+        match NaiveDate::parse_from_str(value, format) {
+            Ok(date) => return Ok(date),
+            Err(err) => {
+                first_error.get_or_insert(err);
+            }
+        }
+    }
+    warn!(
+        target: LOG_TARGET,
+        "Can't parse date {:?}, attempted formats : {:?}", value, DAY_DATE_FORMATS
+    );
+    Err(RParifError::DateParseError {
+        value: value.to_string(),
+        formats: DAY_DATE_FORMATS.iter().map(|f| f.to_string()).collect(),
+        error: first_error.expect("At least one format is attempted"),
+    })
+}
+
+/// Extract a number value from a JsonValue object
+///
+/// # Arguments
+///
+/// * `key` - Member name of the JSON value
+///
+/// * `json` - JsonValue::Object
+///
+/// # Errors
+///
+/// * [RParifError::MissingJsonKey](../error/enum.RParifError.html#variant.MissingJsonKey) when `json`
+///   contains no member `key`
+///
+/// * [RParifError::WrongJsonType](../error/enum.RParifError.html#variant.WrongJsonType) when member
+///   `key`is not a number
+pub(crate) fn number_value(key: &str, json: &JsonValue) -> Result<u32, RParifError> {
+    if !json.has_key(key) {
+        Err(RParifError::MissingJsonKey {
+            key: key.to_string(),
+            json: json.dump(),
+        })
+    } else if !json[key].is_number() {
+        Err(RParifError::WrongJsonType {
+            expected: "number".to_string(),
+            json: json[key].dump(),
+            path: Some(key.to_string()),
+        })
+    } else {
+        Ok(json[key].as_u32().unwrap())
+    }
+}
+
+/// Extract a string value from a JsonValue object
+///
+/// # Arguments
+///
+/// * `key` - Member name of the JSON value
+///
+/// * `json` - JsonValue::Object
+///
+/// # Errors
+///
+/// * [RParifError::MissingJsonKey](../error/enum.RParifError.html#variant.MissingJsonKey) when `json`
+///   contains no member `key`
+///
+/// * [RParifError::WrongJsonType](../error/enum.RParifError.html#variant.WrongJsonType) when member
+///   `key`is not a string
+pub(crate) fn string_value<'a>(key: &str, json: &'a JsonValue) -> Result<&'a str, RParifError> {
+    if !json.has_key(key) {
+        Err(RParifError::MissingJsonKey {
+            key: key.to_string(),
+            json: json.dump(),
+        })
+    } else if !json[key].is_string() {
+        Err(RParifError::WrongJsonType {
+            expected: "string".to_string(),
+            json: json[key].dump(),
+            path: Some(key.to_string()),
+        })
+    } else {
+        Ok(json[key].as_str().unwrap())
+    }
+}
+
+/// Extract an optional string value from a JsonValue object. Missing members, `null`
+/// and empty strings give `None`.
+///
+/// # Arguments
+///
+/// * `key` - Member name of the JSON value
+///
+/// * `json` - JsonValue::Object
+///
+/// # Errors
+///
+/// * [RParifError::WrongJsonType](../error/enum.RParifError.html#variant.WrongJsonType) when member
+///   `key` is neither a string nor `null`
+pub(crate) fn optional_string(key: &str, json: &JsonValue) -> Result<Option<String>, RParifError> {
+    Ok(optional_str(key, json)?.map(|v| v.to_string()))
+}
+
+/// Same as [`optional_string`](fn.optional_string.html) without copying the value
+///
+/// # Arguments
+///
+/// * `key` - Member name of the JSON value
+///
+/// * `json` - JsonValue::Object
+///
+/// # Errors
+///
+/// * [RParifError::WrongJsonType](../error/enum.RParifError.html#variant.WrongJsonType) when member
+///   `key` is neither a string nor `null`
+fn optional_str<'a>(key: &str, json: &'a JsonValue) -> Result<Option<&'a str>, RParifError> {
+    match &json[key] {
+        JsonValue::Null => Ok(None),
+        value => match value.as_str() {
+            Some("") => Ok(None),
+            Some(v) => Ok(Some(v)),
+            None => Err(RParifError::WrongJsonType {
+                expected: "string".to_string(),
+                json: value.dump(),
+                path: Some(key.to_string()),
+            }),
+        },
+    }
+}
+
+/// Convert a value sent by AirParif into an enum value, keeping unknown values
+/// if `lenient` is true
+///
+/// # Arguments
+///
+/// * `value` - value to convert
+///
+/// * `lenient` - whether unknown values are kept
+///
+/// * `from_str_lenient` - conversion used when `lenient` is true
+///
+/// # Errors
+///
+/// * [RParifError::UnkownEnumValue](../error/enum.RParifError.html#variant.UnkownEnumValue) if
+///   `lenient` is false and `value` can't be converted
+pub(crate) fn parse_enum<T>(
+    value: &str,
+    lenient: bool,
+    from_str_lenient: fn(&str) -> T,
+) -> Result<T, RParifError>
+where
+    T: FromStr<Err = RParifError>,
+{
+    if lenient {
+        Ok(from_str_lenient(value))
+    } else {
+        value.parse()
+    }
+}
+
+/// Percent-encode a query parameter value, only unreserved characters (letters, digits,
+/// `-`, `.`, `_` and `~`) are kept as is
+///
+/// # Arguments
+///
+/// * `value` - Value of the parameter
+pub(crate) fn encode_query_value(value: &str) -> String {
+    let mut result = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                result.push(byte as char)
+            }
+            _ => result.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    result
+}
+
+/// Return `true` if one of the cities is in Île-de-France, the region covered by AirParif
+///
+/// # Arguments
+///
+/// * `cities` - List of INSEE city code
+///
+/// # Errors
+///
+/// * [RParifError::EmptyCityList](../error/enum.RParifError.html#variant.EmptyCityList) when `cities`
+///   is empty
+///
+/// * [RParifError::InvalidInsee](../error/enum.RParifError.html#variant.InvalidInsee) when one of
+///   `cities` isn't a valid INSEE code
+pub(crate) fn covered_by_airparif(cities: &[&str]) -> Result<bool, RParifError> {
+    if cities.is_empty() {
+        return Err(RParifError::EmptyCityList);
+    }
+    let cities: Vec<Insee> = cities
+        .iter()
+        .map(|c| c.parse())
+        .collect::<Result<_, RParifError>>()?;
+    Ok(cities.iter().any(Insee::is_ile_de_france))
+}
+
+/// Return the blocking HTTP client shared by clients built with
+/// [`shared_client`](./struct.RParifClientBuilder.html#method.shared_client)
+///
+/// # Errors
+///
+/// * [RParifError::RequestError](../error/enum.RParifError.html#variant.RequestError) when reqwest lib
+///   fails to build its HTTP client.
+#[cfg(feature = "blocking")]
+fn shared_client() -> Result<Client, RParifError> {
+    static SHARED: OnceLock<Client> = OnceLock::new();
+    if let Some(client) = SHARED.get() {
+        return Ok(client.clone());
+    }
+    let client = Client::builder().build()?;
+    Ok(SHARED.get_or_init(|| client).clone())
+}
+
+/// Parse the body of an AirParif response, turning non 2XX status into an error
+///
+/// # Arguments
+///
+/// * `url` - URL called
+///
+/// * `status` - HTTP status
+///
+/// * `success` - whether `status` is 2XX
+///
+/// * `retry_after` - value of `Retry-After` header, if any
+///
+/// * `body` - body of the response
+///
+/// # Errors
+///
+/// * [RParifError::RateLimited](../error/enum.RParifError.html#variant.RateLimited) when HTTP status
+///   is 429. It contains the `Retry-After` delay
+///
+/// * [RParifError::CallError](../error/enum.RParifError.html#variant.CallError) when HTTP status is
+///   other than 2XX. It contains the URL called, the HTTP status, the body response,
+///   AirParif error message if the body has an `erreur` or `message` string member and
+///   the `Retry-After` delay for 503 status. The body is kept as is if it isn't JSON
+///
+/// * [RParifError::JsonError](../error/enum.RParifError.html#variant.JsonError) if response isn't a well
+///   formed JSON
+pub(crate) fn read_response(
+    url: &str,
+    status: u16,
+    success: bool,
+    retry_after: Option<&str>,
+    body: &str,
+) -> Result<JsonValue, RParifError> {
+    debug!(target: LOG_TARGET, "Response status : {}", status);
+    if success {
+        return Ok(json::parse(body)?);
+    }
+    let retry_after = match status {
+        429 | 503 => retry_after.and_then(|value| parse_retry_after(value, Utc::now())),
+        _ => None,
+    };
+    if status == 429 {
+        return Err(RParifError::RateLimited { retry_after });
+    }
+
+    let (body, message) = match json::parse(body) {
+        Ok(data) => {
+            let message = ["erreur", "message"]
+                .iter()
+                .find_map(|key| data[*key].as_str())
+                .map(|m| m.to_string());
+            (data.dump(), message)
+        }
+        Err(_) => (body.to_string(), None),
+    };
+    Err(RParifError::CallError {
+        url: redact_key(url),
+        body,
+        status,
+        message,
+        retry_after,
+    })
+}
+
+/// Parse a `Retry-After` header value, either a number of seconds or an HTTP date.
+/// A date in the past gives a zero delay.
+///
+/// # Arguments
+///
+/// * `value` - header value
+///
+/// * `now` - date the delay is computed from when `value` is a date
+fn parse_retry_after(value: &str, now: DateTime<Utc>) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = DateTime::parse_from_rfc2822(value).ok()?;
+    Some(
+        (date.with_timezone(&Utc) - now)
+            .to_std()
+            .unwrap_or_default(),
+    )
+}
+
+/// Parse a response of `indice` endpoint (e.g. recorded or piped JSON) without calling AirParif.
+/// Relative dates are resolved against current date in Paris
+///
+/// # Arguments
+///
+/// * `json` - JSON response
+///
+/// # Errors
+///
+/// * [RParifError::JsonError](../error/enum.RParifError.html#variant.JsonError) if `json` isn't
+///   well formed
+///
+/// * Same conversion errors as [RParifClient::index](./struct.RParifClient.html#method.index)
+pub fn parse_indices(json: &str) -> Result<Vec<Index>, RParifError> {
+    RParifClientBuilder::new("")
+        .config
+        .index_to_index(json::parse(json)?)
+}
+
+/// Call `f` for each index of an `indice` endpoint response with its date, pollutant
+/// (always `global`), index and map URL, without building [Index](../objects/struct.Index.html)
+/// values. Relative dates are resolved against current date in Paris.
+///
+/// # Arguments
+///
+/// * `json` - JSON response
+///
+/// * `f` - Called with date, pollutant, index and map URL of each index
+///
+/// # Errors
+///
+/// * Same conversion errors as [RParifClient::index](./struct.RParifClient.html#method.index)
+pub fn for_each_index<F>(json: &JsonValue, mut f: F) -> Result<(), RParifError>
+where
+    F: FnMut(NaiveDate, &str, u32, Option<&str>),
+{
+    RParifClientBuilder::new("")
+        .config
+        .visit_indices(json, |date, pollutant, index, url, _| {
+            f(date, pollutant, index, url)
+        })
+}
+
+/// Parse a response of `indiceJour` endpoint without calling AirParif
+///
+/// # Arguments
+///
+/// * `json` - JSON response
+///
+/// # Errors
+///
+/// * [RParifError::JsonError](../error/enum.RParifError.html#variant.JsonError) if `json` isn't
+///   well formed
+///
+/// * Same conversion errors as [RParifClient::index_day](./struct.RParifClient.html#method.index_day)
+pub fn parse_index_day(json: &str) -> Result<Vec<Index>, RParifError> {
+    RParifClientBuilder::new("")
+        .config
+        .index_day_to_index(json::parse(json)?, None)
+}
+
+/// Parse a response of `idxville` endpoint without calling AirParif.
+/// Relative dates are resolved against current date in Paris
+///
+/// # Arguments
+///
+/// * `json` - JSON response
+///
+/// # Errors
+///
+/// * [RParifError::JsonError](../error/enum.RParifError.html#variant.JsonError) if `json` isn't
+///   well formed
+///
+/// * Same conversion errors as [RParifClient::index_city](./struct.RParifClient.html#method.index_city)
+pub fn parse_city_indices(json: &str) -> Result<Vec<Index>, RParifError> {
+    RParifClientBuilder::new("")
+        .config
+        .idxville_to_index(json::parse(json)?)
+}
+
+/// Parse a response of `episode` endpoint without calling AirParif.
+/// Relative dates are resolved against current date in Paris
+///
+/// # Arguments
+///
+/// * `json` - JSON response
+///
+/// # Errors
+///
+/// * [RParifError::JsonError](../error/enum.RParifError.html#variant.JsonError) if `json` isn't
+///   well formed
+///
+/// * Same conversion errors as [RParifClient::episode](./struct.RParifClient.html#method.episode)
+pub fn parse_episodes(json: &str) -> Result<Vec<Episode>, RParifError> {
+    RParifClientBuilder::new("")
+        .config
+        .episode_to_episode(json::parse(json)?)
+}
+
+/// Read a file containing a response of `indice`, `indiceJour` or `idxville` endpoint
+/// (e.g. recorded with `curl`) and convert it into indices. The endpoint is guessed from
+/// the content of the file :
+///
+/// * `indiceJour` : an object, e.g. `{"date":"18/05/2020","global":{"indice":35},...}`
+///
+/// * `idxville` : an array of cities, e.g. `[{"ninsee":"75101","jour":{...}},...]`
+///
+/// * `indice` : any other array, e.g. `[{"date":"jour","indice":35},...]`
+///
+/// Relative dates are resolved against current date in Paris
+///
+/// # Arguments
+///
+/// * `path` - Path of the file
+///
+/// # Errors
+///
+/// * [RParifError::IoError](../error/enum.RParifError.html#variant.IoError) if the file can't
+///   be read
+///
+/// * [RParifError::JsonError](../error/enum.RParifError.html#variant.JsonError) if the file isn't
+///   well formed JSON
+///
+/// * Same conversion errors as [parse_indices](./fn.parse_indices.html),
+///   [parse_index_day](./fn.parse_index_day.html) or
+///   [parse_city_indices](./fn.parse_city_indices.html)
+pub fn index_from_file<P: AsRef<Path>>(path: P) -> Result<Vec<Index>, RParifError> {
+    let json = json::parse(&fs::read_to_string(path)?)?;
+    let config = RParifClientBuilder::new("").config;
+    if json.is_object() {
+        config.index_day_to_index(json, None)
+    } else if json[0].has_key("ninsee") {
+        config.idxville_to_index(json)
+    } else {
+        config.index_to_index(json)
+    }
+}
+
+/// Read a file containing a response of `episode` endpoint (e.g.
+/// `[{"date":"jour","detail":"...","o3":{"type":"prevu","niveau":"info","criteres":["km"]}}]`)
+/// and convert it into episodes. Relative dates are resolved against current date in Paris
+///
+/// # Arguments
+///
+/// * `path` - Path of the file
+///
+/// # Errors
+///
+/// * [RParifError::IoError](../error/enum.RParifError.html#variant.IoError) if the file can't
+///   be read
+///
+/// * [RParifError::JsonError](../error/enum.RParifError.html#variant.JsonError) if the file isn't
+///   well formed JSON
+///
+/// * Same conversion errors as [parse_episodes](./fn.parse_episodes.html)
+pub fn episodes_from_file<P: AsRef<Path>>(path: P) -> Result<Vec<Episode>, RParifError> {
+    parse_episodes(&fs::read_to_string(path)?)
+}
+
+/// URL of a request to AirParif API.  
+/// [`as_str`](#method.as_str) returns the actual URL whereas `Debug` and `Display`
+/// hide the API key (`key=REDACTED`)
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct RequestUrl(String);
+
+impl RequestUrl {
+    /// Return the URL, including API key
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for RequestUrl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", redact_key(&self.0))
+    }
+}
+
+impl fmt::Debug for RequestUrl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("RequestUrl")
+            .field(&redact_key(&self.0))
+            .finish()
+    }
+}
+
+/// AirParif API environment, see
+/// [`environment`](./struct.RParifClientBuilder.html#method.environment)
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Default)]
+pub enum Environment {
+    /// AirParif production API ([PROD_BASE_URL](./constant.PROD_BASE_URL.html)) with default API version
+    #[default]
+    Production,
+    /// Any other URL (e.g. a mock server or a gateway), used as is
+    Custom(String),
+}
+
+/// Paths of AirParif endpoints, relative to the base URL (and API version, if any).
+/// Default values are AirParif ones, override them with
+/// [`endpoint_paths`](./struct.RParifClientBuilder.html#method.endpoint_paths)
+/// when calling a gateway that maps paths differently.
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub struct EndpointPaths {
+    /// Path of `indice` endpoint
+    pub indice: String,
+    /// Path of `indiceJour` endpoint
+    pub indice_jour: String,
+    /// Path of `idxville` endpoint
+    pub idxville: String,
+    /// Path of `episode` endpoint
+    pub episode: String,
+}
+
+impl Default for EndpointPaths {
+    fn default() -> Self {
+        EndpointPaths {
+            indice: INDICE_PATH.to_string(),
+            indice_jour: INDICE_JOUR_PATH.to_string(),
+            idxville: IDXVILLE_PATH.to_string(),
+            episode: EPISODE_PATH.to_string(),
         }
     }
+}
+
+/// Measures of a request to AirParif API, given to the hook set with
+/// [`metrics`](./struct.RParifClientBuilder.html#method.metrics)
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct RequestMetrics {
+    /// Endpoint name (`indice`, `indiceJour`, `idxville` or `episode`)
+    endpoint: String,
+    /// Time spent on the request, including JSON parsing
+    duration: Duration,
+    /// HTTP status, if a response was received
+    status: Option<u16>,
+    /// Whether the request succeeded
+    success: bool,
+}
+
+impl RequestMetrics {
+    /// Return the endpoint name (`indice`, `indiceJour`, `idxville` or `episode`)
+    pub fn endpoint(&self) -> &str {
+        &self.endpoint
+    }
+
+    /// Return the time spent on the request, including JSON parsing.
+    /// It's always zero on WASM targets
+    pub fn duration(&self) -> Duration {
+        self.duration
+    }
+
+    /// Return the HTTP status, `None` if no response was received (e.g. connection error)
+    pub fn status(&self) -> Option<u16> {
+        self.status
+    }
+
+    /// Return `true` if the request succeeded, `false` on error (including non 2XX status
+    /// and malformed JSON)
+    pub fn success(&self) -> bool {
+        self.success
+    }
+}
+
+/// Hook called after each request
+#[derive(Clone)]
+pub(crate) struct MetricsHook(Arc<dyn Fn(&RequestMetrics) + Send + Sync>);
+
+impl fmt::Debug for MetricsHook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "MetricsHook")
+    }
+}
 
-    /// Convert a value into a date
+/// Last successful response of an URL along with its validators
+#[derive(Clone, Debug)]
+struct ValidatedResponse {
+    /// `ETag` header, if any
+    etag: Option<String>,
+    /// `Last-Modified` header, if any
+    last_modified: Option<String>,
+    /// HTTP body
+    body: String,
+}
+
+/// Last responses per URL used to send conditional requests (`If-None-Match` and
+/// `If-Modified-Since` headers), see
+/// [`conditional_requests`](./struct.RParifClientBuilder.html#method.conditional_requests)
+#[derive(Debug, Default)]
+pub(crate) struct ConditionalCache(Mutex<HashMap<String, ValidatedResponse>>);
+
+impl ConditionalCache {
+    /// Return conditional headers to send for an URL, none if no validator is known
     ///
     /// # Arguments
     ///
-    /// * `value` - string containing one of the following `hier`, `jour` or `demain`
-    ///
-    /// # Errors
-    ///
-    /// * [RParifError::UnexpectedDate](../error/enum.RParifError.html#variant.UnexpectedDate)
-    /// if the value doesn't match `hier`, `jour` or `demain`
-    ///
-    fn convert_string_to_date(&self, value: &str) -> Result<Date<Utc>, RParifError> {
-        let date = chrono::Utc::today();
-
-        if value == "hier" {
-            Ok(date.checked_sub_signed(Duration::days(1)).unwrap())
-        } else if value == "demain" {
-            Ok(date.checked_add_signed(Duration::days(1)).unwrap())
-        } else if value == "jour" {
-            Ok(date)
-        } else {
-            Err(RParifError::UnexpectedDate(value.to_string()))
+    /// * `url` - URL to call
+    pub(crate) fn request_headers(&self, url: &str) -> Vec<(HeaderName, String)> {
+        let cache = self.0.lock().unwrap();
+        let mut headers = Vec::new();
+        if let Some(response) = cache.get(url) {
+            if let Some(etag) = &response.etag {
+                headers.push((IF_NONE_MATCH, etag.clone()));
+            }
+            if let Some(last_modified) = &response.last_modified {
+                headers.push((IF_MODIFIED_SINCE, last_modified.clone()));
+            }
         }
+        headers
     }
 
-    /// Extract a number value from a JsonValue object
+    /// Return the body of the last successful response of an URL, if any
     ///
     /// # Arguments
     ///
-    /// * `key` - Member name of the JSON value
+    /// * `url` - URL called
+    pub(crate) fn body(&self, url: &str) -> Option<String> {
+        self.0
+            .lock()
+            .unwrap()
+            .get(url)
+            .map(|response| response.body.clone())
+    }
+
+    /// Keep a successful response if it has validators (`ETag` or `Last-Modified` headers)
     ///
-    /// * `json` - JsonValue::Object
+    /// # Arguments
     ///
-    /// # Errors
+    /// * `url` - URL called
     ///
-    /// * [RParifError::MissingJsonKey](../error/enum.RParifError.html#variant.MissingJsonKey) when `json`
-    /// contains no member `key`
+    /// * `headers` - Response headers
     ///
-    /// * [RParifError::WrongJsonType](../error/enum.RParifError.html#variant.WrongJsonType) when member
-    /// `key`is not a number
-    fn get_number_value(&self, key: &str, json: &JsonValue) -> Result<u32, RParifError> {
-        if !json.has_key(key) {
-            Err(RParifError::MissingJsonKey {
-                key: key.to_string(),
-                json: json.dump(),
-            })
-        } else if !json[key].is_number() {
-            Err(RParifError::WrongJsonType {
-                expected: "number".to_string(),
-                json: json[key].dump(),
-            })
-        } else {
-            Ok(json[key].as_u32().unwrap())
+    /// * `body` - Response body
+    pub(crate) fn store(&self, url: &str, headers: &HeaderMap, body: &str) {
+        let header = |name: HeaderName| {
+            headers
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(|value| value.to_string())
+        };
+        let etag = header(ETAG);
+        let last_modified = header(LAST_MODIFIED);
+        if etag.is_some() || last_modified.is_some() {
+            self.0.lock().unwrap().insert(
+                url.to_string(),
+                ValidatedResponse {
+                    etag,
+                    last_modified,
+                    body: body.to_string(),
+                },
+            );
         }
     }
+}
+
+/// Settings shared by [RParifClient](./struct.RParifClient.html) and its asynchronous
+/// counterpart : URL building and JSON conversion
+#[derive(Clone, Debug)]
+pub(crate) struct Config<'a> {
+    /// API key, borrowed or owned
+    api_key: Cow<'a, str>,
+    /// Base URL
+    base_url: String,
+    /// API version appended to base URL, if any
+    api_version: Option<String>,
+    /// Send API key to endpoints that don't require it (`indice` and `indiceJour`)
+    send_key_when_optional: bool,
+    /// Keep unknown enum values instead of raising an error
+    lenient: bool,
+    /// User-Agent header sent with each request
+    pub(crate) user_agent: String,
+    /// Maximum number of cities per `idxville` request
+    pub(crate) city_batch_size: usize,
+    /// Maximum number of concurrent `idxville` requests of the asynchronous client
+    pub(crate) max_concurrency: usize,
+    /// Date `jour` refers to, current date in Paris if `None`
+    reference_date: Option<NaiveDate>,
+    /// Hook called after each request, if any
+    metrics: Option<MetricsHook>,
+    /// Pollutants kept when converting `indiceJour` and `idxville` responses, all if empty
+    only_pollutants: Vec<String>,
+    /// Source of current time
+    pub(crate) clock: Arc<dyn Clock>,
+    /// Paths of endpoints
+    paths: EndpointPaths,
+    /// Maximum size of a response body, in bytes
+    pub(crate) max_response_bytes: usize,
+    /// Query parameters added to every request
+    extra_params: Vec<(String, String)>,
+}
+
+/// Client to call HTTP API.
+///
+/// Each client owns a connection pool : create one client and reuse it for all calls
+/// (it can be shared between threads) instead of creating a client per request, or use
+/// [`shared_client`](./struct.RParifClientBuilder.html#method.shared_client).
+#[derive(Debug)]
+pub struct RParifClient<'a> {
+    /// HTTP transport
+    #[cfg(feature = "blocking")]
+    transport: Box<dyn Transport>,
+    /// URL and conversion settings
+    config: Config<'a>,
+}
+
+/// Builder to configure a [RParifClient](./struct.RParifClient.html)
+#[derive(Clone, Debug)]
+pub struct RParifClientBuilder<'a> {
+    /// Settings of the client to build
+    pub(crate) config: Config<'a>,
+    /// How long idle connections are kept, reqwest's default if `None`
+    pool_idle_timeout: Option<Duration>,
+    /// Maximum number of idle connections per host, reqwest's default if `None`
+    pool_max_idle_per_host: Option<usize>,
+    /// Maximum duration to establish a connection, reqwest's default if `None`
+    connect_timeout: Option<Duration>,
+    /// Maximum duration of a whole request, reqwest's default if `None`
+    timeout: Option<Duration>,
+    /// Use the HTTP client shared by all clients built with this option
+    shared_client: bool,
+    /// Use proxies from `HTTP_PROXY`, `HTTPS_PROXY`, ... environment variables
+    use_env_proxy: bool,
+    /// Send conditional requests and reuse previous response on 304 status
+    conditional_requests: bool,
+}
 
-    /// Extract a string value from a JsonValue object
+impl<'a> RParifClientBuilder<'a> {
+    /// Construct a new builder with default values
     ///
     /// # Arguments
     ///
-    /// * `key` - Member name of the JSON value
+    /// * `api_key` - [API key](https://www.airparif.asso.fr/rss/api) to authenticate call
+    pub fn new(api_key: &'a str) -> RParifClientBuilder<'a> {
+        RParifClientBuilder::with_key(Cow::Borrowed(api_key))
+    }
+
+    /// Construct a new builder owning the API key, so that built clients are `'static` and can
+    /// be shared between threads (e.g. in an `Arc`) without lifetime constraints
     ///
-    /// * `json` - JsonValue::Object
+    /// # Arguments
     ///
-    /// # Errors
+    /// * `api_key` - [API key](https://www.airparif.asso.fr/rss/api) to authenticate call
+    pub fn new_owned(api_key: String) -> RParifClientBuilder<'static> {
+        RParifClientBuilder::with_key(Cow::Owned(api_key))
+    }
+
+    /// Construct a new builder with default values
     ///
-    /// * [RParifError::MissingJsonKey](../error/enum.RParifError.html#variant.MissingJsonKey) when `json`
-    /// contains no member `key`
+    /// # Arguments
     ///
-    /// * [RParifError::WrongJsonType](../error/enum.RParifError.html#variant.WrongJsonType) when member
-    /// `key`is not a string
-    fn get_string_value<'a>(&self, key: &str, json: &'a JsonValue) -> Result<&'a str, RParifError> {
-        if !json.has_key(key) {
-            Err(RParifError::MissingJsonKey {
-                key: key.to_string(),
-                json: json.dump(),
-            })
-        } else if !json[key].is_string() {
-            Err(RParifError::WrongJsonType {
-                expected: "string".to_string(),
-                json: json[key].dump(),
-            })
-        } else {
-            Ok(json[key].as_str().unwrap())
+    /// * `api_key` - API key, borrowed or owned
+    fn with_key(api_key: Cow<'a, str>) -> RParifClientBuilder<'a> {
+        RParifClientBuilder {
+            config: Config {
+                api_key,
+                base_url: PROD_BASE_URL.to_string(),
+                api_version: Some(DEFAULT_API_VERSION.to_string()),
+                send_key_when_optional: true,
+                lenient: false,
+                user_agent: DEFAULT_USER_AGENT.to_string(),
+                city_batch_size: DEFAULT_CITY_BATCH_SIZE,
+                max_concurrency: 1,
+                reference_date: None,
+                metrics: None,
+                only_pollutants: Vec::new(),
+                clock: Arc::new(SystemClock),
+                paths: EndpointPaths::default(),
+                max_response_bytes: DEFAULT_MAX_RESPONSE_BYTES,
+                extra_params: Vec::new(),
+            },
+            pool_idle_timeout: None,
+            pool_max_idle_per_host: None,
+            connect_timeout: None,
+            timeout: None,
+            shared_client: false,
+            use_env_proxy: true,
+            conditional_requests: false,
         }
     }
 
-    /// Execute a query to HTTP AirParif endpoint and return the body
-    /// content as a string.
+    /// Set the base URL of AirParif API. The URL is used as is, including its version if any
+    /// (e.g. `https://www.airparif.asso.fr/services/api/1.1`), unless
+    /// [`api_version`](#method.api_version) is called afterward.
     ///
     /// # Arguments
     ///
-    /// * `url` - URL to call
-    ///
-    /// # Errors
-    ///
-    /// * [RParifError::RequestError](../error/enum.RParifError.html#variant.RequestError) when reqwest lib
-    /// fails. It contains the underlying error.
+    /// * `base_url` - base URL
+    pub fn base_url(mut self, base_url: &str) -> RParifClientBuilder<'a> {
+        self.config.base_url = base_url.to_string();
+        self.config.api_version = None;
+        self
+    }
+
+    /// Select the API to call. [Environment::Production](./enum.Environment.html#variant.Production)
+    /// (default) restores AirParif URL and default API version, whereas
+    /// [Environment::Custom](./enum.Environment.html#variant.Custom) behaves like
+    /// [`base_url`](#method.base_url)
     ///
-    /// * [RParifError::CallError](../error/enum.RParifError.html#variant.CallError) when HTTP status is
-    /// other than 2XX. It contains the URL called, the HTTP status and the body response
+    /// # Arguments
     ///
-    /// * [RParifError::JsonError](../error/enum.RParifError.html#variant.JsonError) if response isn't a well
-    /// formed JSON
-    fn execute_query(&self, url: &str) -> Result<JsonValue, RParifError> {
-        let response: Response = self.client.get(url).send()?;
-        let status: u16 = response.status().as_u16();
-        let ok: bool = response.status().is_success();
-        let data: JsonValue = json::parse(response.text()?.as_str())?;
-
-        if ok {
-            Ok(data)
-        } else {
-            Err(RParifError::CallError {
-                url: url.to_string(),
-                body: data.dump(),
-                status,
-            })
+    /// * `environment` - API to call
+    pub fn environment(mut self, environment: Environment) -> RParifClientBuilder<'a> {
+        match environment {
+            Environment::Production => {
+                self.config.base_url = PROD_BASE_URL.to_string();
+                self.config.api_version = Some(DEFAULT_API_VERSION.to_string());
+            }
+            Environment::Custom(base_url) => {
+                self.config.base_url = base_url;
+                self.config.api_version = None;
+            }
         }
+        self
     }
 
-    /// This method converts indice's JSON response into a list of
-    /// [`Index`](../objects/struct.Index.html)
+    /// Set the version of AirParif API (default `1.1`). Endpoints are called on
+    /// `<base URL>/<version>/<endpoint>`
     ///
     /// # Arguments
     ///
-    /// * `json` - HTTP body as JsonValue
+    /// * `api_version` - API version, e.g. `1.1`
+    pub fn api_version(mut self, api_version: &str) -> RParifClientBuilder<'a> {
+        self.config.api_version = Some(api_version.to_string());
+        self
+    }
+
+    /// Choose whether API key is sent to endpoints that don't require it, i.e. `indice`
+    /// and `indiceJour`. Default is `true`, `idxville` and `episode` always get the key
     ///
-    /// # Errors
+    /// # Arguments
     ///
-    /// * [RParifError::UnexpectedDate](../error/enum.RParifError.html#variant.UnexpectedDate)
-    /// if the date can't be parsed (see [`convert_json_to_date`](#method.convert_json_to_date))
+    /// * `send_key_when_optional` - `false` to omit `key` parameter on `indice` and `indiceJour`
+    pub fn send_key_when_optional(
+        mut self,
+        send_key_when_optional: bool,
+    ) -> RParifClientBuilder<'a> {
+        self.config.send_key_when_optional = send_key_when_optional;
+        self
+    }
+
+    /// Choose how values of [Level](../objects/enum.Level.html), [Type](../objects/enum.Type.html) and
+    /// [Criteria](../objects/enum.Criteria.html) unknown to this library are handled.
+    /// When `true` they are kept in the `Unknown` variant, otherwise (default) they
+    /// raise [RParifError::UnkownEnumValue](../error/enum.RParifError.html#variant.UnkownEnumValue)
     ///
-    /// * [RParifError::MissingJsonKey](../error/enum.RParifError.html#variant.MissingJsonKey) if missing
-    /// JSON is missing `indice`
+    /// # Arguments
     ///
-    /// * [RParifError::WrongJsonType](../error/enum.RParifError.html#variant.WrongJsonType) if `indice`
-    /// is not a number or if JSON is not as expected
-    fn index_to_index(&self, json: JsonValue) -> Result<Vec<Index>, RParifError> {
-        debug!("Indice json : {}", json);
-        let mut result: Vec<Index> = Vec::new();
-        match &json {
-            JsonValue::Array(data) => {
-                for value in data {
-                    debug!("Converting : {}", value);
-
-                    // Getting date, raising error
-                    let date = self.convert_json_to_date(&value["date"])?.naive_utc();
-
-                    // Getting url
-                    let url = if value.has_key("url_carte") {
-                        Option::from(value["url_carte"].to_string())
-                    } else {
-                        None
-                    };
-
-                    let index = self.get_number_value("indice", &value)?;
-
-                    result.push(Index::new(
-                        date,
-                        url,
-                        vec!["global".to_string()],
-                        index,
-                        None,
-                    ))
-                }
-                debug!("Result : {:?}", result);
-                Ok(result)
-            }
-            _ => Err(RParifError::WrongJsonType {
-                expected: "array".to_string(),
-                json: json.dump(),
-            }),
-        }
+    /// * `lenient` - `true` to keep unknown values
+    pub fn lenient(mut self, lenient: bool) -> RParifClientBuilder<'a> {
+        self.config.lenient = lenient;
+        self
     }
 
-    /// This method converts indiceJour's JSON response into a list of  [`Index`](../objects/struct.Index.html)
+    /// Override paths of endpoints, relative to the base URL (and API version, if any), e.g.
+    /// to call a gateway that rewrites paths. Default is AirParif paths (`indice`,
+    /// `indiceJour`, `idxville` and `episode`). Endpoint names given to
+    /// [MemoryTransport](../transport/struct.MemoryTransport.html),
+    /// [FixtureTransport](../transport/struct.FixtureTransport.html) and
+    /// [metrics](#method.metrics) hook are then the last segment of these paths.
     ///
     /// # Arguments
     ///
-    /// * `json` - HTTP body as JsonValue
+    /// * `paths` - Paths of endpoints
+    pub fn endpoint_paths(mut self, paths: EndpointPaths) -> RParifClientBuilder<'a> {
+        self.config.paths = paths;
+        self
+    }
+
+    /// Set the maximum size of a response body, in bytes. Reading stops as soon as it's
+    /// exceeded and [RParifError::ResponseTooLarge](../error/enum.RParifError.html#variant.ResponseTooLarge)
+    /// is raised, so that a broken endpoint can't exhaust memory. Default is 4 MiB,
+    /// which is far above AirParif responses.
     ///
-    /// # Errors
+    /// # Arguments
     ///
-    /// * [RParifError::MissingJsonKey](../error/enum.RParifError.html#variant.MissingJsonKey) if missing
-    /// JSON is missing `indice` or `date`
+    /// * `max` - maximum size in bytes
+    pub fn max_response_bytes(mut self, max: usize) -> RParifClientBuilder<'a> {
+        self.config.max_response_bytes = max;
+        self
+    }
+
+    /// Set the User-Agent header sent with each request. Default is `r-parif/<version>`
     ///
-    /// * [RParifError::WrongJsonType](../error/enum.RParifError.html#variant.WrongJsonType) if `indice`
-    /// is not a number or if `date` isn't a string
+    /// # Arguments
     ///
-    /// * [RParifError::DateParseError](../error/enum.RParifError.html#variant.DateParseError) if `date`
-    /// is not in `dd/mm/yyyy` format
-    fn index_day_to_index(&self, json: JsonValue) -> Result<Vec<Index>, RParifError> {
-        debug!("Indice day json : {}", json);
-        let mut result: Vec<Index> = Vec::new();
-
-        // Getting date from json
-        let date = self.get_string_value("date", &json)?;
-        let date: NaiveDate = NaiveDate::parse_from_str(date, "%d/%m/%Y")?;
-        debug!("Date : {}", date);
-
-        for (key, value) in json.entries() {
-            if key != "date" {
-                debug!("Converting : {}", value);
-                let index = self.get_number_value("indice", value)?;
-                let url = self
-                    .get_string_value("url_carte", value)
-                    .ok()
-                    .map(|v| v.to_string());
-                result.push(Index::new(date, url, vec![key.to_string()], index, None));
-            }
-        }
-
-        debug!("Result : {:?}", result);
-        return Ok(result);
+    /// * `user_agent` - value of User-Agent header
+    pub fn user_agent(mut self, user_agent: &str) -> RParifClientBuilder<'a> {
+        self.config.user_agent = user_agent.to_string();
+        self
     }
 
-    /// This method converts idxville's JSON response into a list of  [`Index`](../objects/struct.Index.html)
+    /// Only keep indices of the given pollutants (compared case-insensitively) in
+    /// `indiceJour` and `idxville` results, so that filters don't have to be repeated
+    /// at each call. `global` has to be listed to keep the global index of `indiceJour`.
+    /// An `idxville` index is kept if one of its pollutants is listed. Default is an empty
+    /// list, which keeps all pollutants.
     ///
     /// # Arguments
     ///
-    /// * `json` - HTTP body as JsonValue
-    ///
-    /// # Errors
+    /// * `pollutants` - Pollutants to keep, e.g. `&["pm10", "o3"]`
+    pub fn only_pollutants(mut self, pollutants: &[&str]) -> RParifClientBuilder<'a> {
+        self.config.only_pollutants = pollutants.iter().map(|p| p.to_string()).collect();
+        self
+    }
+
+    /// Add a query parameter to every request, e.g. to try a parameter of AirParif API that
+    /// this library doesn't support yet. Parameters are passed through verbatim : they're
+    /// neither validated nor interpreted, only percent-encoded. They're added in call order,
+    /// after the parameters of the endpoint and before `key`. Can be called several times.
     ///
-    /// * [RParifError::UnexpectedDate](../error/enum.RParifError.html#variant.UnexpectedDate)
-    /// if the date can't be parsed (see [`convert_string_to_date`](#method.convert_string_to_date))
+    /// # Arguments
     ///
-    /// * [RParifError::MissingJsonKey](../error/enum.RParifError.html#variant.MissingJsonKey) if missing
-    /// JSON is missing `ninsee` or `indice`
+    /// * `key` - Name of the parameter
     ///
-    /// * [RParifError::WrongJsonType](../error/enum.RParifError.html#variant.WrongJsonType) if `ninsee`
-    /// isn't a string or `indice` is not a number or if JSON is not as expected
-    fn idxville_to_index(&self, json: JsonValue) -> Result<Vec<Index>, RParifError> {
-        debug!("Idxville json : {}", json);
-        let mut result: Vec<Index> = Vec::new();
-
-        match json {
-            JsonValue::Array(data) => {
-                for i in data {
-                    debug!("Converting : {}", i);
-                    let insee: &str = self.get_string_value("ninsee", &i)?;
-                    debug!("City code : {}", insee);
-                    for (key, value) in i.entries() {
-                        if key != "ninsee" {
-                            debug!("Key : {}", key);
-                            debug!("Converting : {}", value);
-                            let date: NaiveDate = self.convert_string_to_date(key)?.naive_utc();
-                            let index = self.get_number_value("indice", value)?;
-                            let pollutants: Vec<String> = match &value["polluants"] {
-                                JsonValue::Array(p) => p
-                                    .into_iter()
-                                    .map(|v| v.as_str().unwrap().to_string())
-                                    .collect(),
-                                _ => Vec::new(),
-                            };
-                            result.push(Index::new(
-                                date,
-                                None,
-                                pollutants,
-                                index,
-                                Some(insee.to_string()),
-                            ));
-                        }
-                    }
-                }
-
-                debug!("Result : {:?}", result);
-                Ok(result)
-            }
-            _ => Err(RParifError::WrongJsonType {
-                expected: "array".to_string(),
-                json: json.dump(),
-            }),
-        }
+    /// * `value` - Value of the parameter
+    pub fn extra_query_param(mut self, key: &str, value: &str) -> RParifClientBuilder<'a> {
+        self.config
+            .extra_params
+            .push((key.to_string(), value.to_string()));
+        self
     }
 
-    /// This method converts episode's JSON response into a list of  [`Episode`](../objects/struct.Episode.html)
+    /// Set the maximum number of cities per `idxville` request. When more cities are
+    /// queried, [`index_city`](./struct.RParifClient.html#method.index_city) makes one request
+    /// per batch. Default is 20, 0 is treated as 1.
     ///
     /// # Arguments
     ///
-    /// * `json` - HTTP body as JsonValue
-    ///
-    /// # Errors
+    /// * `city_batch_size` - maximum number of cities per request
+    pub fn city_batch_size(mut self, city_batch_size: usize) -> RParifClientBuilder<'a> {
+        self.config.city_batch_size = city_batch_size.max(1);
+        self
+    }
+
+    /// Set the maximum number of `idxville` requests the
+    /// [asynchronous client](../async_client/struct.AsyncRParifClient.html#method.index_city)
+    /// sends concurrently when cities are split into several batches. Results keep the order
+    /// of cities. Default is 1 (one batch after the other), 0 is treated as 1.
+    /// The blocking client always sends requests one after the other.
     ///
-    /// * [RParifError::UnexpectedDate](../error/enum.RParifError.html#variant.UnexpectedDate)
-    /// if the date can't be parsed (see [`convert_json_to_date`](#method.convert_json_to_date))
+    /// # Arguments
     ///
-    /// * [RParifError::MissingJsonKey](../error/enum.RParifError.html#variant.MissingJsonKey) if missing
-    /// JSON is missing `type` or `niveau`
+    /// * `max_concurrency` - maximum number of concurrent requests
+    pub fn max_concurrency(mut self, max_concurrency: usize) -> RParifClientBuilder<'a> {
+        self.config.max_concurrency = max_concurrency.max(1);
+        self
+    }
+
+    /// Set the date that AirParif's relative dates (`hier`, `jour` and `demain`) are resolved
+    /// against. Default is the current date in Paris, a fixed date allows to process a recorded
+    /// response with the right anchor date or to get reproducible results.
     ///
-    /// * [RParifError::UnkownEnumValue](../error/enum.RParifError.html#variant.UnkownEnumValue) if  `type`
-    /// or `niveau` can't be converted into corresponding enum variant
+    /// # Arguments
     ///
-    /// * [RParifError::WrongJsonType](../error/enum.RParifError.html#variant.WrongJsonType) if `type`
-    ///  or `niveau` is not a string or if JSON is not as expected
-    fn episode_to_episode(&self, json: JsonValue) -> Result<Vec<Episode>, RParifError> {
-        let mut result: Vec<Episode> = Vec::new();
+    /// * `date` - date `jour` refers to
+    pub fn with_reference_date(mut self, date: NaiveDate) -> RParifClientBuilder<'a> {
+        self.config.reference_date = Some(date);
+        self
+    }
 
-        match &json {
-            JsonValue::Array(data) => {
-                for j in data {
-                    let date = self.convert_json_to_date(&j["date"])?.naive_utc();
-                    let detail: Option<String> = j["detail"].as_str().and_then(|v| {
-                        if v.is_empty() {
-                            None
-                        } else {
-                            Some(v.to_string())
-                        }
-                    });
-                    let mut episode = Episode::new(date, detail);
-                    for (key, value) in j.entries() {
-                        if key != "date" && key != "detail" {
-                            let pollutant = key.to_string();
-                            let kind = self.get_string_value("type", value)?.parse()?;
-                            let level = self.get_string_value("niveau", value)?.parse()?;
-                            let criteria: Vec<Criteria> = match &value["criteres"] {
-                                JsonValue::Array(v) => v
-                                    .into_iter()
-                                    .map(|v| v.as_str().unwrap().parse().unwrap()) // TODO handle errors ?
-                                    .collect(),
-                                _ => Vec::new(),
-                            };
-                            episode.add(pollutant, kind, level, criteria);
-                        }
-                    }
-                    result.push(episode);
-                }
+    /// Set a hook called after each request with its endpoint, duration, HTTP status and
+    /// outcome (see [RequestMetrics](./struct.RequestMetrics.html)), e.g. to feed a metrics
+    /// backend. The API key isn't given to the hook.
+    ///
+    /// # Arguments
+    ///
+    /// * `hook` - Function called after each request
+    pub fn metrics<F>(mut self, hook: F) -> RParifClientBuilder<'a>
+    where
+        F: Fn(&RequestMetrics) + Send + Sync + 'static,
+    {
+        self.config.metrics = Some(MetricsHook(Arc::new(hook)));
+        self
+    }
 
-                debug!("Result : {:?}", result);
-                Ok(result)
-            }
-            _ => Err(RParifError::WrongJsonType {
-                expected: "array".to_string(),
-                json: json.dump(),
-            }),
-        }
+    /// Set the source of current time, used to resolve `jour` (unless
+    /// [`with_reference_date`](#method.with_reference_date) is set) and to expire
+    /// [cached](../cache/index.html) results. Default is [SystemClock](../clock/struct.SystemClock.html).
+    ///
+    /// # Arguments
+    ///
+    /// * `clock` - Source of current time, e.g. a fake clock in tests
+    pub fn clock<C>(mut self, clock: C) -> RParifClientBuilder<'a>
+    where
+        C: Clock + 'static,
+    {
+        self.config.clock = Arc::new(clock);
+        self
     }
 
-    /// Access global pollution index for previous day, current day and next day through `indice` endpoint
+    /// Set how long idle connections of the pool are kept alive. Default is reqwest's one (90 seconds)
     ///
-    /// # Errors
+    /// # Arguments
     ///
-    /// * [RParifError::RequestError](../error/enum.RParifError.html#variant.RequestError) when reqwest lib
-    /// fails. It contains the underlying error.
+    /// * `timeout` - idle timeout
+    pub fn pool_idle_timeout(mut self, timeout: Duration) -> RParifClientBuilder<'a> {
+        self.pool_idle_timeout = Some(timeout);
+        self
+    }
+
+    /// Set the maximum number of idle connections kept per host. Default is reqwest's one (no limit),
+    /// 0 disables keep-alive
     ///
-    /// * [RParifError::CallError](../error/enum.RParifError.html#variant.CallError) when HTTP status is
-    /// other than 2XX. It contains the URL called, the HTTP status and the body response
+    /// # Arguments
     ///
-    /// * [RParifError::UnexpectedDate](../error/enum.RParifError.html#variant.UnexpectedDate)
-    /// if the date can't be parsed (see [`convert_json_to_date`](#method.convert_json_to_date))
+    /// * `max` - maximum number of idle connections
+    pub fn pool_max_idle_per_host(mut self, max: usize) -> RParifClientBuilder<'a> {
+        self.pool_max_idle_per_host = Some(max);
+        self
+    }
+
+    /// Set the maximum duration to establish a connection (DNS resolution, TCP and TLS
+    /// handshakes), so that an unreachable host fails fast. Default is reqwest's one (no timeout).
+    /// Expiry is reported as
+    /// [RParifError::RequestError](../error/enum.RParifError.html#variant.RequestError).
+    /// This has no effect on WASM targets.
     ///
-    /// * [RParifError::MissingJsonKey](../error/enum.RParifError.html#variant.MissingJsonKey) if missing
-    /// JSON is missing `ìndice`
+    /// # Arguments
     ///
-    /// * [RParifError::WrongJsonType](../error/enum.RParifError.html#variant.WrongJsonType) if `ìndice`
-    /// is not a number or if JSON is not as expected
+    /// * `timeout` - connection timeout
+    pub fn connect_timeout(mut self, timeout: Duration) -> RParifClientBuilder<'a> {
+        self.connect_timeout = Some(timeout);
+        self
+    }
+
+    /// Set the maximum duration of a whole request, from connection to the end of the body.
+    /// Default is reqwest's one (30 seconds for blocking client, no timeout for asynchronous client).
+    /// Expiry is reported as
+    /// [RParifError::RequestError](../error/enum.RParifError.html#variant.RequestError).
+    /// This has no effect on WASM targets.
     ///
-    /// * [RParifError::JsonError](../error/enum.RParifError.html#variant.JsonError) if response isn't a well
-    /// formed JSON
-    pub fn index(&self) -> Result<Vec<Index>, RParifError> {
-        debug!("Querying indice endpoint");
-        // api key is not really needed here...
-        let response: JsonValue =
-            self.execute_query(format!("{}/indice?key={}", self.base_url, self.api_key).as_str())?;
-        self.index_to_index(response)
+    /// # Arguments
+    ///
+    /// * `timeout` - request timeout
+    pub fn timeout(mut self, timeout: Duration) -> RParifClientBuilder<'a> {
+        self.timeout = Some(timeout);
+        self
     }
 
-    /// Retrieve index pollution (global and per pollutant) for a given date (previous day, current or next day) using
-    /// `indiceJour` endpoint
+    /// Use a blocking HTTP client shared by every client built with this option, so that
+    /// they all use the same connection pool, even when clients are created per request.
+    /// The shared HTTP client is created with default settings on first use, so
+    /// [`pool_idle_timeout`](#method.pool_idle_timeout) and
+    /// [`pool_max_idle_per_host`](#method.pool_max_idle_per_host),
+    /// [`connect_timeout`](#method.connect_timeout), [`timeout`](#method.timeout) and
+    /// [`use_env_proxy`](#method.use_env_proxy) are ignored.
     ///
     /// # Arguments
     ///
-    /// * `day` - Which day to get indices pollution for
+    /// * `shared` - `true` to use the shared HTTP client
+    pub fn shared_client(mut self, shared: bool) -> RParifClientBuilder<'a> {
+        self.shared_client = shared;
+        self
+    }
+
+    /// Choose whether proxies configured through `HTTP_PROXY`, `HTTPS_PROXY`, `ALL_PROXY`
+    /// and `NO_PROXY` environment variables (upper or lower case) are used, like reqwest does
+    /// by default. Default is `true`. This has no effect on WASM targets where the browser
+    /// handles proxies.
     ///
-    /// # Errors
+    /// # Arguments
     ///
-    /// * [RParifError::RequestError](../error/enum.RParifError.html#variant.RequestError) when reqwest lib
-    /// fails. It contains the underlying error.
+    /// * `use_env_proxy` - `false` to ignore proxy environment variables
+    pub fn use_env_proxy(mut self, use_env_proxy: bool) -> RParifClientBuilder<'a> {
+        self.use_env_proxy = use_env_proxy;
+        self
+    }
+
+    /// Choose whether conditional requests are sent. When enabled, the last successful response
+    /// of each URL is kept in memory along with its `ETag` and `Last-Modified` headers, which are
+    /// sent back as `If-None-Match` and `If-Modified-Since`. On `304 Not Modified` status,
+    /// the kept response is converted again, as if AirParif had sent it with a 200 status.
+    /// Default is `false`.
     ///
-    /// * [RParifError::CallError](../error/enum.RParifError.html#variant.CallError) when HTTP status is
-    /// other than 2XX. It contains the URL called, the HTTP status and the body response
+    /// This only saves bandwidth if AirParif sets `ETag` or `Last-Modified` headers,
+    /// otherwise nothing is kept and requests are unchanged. It's ignored by
+    /// [`build_with_transport`](#method.build_with_transport).
     ///
-    /// * [RParifError::MissingJsonKey](../error/enum.RParifError.html#variant.MissingJsonKey) if missing
-    /// JSON is missing `ìndice` or `date`
+    /// # Arguments
     ///
-    /// * [RParifError::WrongJsonType](../error/enum.RParifError.html#variant.WrongJsonType) if `ìndice`
-    /// is not a number or if `date` isn't a string
+    /// * `enabled` - `true` to send conditional requests
+    pub fn conditional_requests(mut self, enabled: bool) -> RParifClientBuilder<'a> {
+        self.conditional_requests = enabled;
+        self
+    }
+
+    /// Create the blocking HTTP client according to the pool, timeout and proxy settings
+    #[cfg(feature = "blocking")]
+    fn http_client(&self) -> Result<Client, RParifError> {
+        if self.shared_client {
+            return shared_client();
+        }
+        let mut builder = Client::builder();
+        if let Some(timeout) = self.pool_idle_timeout {
+            builder = builder.pool_idle_timeout(timeout);
+        }
+        if let Some(max) = self.pool_max_idle_per_host {
+            builder = builder.pool_max_idle_per_host(max);
+        }
+        if let Some(timeout) = self.connect_timeout {
+            builder = builder.connect_timeout(timeout);
+        }
+        if let Some(timeout) = self.timeout {
+            builder = builder.timeout(timeout);
+        }
+        if !self.use_env_proxy {
+            builder = builder.no_proxy();
+        }
+        Ok(builder.build()?)
+    }
+
+    /// Build the client
     ///
-    /// * [RParifError::DateParseError](../error/enum.RParifError.html#variant.DateParseError) if `date`
-    /// is not in `dd/mm/yyyy` format
+    /// # Errors
     ///
-    /// * [RParifError::JsonError](../error/enum.RParifError.html#variant.JsonError) if response isn't a well
-    /// formed JSON
-    pub fn index_day(&self, day: Day) -> Result<Vec<Index>, RParifError> {
-        debug!("Querying indiceJour endpoint");
-        // api key is not really needed here...
-        let tmp = match day {
-            Day::Yesterday => "hier",
-            Day::Today => "jour",
-            Day::Tomorrow => "demain",
-        };
-        let response: JsonValue = self.execute_query(
-            format!(
-                "{}/indiceJour?date={}&key={}",
-                self.base_url, tmp, self.api_key
-            )
-                .as_str(),
-        )?;
-        self.index_day_to_index(response)
+    /// * [RParifError::RequestError](../error/enum.RParifError.html#variant.RequestError) when reqwest lib
+    ///   fails to build its HTTP client.
+    pub fn build(self) -> Result<RParifClient<'a>, RParifError> {
+        Ok(RParifClient {
+            #[cfg(feature = "blocking")]
+            transport: Box::new(
+                ReqwestTransport::new(self.http_client()?, &self.config.user_agent)
+                    .conditional_requests(self.conditional_requests)
+                    .max_response_bytes(self.config.max_response_bytes),
+            ),
+            config: self.config,
+        })
     }
 
-    /// Allow to get pollution indices for multiple cities through `idxville` endpoint.  
-    /// The indes is the combination of indices for all [pollutants](../objects/struct.Index.html#method.pollutants) listed in [index](../objects/struct.Index.html)
+    /// Build the client with a custom [Transport](../transport/trait.Transport.html)
+    /// (requires `blocking` feature). Pool settings and User-Agent are ignored
     ///
     /// # Arguments
     ///
-    /// * `cities` - List of INSEE city code. See [here](https://data.opendatasoft.com/explore/dataset/correspondance-code-insee-code-postal%40public/table/)
-    /// or [here](https://www.data.gouv.fr/en/datasets/correspondance-entre-les-codes-postaux-et-codes-insee-des-communes-francaises/) to find corresponding code
+    /// * `transport` - Transport used to call AirParif API
+    #[cfg(feature = "blocking")]
+    pub fn build_with_transport<T>(self, transport: T) -> RParifClient<'a>
+    where
+        T: Transport + 'static,
+    {
+        RParifClient {
+            transport: Box::new(transport),
+            config: self.config,
+        }
+    }
+
+    /// Build an [asynchronous client](../async_client/struct.AsyncRParifClient.html)
+    /// (requires `async` feature)
     ///
     /// # Errors
     ///
     /// * [RParifError::RequestError](../error/enum.RParifError.html#variant.RequestError) when reqwest lib
-    /// fails. It contains the underlying error.
+    ///   fails to build its HTTP client.
+    #[cfg(feature = "async")]
+    pub fn build_async(self) -> Result<AsyncRParifClient<'a>, RParifError> {
+        #[allow(unused_mut)]
+        let mut builder = reqwest::Client::builder();
+        // Connection pool and timeouts aren't handled by reqwest on WASM
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            if let Some(timeout) = self.pool_idle_timeout {
+                builder = builder.pool_idle_timeout(timeout);
+            }
+            if let Some(max) = self.pool_max_idle_per_host {
+                builder = builder.pool_max_idle_per_host(max);
+            }
+            if let Some(timeout) = self.connect_timeout {
+                builder = builder.connect_timeout(timeout);
+            }
+            if let Some(timeout) = self.timeout {
+                builder = builder.timeout(timeout);
+            }
+            if !self.use_env_proxy {
+                builder = builder.no_proxy();
+            }
+        }
+        Ok(AsyncRParifClient::from_config(
+            builder.build()?,
+            self.config,
+            self.conditional_requests,
+        ))
+    }
+}
+
+impl RParifClient<'_> {
+    /// Construct a new client
     ///
-    /// * [RParifError::CallError](../error/enum.RParifError.html#variant.CallError) when HTTP status is
-    /// other than 2XX. It contains the URL called, the HTTP status and the body response
+    /// # Arguments
     ///
-    /// * [RParifError::UnexpectedDate](../error/enum.RParifError.html#variant.UnexpectedDate)
-    /// if the date can't be parsed (see [`convert_string_to_date`](#method.convert_string_to_date))
+    /// * `api_key` - [API key](https://www.airparif.asso.fr/rss/api) to authenticate call
     ///
-    /// * [RParifError::MissingJsonKey](../error/enum.RParifError.html#variant.MissingJsonKey) if missing
-    /// JSON is missing `ninsee` or `indice`
+    /// # Panics
     ///
-    /// * [RParifError::WrongJsonType](../error/enum.RParifError.html#variant.WrongJsonType) if `ninsee`
-    /// isn't a string or `indice` is not a number or if JSON is not as expected
+    /// Like reqwest's `Client::new`, it panics if the HTTP client can't be initialized,
+    /// use [`builder`](#method.builder) to handle this error
+    pub fn new(api_key: &str) -> RParifClient<'_> {
+        RParifClientBuilder::new(api_key)
+            .build()
+            .expect("Failed to build HTTP client")
+    }
+
+    /// Return a [builder](./struct.RParifClientBuilder.html) to configure a client
     ///
-    /// * [RParifError::JsonError](../error/enum.RParifError.html#variant.JsonError) if response isn't a well
-    /// formed JSON
-    pub fn index_city(&self, cities: Vec<&str>) -> Result<Vec<Index>, RParifError> {
-        debug!("Querying idxville endpoint");
-        let cities = cities.join(",");
-        let response: JsonValue = self.execute_query(
-            format!(
-                "{}/idxville?villes={}&key={}",
-                self.base_url, cities, self.api_key
-            )
-                .as_str(),
-        )?;
-        self.idxville_to_index(response)
+    /// # Arguments
+    ///
+    /// * `api_key` - [API key](https://www.airparif.asso.fr/rss/api) to authenticate call
+    pub fn builder(api_key: &str) -> RParifClientBuilder<'_> {
+        RParifClientBuilder::new(api_key)
     }
 
-    /// List pollution alert for previous day, current day and next day using `episode` endpoint
+    /// Construct a new client owning the API key. Unlike [`new`](#method.new), the client is
+    /// `'static` : it can be stored in an `Arc` and shared between threads without borrowing
+    /// the key. Use [`RParifClientBuilder::new_owned`](./struct.RParifClientBuilder.html#method.new_owned)
+    /// to configure it
     ///
-    /// # Errors
+    /// # Arguments
     ///
-    /// * [RParifError::RequestError](../error/enum.RParifError.html#variant.RequestError) when reqwest lib
-    /// fails. It contains the underlying error.
+    /// * `api_key` - [API key](https://www.airparif.asso.fr/rss/api) to authenticate call
     ///
-    /// * [RParifError::CallError](../error/enum.RParifError.html#variant.CallError) when HTTP status is
-    /// other than 2XX. It contains the URL called, the HTTP status and the body response
+    /// # Panics
     ///
-    /// * [RParifError::UnexpectedDate](../error/enum.RParifError.html#variant.UnexpectedDate)
-    /// if the date can't be parsed (see [`convert_json_to_date`](#method.convert_json_to_date))
+    /// Like reqwest's `Client::new`, it panics if the HTTP client can't be initialized,
+    /// use [`RParifClientBuilder::new_owned`](./struct.RParifClientBuilder.html#method.new_owned)
+    /// to handle this error
+    pub fn new_owned(api_key: String) -> RParifClient<'static> {
+        RParifClientBuilder::new_owned(api_key)
+            .build()
+            .expect("Failed to build HTTP client")
+    }
+
+    /// Constructor used for test with httpmock. It calls `base_url` (e.g. the mock server
+    /// URL) instead of real services
     ///
-    /// * [RParifError::MissingJsonKey](../error/enum.RParifError.html#variant.MissingJsonKey) if missing
-    /// JSON is missing `type` or `niveau`
+    /// # Arguments
+    ///
+    /// * `api_key` - any string
     ///
-    /// * [RParifError::UnkownEnumValue](../error/enum.RParifError.html#variant.UnkownEnumValue) if  `type`
-    /// or `niveau` can't be converted into corresponding enum variant
+    /// * `base_url` - base URL, used as is (see
+    ///   [`RParifClientBuilder::base_url`](./struct.RParifClientBuilder.html#method.base_url))
     ///
-    /// * [RParifError::WrongJsonType](../error/enum.RParifError.html#variant.WrongJsonType) if `type`
-    ///  or `niveau` is not a string or if JSON is not as expected
+    /// # Panics
     ///
-    /// * [RParifError::JsonError](../error/enum.RParifError.html#variant.JsonError) if response isn't a well
-    /// formed JSON
-    pub fn episode(&self) -> Result<Vec<Episode>, RParifError> {
-        debug!("Querying episode endpoint");
-        let response: JsonValue =
-            self.execute_query(format!("{}/episode?key={}", self.base_url, self.api_key).as_str())?;
-        self.episode_to_episode(response)
+    /// If the HTTP client can't be initialized
+    pub fn new_test<'a>(api_key: &'a str, base_url: &'a str) -> RParifClient<'a> {
+        RParifClientBuilder::new(api_key)
+            .base_url(base_url)
+            .build()
+            .expect("Failed to build HTTP client")
     }
-}
 
-#[cfg(test)]
-mod test {
-    use chrono::Datelike;
-    use httpmock::Method::GET;
-    use httpmock::MockServer;
-    use reqwest::Url;
-
-    use crate::objects::{Level, Type};
-
-    use super::*;
-
-    #[test]
-    // Return yesterday
-    fn test_convert_json_to_date_hier() {
-        let client = RParifClient::new("api-key");
-        let json = JsonValue::String("hier".to_string());
-        let result = client.convert_json_to_date(&json);
-
-        let expected = Utc::today();
-        let expected = expected.checked_sub_signed(Duration::days(1));
-        assert!(result.is_ok(), "Convert JSON 'hier' fails");
-        assert_eq!(result.ok(), expected);
+    /// Return the source of current time
+    #[cfg(feature = "blocking")]
+    pub(crate) fn clock(&self) -> &dyn Clock {
+        self.config.clock.as_ref()
     }
 
-    #[test]
-    // Return today
-    fn test_convert_json_to_date_jour() {
-        let client = RParifClient::new("api-key");
-        let json = JsonValue::String("jour".to_string());
-        let result = client.convert_json_to_date(&json);
-
-        let expected = Utc::today();
-        assert!(result.is_ok(), "Convert JSON 'jour' fails");
-        assert_eq!(result.ok(), Some(expected));
+    /// Return the URL called by [`index`](#method.index), without calling it
+    pub fn index_url(&self) -> RequestUrl {
+        self.config.index_url()
     }
 
-    #[test]
-    // Return tomorrow
-    fn test_convert_json_to_date_demain() {
-        let client = RParifClient::new("api-key");
-        let json = JsonValue::String("demain".to_string());
-        let result = client.convert_json_to_date(&json);
-
-        let expected = Utc::today();
-        let expected = expected.checked_add_signed(Duration::days(1));
-        assert!(result.is_ok(), "Convert JSON 'demain' fails");
-        assert_eq!(result.ok(), expected);
+    /// Return the URL called by [`index_day`](#method.index_day), without calling it
+    ///
+    /// # Arguments
+    ///
+    /// * `day` - Which day to get indices pollution for
+    pub fn index_day_url(&self, day: Day) -> RequestUrl {
+        self.config.index_day_url(day)
     }
 
-    #[test]
-    // Return an error because date isn't 'hier', 'jour' or 'demain'
-    fn test_convert_json_to_date_wrong() {
-        let client = RParifClient::new("api-key");
-        let json = JsonValue::String("wrong string".to_string());
-        let result = client.convert_json_to_date(&json);
-
-        assert!(result.is_err(), "Convert JSON 'wrong string' should fails");
-        match result.err().unwrap() {
-            RParifError::UnexpectedDate(s) => assert_eq!(s, "\"wrong string\"".to_string()),
-            _ => panic!("Wrong error"),
-        }
+    /// Return the URL called by [`index_city`](#method.index_city), without calling it.
+    /// All cities are in this URL, whereas `index_city` may split them into several requests
+    ///
+    /// # Arguments
+    ///
+    /// * `cities` - List of INSEE city code
+    pub fn index_city_url<I, S>(&self, cities: I) -> RequestUrl
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        self.config.index_city_url(cities)
     }
 
-    #[test]
-    // Return yesterday day
-    fn test_convert_string_to_date_hier() {
-        let client = RParifClient::new("api-key");
-        let result = client.convert_string_to_date("hier");
-
-        let expected = Utc::today();
-        let expected = expected.checked_sub_signed(Duration::days(1));
-        assert!(result.is_ok(), "Convert string 'hier' fails");
-        assert_eq!(result.ok(), expected);
+    /// Return the URL called by [`episode`](#method.episode), without calling it
+    pub fn episode_url(&self) -> RequestUrl {
+        self.config.episode_url()
     }
+}
 
-    #[test]
-    // Return today
-    fn test_convert_string_to_date_jour() {
-        let client = RParifClient::new("api-key");
-        let result = client.convert_string_to_date("jour");
-
-        let expected = Utc::today();
-        assert!(result.is_ok(), "Convert string 'jour' fails");
-        assert_eq!(result.ok(), Some(expected));
+impl Config<'_> {
+    /// Date `jour` refers to : the reference date if set, current date in Paris otherwise
+    pub(crate) fn today(&self) -> NaiveDate {
+        self.reference_date
+            .unwrap_or_else(|| paris_date(self.clock.now()))
     }
 
-    #[test]
-    // Return tomorrow
-    fn test_convert_string_to_date_demain() {
-        let client = RParifClient::new("api-key");
-        let result = client.convert_string_to_date("demain");
-
-        let expected = Utc::today();
-        let expected = expected.checked_add_signed(Duration::days(1));
-        assert!(result.is_ok(), "Convert string 'demain' fails");
-        assert_eq!(result.ok(), expected);
+    /// Return `true` if the pollutant is kept by
+    /// [`only_pollutants`](./struct.RParifClientBuilder.html#method.only_pollutants)
+    ///
+    /// # Arguments
+    ///
+    /// * `pollutant` - Pollutant name
+    fn keeps_pollutant(&self, pollutant: &str) -> bool {
+        self.only_pollutants.is_empty()
+            || self
+                .only_pollutants
+                .iter()
+                .any(|p| p.eq_ignore_ascii_case(pollutant))
     }
 
-    #[test]
-    // Return an error because date isn't 'hier', 'jour' or 'demain'
-    fn test_convert_string_to_date_wrong() {
-        let client = RParifClient::new("api-key");
-        let result = client.convert_string_to_date("wrong string");
-
-        assert!(
-            result.is_err(),
-            "Convert string 'wrong string' should fails"
-        );
-        match result.err().unwrap() {
-            RParifError::UnexpectedDate(s) => assert_eq!(s, "wrong string".to_string()),
-            _ => panic!("Wrong error"),
+    /// Call metrics hook, if any
+    ///
+    /// # Arguments
+    ///
+    /// * `url` - URL called
+    ///
+    /// * `duration` - Time spent on the request
+    ///
+    /// * `status` - HTTP status, if a response was received
+    ///
+    /// * `success` - Whether the request succeeded
+    pub(crate) fn record_metrics(
+        &self,
+        url: &str,
+        duration: Duration,
+        status: Option<u16>,
+        success: bool,
+    ) {
+        if let Some(MetricsHook(hook)) = &self.metrics {
+            hook(&RequestMetrics {
+                endpoint: endpoint_name(url).to_string(),
+                duration,
+                status,
+                success,
+            });
         }
     }
 
-    #[test]
-    // Get number value from JSON return an error because
-    // the JSON key doesn't exists
-    fn test_get_number_value_no_key() {
-        let client = RParifClient::new("api-key");
-        let data = object! {
-            wrong_key: 12
-        };
-
-        let result = client.get_number_value("key", &data);
+    /// This method converts indice's JSON response into a list of
+    /// [`Index`](../objects/struct.Index.html)
+    ///
+    /// # Arguments
+    ///
+    /// * `json` - HTTP body as JsonValue
+    ///
+    /// # Errors
+    ///
+    /// * [RParifError::UnexpectedDate](../error/enum.RParifError.html#variant.UnexpectedDate)
+    ///   if the date can't be parsed
+    ///
+    /// * [RParifError::MissingJsonKey](../error/enum.RParifError.html#variant.MissingJsonKey) if missing
+    ///   JSON is missing `indice`
+    ///
+    /// * [RParifError::WrongJsonType](../error/enum.RParifError.html#variant.WrongJsonType) if `indice`
+    ///   is not a number, if `url_carte` is not a string or if JSON is not as expected
+    pub(crate) fn index_to_index(&self, json: JsonValue) -> Result<Vec<Index>, RParifError> {
+        let mut result: Vec<Index> = Vec::new();
+        self.visit_indices(&json, |date, pollutant, index, url, comment| {
+            result.push(
+                Index::new(
+                    date,
+                    url.map(|u| u.to_string()),
+                    vec![pollutant.to_string()],
+                    index,
+                    None,
+                )
+                .with_comment(comment.map(|c| c.to_string())),
+            );
+        })?;
+        trace!(target: LOG_TARGET, "Result : {:?}", result);
+        Ok(result)
+    }
 
-        assert!(result.is_err());
-        match result.err().unwrap() {
-            RParifError::MissingJsonKey { key, json } => {
-                assert_eq!(key, "key".to_string());
-                assert_eq!(json, "{\"wrong_key\":12}")
+    /// Call `f` for each element of indice's JSON response with its date, pollutant
+    /// (always `global`), index, map URL and comment
+    ///
+    /// # Arguments
+    ///
+    /// * `json` - HTTP body as JsonValue
+    ///
+    /// * `f` - Called for each index
+    ///
+    /// # Errors
+    ///
+    /// See [`index_to_index`](#method.index_to_index)
+    pub(crate) fn visit_indices<F>(&self, json: &JsonValue, mut f: F) -> Result<(), RParifError>
+    where
+        F: FnMut(NaiveDate, &str, u32, Option<&str>, Option<&str>),
+    {
+        let today = self.today();
+        trace!(target: LOG_TARGET, "Indice json : {}", json);
+        match json {
+            JsonValue::Array(data) => {
+                for (position, value) in data.iter().enumerate() {
+                    trace!(target: LOG_TARGET, "Converting : {}", value);
+                    let at = |err: RParifError| err.at(&format!("[{}]", position));
+                    let date = json_to_date(&value["date"], today)?;
+                    let url = optional_str("url_carte", value).map_err(at)?;
+                    let index = number_value("indice", value).map_err(at)?;
+                    let comment = optional_str("commentaire", value).map_err(at)?;
+                    f(date, "global", index, url, comment);
+                }
+                Ok(())
             }
-            _ => panic!("Wrong error"),
+            _ => Err(RParifError::WrongJsonType {
+                expected: "array".to_string(),
+                json: json.dump(),
+                path: None,
+            }),
         }
     }
 
-    #[test]
-    // Get number value from JSON return an error because
-    // the value is not a string
-    fn test_get_number_value_wrong_type() {
-        let client = RParifClient::new("api-key");
-        let data = object! {
-            key: "wrong type"
-        };
+    /// This method converts indiceJour's JSON response into a list of  [`Index`](../objects/struct.Index.html)
+    ///
+    /// # Arguments
+    ///
+    /// * `json` - HTTP body as JsonValue
+    ///
+    /// * `pollutants` - If set, only keep these pollutants (case-insensitive), others are skipped
+    ///
+    /// # Errors
+    ///
+    /// * [RParifError::MissingJsonKey](../error/enum.RParifError.html#variant.MissingJsonKey) if missing
+    ///   JSON is missing `indice` or `date`
+    ///
+    /// * [RParifError::WrongJsonType](../error/enum.RParifError.html#variant.WrongJsonType) if `indice`
+    ///   is not a number, if `date` isn't a string or if JSON is not an object
+    ///
+    /// * [RParifError::DateParseError](../error/enum.RParifError.html#variant.DateParseError) if `date`
+    ///   is neither in `dd/mm/yyyy` nor in `yyyy-mm-dd` format, optionally followed by a time
+    pub(crate) fn index_day_to_index(
+        &self,
+        json: JsonValue,
+        pollutants: Option<&[&str]>,
+    ) -> Result<Vec<Index>, RParifError> {
+        trace!(target: LOG_TARGET, "Indice day json : {}", json);
+        if !json.is_object() {
+            return Err(RParifError::WrongJsonType {
+                expected: "object".to_string(),
+                json: json.dump(),
+                path: None,
+            });
+        }
+        let mut result: Vec<Index> = Vec::new();
 
-        let result = client.get_number_value("key", &data);
+        // Getting date from json
+        let date = string_value("date", &json)?;
+        let date: NaiveDate = parse_day_date(date)?;
+        trace!(target: LOG_TARGET, "Date : {}", date);
 
-        assert!(result.is_err());
-        match result.err().unwrap() {
-            RParifError::WrongJsonType { expected, json } => {
-                assert_eq!(expected, "number".to_string());
-                assert_eq!(json, "\"wrong type\"")
+        let comment = optional_string("commentaire", &json)?;
+
+        for (key, value) in json.entries() {
+            let wanted = pollutants
+                .map(|p| p.iter().any(|p| p.eq_ignore_ascii_case(key)))
+                .unwrap_or(true)
+                && self.keeps_pollutant(key);
+            if key != "date" && key != "commentaire" && wanted {
+                trace!(target: LOG_TARGET, "Converting : {}", value);
+                let index = number_value("indice", value).map_err(|e| e.at(key))?;
+                let url = optional_string("url_carte", value).map_err(|e| e.at(key))?;
+                result.push(
+                    Index::new(date, url, vec![normalize_pollutant(key)], index, None)
+                        .with_comment(comment.clone()),
+                );
             }
-            _ => panic!("Wrong error"),
         }
-    }
-
-    #[test]
-    // Get number value from JSON ok
-    fn test_get_number_value() {
-        let client = RParifClient::new("api-key");
-        let data = object! {
-            key: 12
-        };
 
-        let result = client.get_number_value("key", &data);
-        assert!(result.is_ok());
-        assert_eq!(result.ok(), Some(12));
+        mark_global_drivers(&mut result);
+        trace!(target: LOG_TARGET, "Result : {:?}", result);
+        Ok(result)
     }
 
-    #[test]
-    // Get string value from JSON return an error because
-    // the JSON key doesn't exists
-    fn test_get_string_value_no_key() {
-        let client = RParifClient::new("api-key");
-        let data = object! {
-            wrong_key: "data"
-        };
+    /// This method converts idxville's JSON response into a list of  [`Index`](../objects/struct.Index.html)
+    ///
+    /// # Arguments
+    ///
+    /// * `json` - HTTP body as JsonValue
+    ///
+    /// # Errors
+    ///
+    /// * [RParifError::UnexpectedDate](../error/enum.RParifError.html#variant.UnexpectedDate)
+    ///   if the date can't be parsed
+    ///
+    /// * [RParifError::MissingJsonKey](../error/enum.RParifError.html#variant.MissingJsonKey) if missing
+    ///   JSON is missing `ninsee` or `indice`
+    ///
+    /// * [RParifError::WrongJsonType](../error/enum.RParifError.html#variant.WrongJsonType) if `ninsee`
+    ///   isn't a string, `indice` is not a number, one of `polluants` is not a string or if JSON is
+    ///   not as expected
+    ///
+    /// * [RParifError::InvalidInsee](../error/enum.RParifError.html#variant.InvalidInsee) if `ninsee`
+    ///   isn't a valid INSEE code
+    pub(crate) fn idxville_to_index(&self, json: JsonValue) -> Result<Vec<Index>, RParifError> {
+        let today = self.today();
+        trace!(target: LOG_TARGET, "Idxville json : {}", json);
+        let mut result: Vec<Index> = Vec::new();
 
-        let result = client.get_string_value("key", &data);
+        match json {
+            JsonValue::Array(data) => {
+                for (position, i) in data.iter().enumerate() {
+                    trace!(target: LOG_TARGET, "Converting : {}", i);
+                    let position = format!("[{}]", position);
+                    let insee: Insee = string_value("ninsee", i)
+                        .map_err(|e| e.at(&position))?
+                        .parse()?;
+                    trace!(target: LOG_TARGET, "City code : {}", insee);
+                    for (key, value) in i.entries() {
+                        if key != "ninsee" {
+                            trace!(target: LOG_TARGET, "Key : {}", key);
+                            trace!(target: LOG_TARGET, "Converting : {}", value);
+                            let date: NaiveDate = string_to_date(key, today)?;
+                            let index = number_value("indice", value)
+                                .map_err(|e| e.at(key).at(&position))?;
+                            let pollutants: Vec<String> = match &value["polluants"] {
+                                JsonValue::Array(p) => p
+                                    .iter()
+                                    .enumerate()
+                                    .map(|(n, v)| {
+                                        v.as_str().map(normalize_pollutant).ok_or_else(|| {
+                                            RParifError::WrongJsonType {
+                                                expected: "string".to_string(),
+                                                json: v.dump(),
+                                                path: Some(format!("polluants[{}]", n)),
+                                            }
+                                            .at(key)
+                                            .at(&position)
+                                        })
+                                    })
+                                    .collect::<Result<_, RParifError>>()?,
+                                _ => Vec::new(),
+                            };
+                            if !self.only_pollutants.is_empty()
+                                && !pollutants.iter().any(|p| self.keeps_pollutant(p))
+                            {
+                                trace!(target: LOG_TARGET, "Skipping pollutants {:?}", pollutants);
+                                continue;
+                            }
+                            result.push(Index::new(
+                                date,
+                                None,
+                                pollutants,
+                                index,
+                                Some(insee.clone()),
+                            ));
+                        }
+                    }
+                }
 
-        assert!(result.is_err());
-        match result.err().unwrap() {
-            RParifError::MissingJsonKey { key, json } => {
-                assert_eq!(key, "key".to_string());
-                assert_eq!(json, "{\"wrong_key\":\"data\"}")
+                trace!(target: LOG_TARGET, "Result : {:?}", result);
+                Ok(result)
             }
-            _ => panic!("Wrong error"),
+            _ => Err(RParifError::WrongJsonType {
+                expected: "array".to_string(),
+                json: json.dump(),
+                path: None,
+            }),
         }
     }
 
-    #[test]
-    // Get string value from JSON return an error because
-    // the value is not a string
-    fn test_get_string_value_wrong_type() {
-        let client = RParifClient::new("api-key");
-        let data = object! {
-            key: 12
-        };
+    /// This method converts episode's JSON response into a list of  [`Episode`](../objects/struct.Episode.html)
+    ///
+    /// # Arguments
+    ///
+    /// * `json` - HTTP body as JsonValue
+    ///
+    /// # Errors
+    ///
+    /// * [RParifError::UnexpectedDate](../error/enum.RParifError.html#variant.UnexpectedDate)
+    ///   if the date can't be parsed
+    ///
+    /// * [RParifError::MissingJsonKey](../error/enum.RParifError.html#variant.MissingJsonKey) if missing
+    ///   JSON is missing `type` or `niveau`
+    ///
+    /// * [RParifError::UnkownEnumValue](../error/enum.RParifError.html#variant.UnkownEnumValue) if  `type`,
+    ///   `niveau` or one of `criteres` can't be converted into corresponding enum variant
+    ///
+    /// * [RParifError::WrongJsonType](../error/enum.RParifError.html#variant.WrongJsonType) if `type`,
+    ///   `niveau` or one of `criteres` is not a string or if JSON is not as expected
+    pub(crate) fn episode_to_episode(&self, json: JsonValue) -> Result<Vec<Episode>, RParifError> {
+        let today = self.today();
+        let mut result: Vec<Episode> = Vec::new();
 
-        let result = client.get_string_value("key", &data);
+        match &json {
+            JsonValue::Array(data) => {
+                for (position, j) in data.iter().enumerate() {
+                    result.push(
+                        Episode::from_json(j, self.lenient, today)
+                            .map_err(|e| e.at(&format!("[{}]", position)))?,
+                    );
+                }
 
-        assert!(result.is_err());
-        match result.err().unwrap() {
-            RParifError::WrongJsonType { expected, json } => {
-                assert_eq!(expected, "string".to_string());
-                assert_eq!(json, "12")
+                trace!(target: LOG_TARGET, "Result : {:?}", result);
+                Ok(result)
             }
-            _ => panic!("Wrong error"),
+            _ => Err(RParifError::WrongJsonType {
+                expected: "array".to_string(),
+                json: json.dump(),
+                path: None,
+            }),
         }
     }
 
-    #[test]
-    // Get string value from JSON ok
-    fn test_get_string_value() {
-        let client = RParifClient::new("api-key");
-        let data = object! {
-            key: "data"
-        };
-
-        let result = client.get_string_value("key", &data);
-        assert!(result.is_ok());
-        assert_eq!(result.ok(), Some("data"));
+    /// Base URL followed by API version, if any
+    fn root_url(&self) -> String {
+        match &self.api_version {
+            Some(version) => format!("{}/{}", self.base_url.trim_end_matches('/'), version),
+            None => self.base_url.to_string(),
+        }
     }
 
-    #[test]
-    // Call return an error because reqwest return an error
-    fn test_execute_query_reqwest_error() {
-        let client = RParifClient::new("api-key");
-        let result = client.execute_query("http://localhost:5001");
+    /// URL of an endpoint, without parameters
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path of the endpoint
+    fn endpoint_url(&self, path: &str) -> String {
+        format!("{}/{}", self.root_url(), path.trim_start_matches('/'))
+    }
 
-        assert!(result.is_err());
-        match result.err().unwrap() {
-            RParifError::RequestError(err) => {
-                assert_eq!(
-                    err.url(),
-                    Some(&Url::parse("http://localhost:5001").unwrap())
-                );
-            }
-            _ => panic!("Wrong error"),
+    /// URL of an endpoint with its query parameters. Values are percent-encoded, values of a
+    /// parameter are joined with `,`, then extra parameters are added and the API key is added
+    /// last as `key` parameter
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path of the endpoint
+    ///
+    /// * `params` - Name and values of each parameter
+    ///
+    /// * `with_key` - Whether API key is sent
+    fn request_url(&self, path: &str, params: &[(&str, &[&str])], with_key: bool) -> RequestUrl {
+        let mut query: Vec<String> = params
+            .iter()
+            .map(|(name, values)| {
+                let values: Vec<String> = values.iter().map(|v| encode_query_value(v)).collect();
+                format!("{}={}", name, values.join(","))
+            })
+            .collect();
+        for (name, value) in &self.extra_params {
+            query.push(format!(
+                "{}={}",
+                encode_query_value(name),
+                encode_query_value(value)
+            ));
+        }
+        if with_key {
+            query.push(format!("key={}", encode_query_value(&self.api_key)));
         }
+        let mut url = self.endpoint_url(path);
+        if !query.is_empty() {
+            url.push('?');
+            url.push_str(&query.join("&"));
+        }
+        RequestUrl(url)
     }
 
-    #[test]
-    // Call return an error because the result is not a well formed JSON
-    fn test_execute_query_reqwest_not_json() {
-        let server = MockServer::start();
-        let _search_mock = server.mock(|when, then| {
-            when.method(GET)
-                .path("/path");
-            then.status(200)
-                .body("this is not a json");
-        });
-
-        let client = RParifClient::new("api-key");
-        let result = client.execute_query(&server.url("/path"));
-
-        assert!(result.is_err());
-        match result.err().unwrap() {
-            RParifError::JsonError(err) => {
-                assert_eq!(format!("{}", err), "Unexpected character: h at (1:2)")
-            }
-            _ => panic!("Wrong error"),
-        }
+    /// URL of `indice` endpoint
+    pub(crate) fn index_url(&self) -> RequestUrl {
+        self.request_url(&self.paths.indice, &[], self.send_key_when_optional)
     }
 
-    #[test]
-    // Call return an error because status is different from 2xx
-    fn test_execute_query_reqwest_wrong_status() {
-        let server = MockServer::start();
+    /// URL of `indiceJour` endpoint
+    ///
+    /// # Arguments
+    ///
+    /// * `day` - Which day to get indices pollution for
+    pub(crate) fn index_day_url(&self, day: Day) -> RequestUrl {
+        let tmp = match day {
+            Day::Yesterday => "hier",
+            Day::Today => "jour",
+            Day::Tomorrow => "demain",
+        };
+        self.request_url(
+            &self.paths.indice_jour,
+            &[("date", &[tmp])],
+            self.send_key_when_optional,
+        )
+    }
+
+    /// URL of `idxville` endpoint
+    ///
+    /// # Arguments
+    ///
+    /// * `cities` - List of INSEE city code
+    pub(crate) fn index_city_url<I, S>(&self, cities: I) -> RequestUrl
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let cities: Vec<S> = cities.into_iter().collect();
+        let cities: Vec<&str> = cities.iter().map(|c| c.as_ref()).collect();
+        self.request_url(&self.paths.idxville, &[("villes", &cities)], true)
+    }
+
+    /// URL of `episode` endpoint
+    pub(crate) fn episode_url(&self) -> RequestUrl {
+        self.request_url(&self.paths.episode, &[], true)
+    }
+}
+
+#[cfg(feature = "blocking")]
+impl RParifClient<'_> {
+    /// Construct a client that reads responses recorded in `directory` instead of calling
+    /// AirParif (see [FixtureTransport](../transport/struct.FixtureTransport.html)).
+    /// Useful for demos and reproducible tests, no API key is needed
+    ///
+    /// # Arguments
+    ///
+    /// * `directory` - Directory containing `indice.json`, `indiceJour.json`, `idxville.json`
+    ///   and/or `episode.json`
+    pub fn from_fixtures<P: Into<PathBuf>>(directory: P) -> RParifClient<'static> {
+        RParifClientBuilder::new("").build_with_transport(FixtureTransport::new(directory))
+    }
+
+    /// Execute a query to HTTP AirParif endpoint and return the body
+    /// content as a string.
+    ///
+    /// # Arguments
+    ///
+    /// * `url` - URL to call
+    ///
+    /// # Errors
+    ///
+    /// * [RParifError::RequestError](../error/enum.RParifError.html#variant.RequestError) when reqwest lib
+    ///   fails. It contains the underlying error.
+    ///
+    /// * [RParifError::CallError](../error/enum.RParifError.html#variant.CallError) when HTTP status is
+    ///   other than 2XX. It contains the URL called, the HTTP status and the body response
+    ///
+    /// * [RParifError::RateLimited](../error/enum.RParifError.html#variant.RateLimited) when HTTP status
+    ///   is 429. It contains the `Retry-After` delay, if any
+    ///
+    /// * [RParifError::ResponseTooLarge](../error/enum.RParifError.html#variant.ResponseTooLarge) when
+    ///   the body exceeds the maximum size
+    ///
+    /// * [RParifError::JsonError](../error/enum.RParifError.html#variant.JsonError) if response isn't a well
+    ///   formed JSON
+    fn execute_query(&self, url: &str) -> Result<JsonValue, RParifError> {
+        let start = Instant::now();
+        let (status, result) = match self.transport.get(url) {
+            Ok((status, body)) => (
+                Some(status),
+                read_response(url, status, (200..300).contains(&status), None, &body),
+            ),
+            Err(err) => (error_status(&err), Err(err)),
+        };
+        self.config
+            .record_metrics(url, start.elapsed(), status, result.is_ok());
+        result
+    }
+
+    /// Access global pollution index for previous day, current day and next day through `indice` endpoint
+    ///
+    /// # Errors
+    ///
+    /// * [RParifError::RequestError](../error/enum.RParifError.html#variant.RequestError) when reqwest lib
+    ///   fails. It contains the underlying error.
+    ///
+    /// * [RParifError::CallError](../error/enum.RParifError.html#variant.CallError) when HTTP status is
+    ///   other than 2XX. It contains the URL called, the HTTP status and the body response
+    ///
+    /// * [RParifError::RateLimited](../error/enum.RParifError.html#variant.RateLimited) when HTTP status
+    ///   is 429. It contains the `Retry-After` delay, if any
+    ///
+    /// * [RParifError::ResponseTooLarge](../error/enum.RParifError.html#variant.ResponseTooLarge) when
+    ///   the body exceeds the maximum size
+    ///
+    /// * [RParifError::UnexpectedDate](../error/enum.RParifError.html#variant.UnexpectedDate)
+    ///   if the date can't be parsed
+    ///
+    /// * [RParifError::MissingJsonKey](../error/enum.RParifError.html#variant.MissingJsonKey) if missing
+    ///   JSON is missing `ìndice`
+    ///
+    /// * [RParifError::WrongJsonType](../error/enum.RParifError.html#variant.WrongJsonType) if `ìndice`
+    ///   is not a number, if `url_carte` is not a string or if JSON is not as expected
+    ///
+    /// * [RParifError::JsonError](../error/enum.RParifError.html#variant.JsonError) if response isn't a well
+    ///   formed JSON
+    pub fn index(&self) -> Result<Vec<Index>, RParifError> {
+        self.index_raw().map(|(result, _)| result)
+    }
+
+    /// Same as [`index`](#method.index) but also return the JSON response as sent by AirParif
+    ///
+    /// # Errors
+    ///
+    /// See [`index`](#method.index)
+    pub fn index_raw(&self) -> Result<(Vec<Index>, JsonValue), RParifError> {
+        debug!(target: LOG_TARGET, "Querying indice endpoint");
+        let response: JsonValue = self.execute_query(self.index_url().as_str())?;
+        Ok((self.config.index_to_index(response.clone())?, response))
+    }
+
+    /// Check that the API key is accepted by AirParif, e.g. before running a batch job.
+    /// It calls `episode` endpoint, as `indice` and `indiceJour` don't require a key,
+    /// so it consumes one request against any quota
+    ///
+    /// # Errors
+    ///
+    /// Same errors as [`episode`](#method.episode), except
+    /// [RParifError::CallError](../error/enum.RParifError.html#variant.CallError) with 401 or 403
+    /// status that gives `Ok(false)`
+    pub fn validate_key(&self) -> Result<bool, RParifError> {
+        debug!(target: LOG_TARGET, "Validating API key");
+        match self.execute_query(self.episode_url().as_str()) {
+            Ok(_) => Ok(true),
+            Err(RParifError::CallError { status, .. }) if status == 401 || status == 403 => {
+                Ok(false)
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Return today's global pollution index, using [`index`](#method.index). Today is the
+    /// current date in Paris (see [paris_date](../clock/fn.paris_date.html)), whatever the time
+    /// zone of the machine, or the
+    /// [reference date](./struct.RParifClientBuilder.html#method.with_reference_date) if set
+    ///
+    /// # Errors
+    ///
+    /// * [RParifError::NotFound](../error/enum.RParifError.html#variant.NotFound) if AirParif
+    ///   response has no index for today
+    ///
+    /// * Same errors as [`index`](#method.index)
+    pub fn current_index(&self) -> Result<Index, RParifError> {
+        let today = self.config.today();
+        self.index()?
+            .into_iter()
+            .find(|index| index.date() == today)
+            .ok_or_else(|| RParifError::NotFound(format!("index for {}", today)))
+    }
+
+    /// Return global pollution index of previous day, current day and next day (in this order),
+    /// using [`index`](#method.index). Each index is matched to its [Day](../objects/enum.Day.html)
+    /// by comparing its date with current date in Paris (see
+    /// [paris_date](../clock/fn.paris_date.html)), or with the
+    /// [reference date](./struct.RParifClientBuilder.html#method.with_reference_date) if set,
+    /// days missing from AirParif response are `None`
+    ///
+    /// # Errors
+    ///
+    /// Same errors as [`index`](#method.index)
+    pub fn forecast(&self) -> Result<[(Day, Option<Index>); 3], RParifError> {
+        let today = self.config.today();
+        let mut result = [
+            (Day::Yesterday, None),
+            (Day::Today, None),
+            (Day::Tomorrow, None),
+        ];
+        for index in self.index()? {
+            let day = Day::from_offset((index.date() - today).num_days());
+            if let Some(day) = day {
+                let slot = &mut result[(day.offset_days() + 1) as usize].1;
+                if slot.is_none() {
+                    *slot = Some(index);
+                }
+            }
+        }
+        Ok(result)
+    }
+
+    /// Retrieve index pollution (global and per pollutant) for a given date (previous day, current or next day) using
+    /// `indiceJour` endpoint
+    ///
+    /// Indices are region-wide : `indiceJour` doesn't accept any city parameter, so returned indices have no
+    /// INSEE code. Use [`index_city`](#method.index_city) for indices of a city, which only gives the pollutants
+    /// responsible of the index.
+    ///
+    /// # Arguments
+    ///
+    /// * `day` - Which day to get indices pollution for
+    ///
+    /// # Errors
+    ///
+    /// * [RParifError::RequestError](../error/enum.RParifError.html#variant.RequestError) when reqwest lib
+    ///   fails. It contains the underlying error.
+    ///
+    /// * [RParifError::CallError](../error/enum.RParifError.html#variant.CallError) when HTTP status is
+    ///   other than 2XX. It contains the URL called, the HTTP status and the body response
+    ///
+    /// * [RParifError::RateLimited](../error/enum.RParifError.html#variant.RateLimited) when HTTP status
+    ///   is 429. It contains the `Retry-After` delay, if any
+    ///
+    /// * [RParifError::ResponseTooLarge](../error/enum.RParifError.html#variant.ResponseTooLarge) when
+    ///   the body exceeds the maximum size
+    ///
+    /// * [RParifError::MissingJsonKey](../error/enum.RParifError.html#variant.MissingJsonKey) if missing
+    ///   JSON is missing `ìndice` or `date`
+    ///
+    /// * [RParifError::WrongJsonType](../error/enum.RParifError.html#variant.WrongJsonType) if `ìndice`
+    ///   is not a number, if `date` isn't a string or if JSON is not an object
+    ///
+    /// * [RParifError::DateParseError](../error/enum.RParifError.html#variant.DateParseError) if `date`
+    ///   is neither in `dd/mm/yyyy` nor in `yyyy-mm-dd` format, optionally followed by a time
+    ///
+    /// * [RParifError::JsonError](../error/enum.RParifError.html#variant.JsonError) if response isn't a well
+    ///   formed JSON
+    pub fn index_day(&self, day: Day) -> Result<Vec<Index>, RParifError> {
+        self.index_day_raw(day).map(|(result, _)| result)
+    }
+
+    /// Same as [`index_day`](#method.index_day) but also return the JSON response as sent by AirParif
+    ///
+    /// # Arguments
+    ///
+    /// * `day` - Which day to get indices pollution for
+    ///
+    /// # Errors
+    ///
+    /// See [`index_day`](#method.index_day)
+    pub fn index_day_raw(&self, day: Day) -> Result<(Vec<Index>, JsonValue), RParifError> {
+        debug!(target: LOG_TARGET, "Querying indiceJour endpoint");
+        let response: JsonValue = self.execute_query(self.index_day_url(day).as_str())?;
+        Ok((
+            self.config.index_day_to_index(response.clone(), None)?,
+            response,
+        ))
+    }
+
+    /// Same as [`index_day`](#method.index_day) but only keep indices of the given pollutants
+    /// (e.g. `global`, `o3`), other pollutants are skipped during conversion.
+    /// Pollutant names are compared case-insensitively.
+    ///
+    /// # Arguments
+    ///
+    /// * `day` - Which day to get indices pollution for
+    ///
+    /// * `pollutants` - Pollutants to keep
+    ///
+    /// # Errors
+    ///
+    /// See [`index_day`](#method.index_day)
+    pub fn index_day_filtered(
+        &self,
+        day: Day,
+        pollutants: &[&str],
+    ) -> Result<Vec<Index>, RParifError> {
+        debug!(target: LOG_TARGET, "Querying indiceJour endpoint");
+        let response: JsonValue = self.execute_query(self.index_day_url(day).as_str())?;
+        self.config.index_day_to_index(response, Some(pollutants))
+    }
+
+    /// Allow to get pollution indices for multiple cities through `idxville` endpoint.  
+    /// The indes is the combination of indices for all [pollutants](../objects/struct.Index.html#method.pollutants) listed in [index](../objects/struct.Index.html)
+    ///
+    /// Cities are split into batches (see [`city_batch_size`](./struct.RParifClientBuilder.html#method.city_batch_size)),
+    /// one request is made per batch and results are concatenated in the order of `cities`.
+    /// Cities without data are silently dropped by AirParif, use
+    /// [missing_cities](../objects/fn.missing_cities.html) to find them.
+    ///
+    /// # Arguments
+    ///
+    /// * `cities` - List of INSEE city code (`Vec`, slice or iterator of `&str` or `String`). See [here](https://data.opendatasoft.com/explore/dataset/correspondance-code-insee-code-postal%40public/table/)
+    ///   or [here](https://www.data.gouv.fr/en/datasets/correspondance-entre-les-codes-postaux-et-codes-insee-des-communes-francaises/) to find corresponding code
+    ///
+    /// # Errors
+    ///
+    /// * [RParifError::EmptyCityList](../error/enum.RParifError.html#variant.EmptyCityList) when `cities`
+    ///   is empty
+    ///
+    /// * [RParifError::InvalidInsee](../error/enum.RParifError.html#variant.InvalidInsee) when one of
+    ///   `cities` isn't a valid INSEE code (see [Insee](../objects/struct.Insee.html))
+    ///
+    /// * [RParifError::RequestError](../error/enum.RParifError.html#variant.RequestError) when reqwest lib
+    ///   fails. It contains the underlying error.
+    ///
+    /// * [RParifError::CallError](../error/enum.RParifError.html#variant.CallError) when HTTP status is
+    ///   other than 2XX. It contains the URL called, the HTTP status and the body response
+    ///
+    /// * [RParifError::RateLimited](../error/enum.RParifError.html#variant.RateLimited) when HTTP status
+    ///   is 429. It contains the `Retry-After` delay, if any
+    ///
+    /// * [RParifError::ResponseTooLarge](../error/enum.RParifError.html#variant.ResponseTooLarge) when
+    ///   the body exceeds the maximum size
+    ///
+    /// * [RParifError::UnexpectedDate](../error/enum.RParifError.html#variant.UnexpectedDate)
+    ///   if a date isn't `hier`, `jour` or `demain`
+    ///
+    /// * [RParifError::MissingJsonKey](../error/enum.RParifError.html#variant.MissingJsonKey) if missing
+    ///   JSON is missing `ninsee` or `indice`
+    ///
+    /// * [RParifError::WrongJsonType](../error/enum.RParifError.html#variant.WrongJsonType) if `ninsee`
+    ///   isn't a string, `indice` is not a number, one of `polluants` is not a string or if JSON is
+    ///   not as expected
+    ///
+    /// * [RParifError::JsonError](../error/enum.RParifError.html#variant.JsonError) if response isn't a well
+    ///   formed JSON
+    pub fn index_city<I, S>(&self, cities: I) -> Result<Vec<Index>, RParifError>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        self.index_city_raw(cities).map(|(result, _)| result)
+    }
+
+    /// Same as [`index_city`](#method.index_city) but also return the JSON response as sent by AirParif.
+    /// When several requests are made, it's an array with elements of all responses.
+    ///
+    /// # Arguments
+    ///
+    /// * `cities` - List of INSEE city code
+    ///
+    /// # Errors
+    ///
+    /// See [`index_city`](#method.index_city)
+    pub fn index_city_raw<I, S>(&self, cities: I) -> Result<(Vec<Index>, JsonValue), RParifError>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        debug!(target: LOG_TARGET, "Querying idxville endpoint");
+        let cities: Vec<Insee> = cities
+            .into_iter()
+            .map(|c| c.as_ref().parse())
+            .collect::<Result<_, RParifError>>()?;
+        if cities.is_empty() {
+            return Err(RParifError::EmptyCityList);
+        }
+        let mut result: Vec<Index> = Vec::new();
+        let mut raw: Vec<JsonValue> = Vec::new();
+        for batch in cities.chunks(self.config.city_batch_size) {
+            debug!(target: LOG_TARGET, "Querying cities {:?}", batch);
+            let response: JsonValue = self.execute_query(self.index_city_url(batch).as_str())?;
+            result.append(&mut self.config.idxville_to_index(response.clone())?);
+            if let JsonValue::Array(mut data) = response {
+                raw.append(&mut data);
+            }
+        }
+        Ok((result, JsonValue::Array(raw)))
+    }
+
+    /// Same as [`index_city`](#method.index_city) for a single city. Only indices of that city
+    /// are returned (previous day, current day and next day)
+    ///
+    /// # Arguments
+    ///
+    /// * `city` - INSEE city code
+    ///
+    /// # Errors
+    ///
+    /// See [`index_city`](#method.index_city)
+    pub fn index_city_one(&self, city: &str) -> Result<Vec<Index>, RParifError> {
+        let insee: Insee = city.parse()?;
+        Ok(self
+            .index_city(std::iter::once(&insee))?
+            .into_iter()
+            .filter(|index| index.insee().as_ref() == Some(&insee))
+            .collect())
+    }
+
+    /// Same as [`index_city`](#method.index_city) but indices are grouped by date, which is
+    /// convenient to display previous day, current day and next day
+    ///
+    /// # Arguments
+    ///
+    /// * `cities` - List of INSEE city code
+    ///
+    /// # Errors
+    ///
+    /// See [`index_city`](#method.index_city)
+    pub fn index_city_all_days<I, S>(
+        &self,
+        cities: I,
+    ) -> Result<BTreeMap<NaiveDate, Vec<Index>>, RParifError>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        self.index_city(cities).map(group_by_date)
+    }
+
+    /// Same as [`index_city`](#method.index_city) but using postal codes instead of INSEE codes.
+    /// Postal codes are resolved with `postal_codes` (see [postal](../postal/index.html) module);
+    /// a postal code shared by several communes is expanded into all of them.
+    ///
+    /// **Note** : [PostalCodes::embedded](../postal/struct.PostalCodes.html#method.embedded) only
+    /// knows Paris arrondissements, load the full La Poste dataset with
+    /// [PostalCodes::from_csv](../postal/struct.PostalCodes.html#method.from_csv) for other communes.
+    ///
+    /// # Arguments
+    ///
+    /// * `cities` - List of postal codes (`Vec`, slice or iterator of `&str` or `String`)
+    ///
+    /// * `postal_codes` - Table used to convert postal codes into INSEE codes
+    ///
+    /// # Errors
+    ///
+    /// * [RParifError::UnknownPostalCode](../error/enum.RParifError.html#variant.UnknownPostalCode)
+    ///   if one of the postal codes can't be resolved
+    ///
+    /// * Same errors as [`index_city`](#method.index_city)
+    #[cfg(feature = "postal")]
+    pub fn index_city_by_postal<I, S>(
+        &self,
+        cities: I,
+        postal_codes: &PostalCodes,
+    ) -> Result<Vec<Index>, RParifError>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let cities: Vec<String> = cities.into_iter().map(|c| c.as_ref().to_string()).collect();
+        let insee = postal_codes.resolve(&cities)?;
+        debug!(target: LOG_TARGET, "Postal codes {:?} resolved into {:?}", cities, insee);
+        self.index_city(insee)
+    }
+
+    /// Get pollution indices of cities (`idxville` endpoint) along with alerts (`episode`
+    /// endpoint) of their pollutants, see [join_episodes](../objects/fn.join_episodes.html)
+    ///
+    /// # Arguments
+    ///
+    /// * `cities` - List of INSEE city code
+    ///
+    /// # Errors
+    ///
+    /// Same errors as [`index_city`](#method.index_city) and [`episode`](#method.episode)
+    pub fn index_city_with_episodes<I, S>(&self, cities: I) -> Result<Vec<IndexAlert>, RParifError>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let indices = self.index_city(cities)?;
+        let episodes = self.episode()?;
+        Ok(join_episodes(indices, &episodes))
+    }
+
+    /// List pollution alert for previous day, current day and next day using `episode` endpoint
+    ///
+    /// # Errors
+    ///
+    /// * [RParifError::RequestError](../error/enum.RParifError.html#variant.RequestError) when reqwest lib
+    ///   fails. It contains the underlying error.
+    ///
+    /// * [RParifError::CallError](../error/enum.RParifError.html#variant.CallError) when HTTP status is
+    ///   other than 2XX. It contains the URL called, the HTTP status and the body response
+    ///
+    /// * [RParifError::RateLimited](../error/enum.RParifError.html#variant.RateLimited) when HTTP status
+    ///   is 429. It contains the `Retry-After` delay, if any
+    ///
+    /// * [RParifError::ResponseTooLarge](../error/enum.RParifError.html#variant.ResponseTooLarge) when
+    ///   the body exceeds the maximum size
+    ///
+    /// * [RParifError::UnexpectedDate](../error/enum.RParifError.html#variant.UnexpectedDate)
+    ///   if the date can't be parsed
+    ///
+    /// * [RParifError::MissingJsonKey](../error/enum.RParifError.html#variant.MissingJsonKey) if missing
+    ///   JSON is missing `type` or `niveau`
+    ///
+    /// * [RParifError::UnkownEnumValue](../error/enum.RParifError.html#variant.UnkownEnumValue) if  `type`,
+    ///   `niveau` or one of `criteres` can't be converted into corresponding enum variant
+    ///
+    /// * [RParifError::WrongJsonType](../error/enum.RParifError.html#variant.WrongJsonType) if `type`,
+    ///   `niveau` or one of `criteres` is not a string or if JSON is not as expected
+    ///
+    /// * [RParifError::JsonError](../error/enum.RParifError.html#variant.JsonError) if response isn't a well
+    ///   formed JSON
+    pub fn episode(&self) -> Result<Vec<Episode>, RParifError> {
+        self.episode_raw().map(|(result, _)| result)
+    }
+
+    /// Same as [`episode`](#method.episode) but only keep episodes with at least one pollutant
+    /// at [Level::Info](../objects/enum.Level.html#variant.Info) or
+    /// [Level::Alert](../objects/enum.Level.html#variant.Alert), e.g. for notifications
+    ///
+    /// # Errors
+    ///
+    /// See [`episode`](#method.episode)
+    pub fn active_alerts(&self) -> Result<Vec<Episode>, RParifError> {
+        Ok(self
+            .episode()?
+            .into_iter()
+            .filter(|episode| episode.max_level() >= Level::Info)
+            .collect())
+    }
+
+    /// Same as [`episode`](#method.episode) but only for the given cities. `episode` endpoint
+    /// has no city parameter and its alerts cover the whole Île-de-France region, so filtering
+    /// is done client-side : alerts are returned if one of the cities is in Île-de-France
+    /// (see [Insee::is_ile_de_france](../objects/struct.Insee.html#method.is_ile_de_france)),
+    /// otherwise the result is empty and AirParif isn't called.
+    ///
+    /// # Arguments
+    ///
+    /// * `cities` - List of INSEE city code
+    ///
+    /// # Errors
+    ///
+    /// * [RParifError::EmptyCityList](../error/enum.RParifError.html#variant.EmptyCityList) when `cities`
+    ///   is empty
+    ///
+    /// * [RParifError::InvalidInsee](../error/enum.RParifError.html#variant.InvalidInsee) when one of
+    ///   `cities` isn't a valid INSEE code (see [Insee](../objects/struct.Insee.html))
+    ///
+    /// * Same errors as [`episode`](#method.episode)
+    pub fn episode_for_cities(&self, cities: &[&str]) -> Result<Vec<Episode>, RParifError> {
+        if !covered_by_airparif(cities)? {
+            debug!(target: LOG_TARGET, "No city of {:?} in Île-de-France", cities);
+            return Ok(Vec::new());
+        }
+        self.episode()
+    }
+
+    /// Same as [`episode`](#method.episode) but also return the JSON response as sent by AirParif
+    ///
+    /// # Errors
+    ///
+    /// See [`episode`](#method.episode)
+    pub fn episode_raw(&self) -> Result<(Vec<Episode>, JsonValue), RParifError> {
+        debug!(target: LOG_TARGET, "Querying episode endpoint");
+        let response: JsonValue = self.execute_query(self.episode_url().as_str())?;
+        Ok((self.config.episode_to_episode(response.clone())?, response))
+    }
+}
+
+#[cfg(all(test, feature = "blocking"))]
+mod test {
+    use chrono::{Datelike, Duration};
+    use httpmock::Method::GET;
+    use httpmock::MockServer;
+    use reqwest::Url;
+
+    use crate::objects::{Criteria, Level, Type};
+    use crate::transport::MemoryTransport;
+
+    use super::*;
+
+    /// Clock that always returns the same time
+    #[derive(Debug)]
+    struct FixedClock(DateTime<Utc>);
+
+    impl Clock for FixedClock {
+        fn now(&self) -> DateTime<Utc> {
+            self.0
+        }
+
+        fn sleep(&self, _: std::time::Duration) {}
+    }
+
+    #[test]
+    // Return yesterday
+    fn test_convert_json_to_date_hier() {
+        let json = JsonValue::String("hier".to_string());
+        let result = json_to_date(&json, paris_date(Utc::now()));
+
+        let expected = paris_date(Utc::now());
+        let expected = expected.checked_sub_signed(Duration::days(1));
+        assert!(result.is_ok(), "Convert JSON 'hier' fails");
+        assert_eq!(result.ok(), expected);
+    }
+
+    #[test]
+    // Return today
+    fn test_convert_json_to_date_jour() {
+        let json = JsonValue::String("jour".to_string());
+        let result = json_to_date(&json, paris_date(Utc::now()));
+
+        let expected = paris_date(Utc::now());
+        assert!(result.is_ok(), "Convert JSON 'jour' fails");
+        assert_eq!(result.ok(), Some(expected));
+    }
+
+    #[test]
+    // Return tomorrow
+    fn test_convert_json_to_date_demain() {
+        let json = JsonValue::String("demain".to_string());
+        let result = json_to_date(&json, paris_date(Utc::now()));
+
+        let expected = paris_date(Utc::now());
+        let expected = expected.checked_add_signed(Duration::days(1));
+        assert!(result.is_ok(), "Convert JSON 'demain' fails");
+        assert_eq!(result.ok(), expected);
+    }
+
+    #[test]
+    // Return an error because date isn't 'hier', 'jour' or 'demain'
+    fn test_convert_json_to_date_wrong() {
+        let json = JsonValue::String("wrong string".to_string());
+        let result = json_to_date(&json, paris_date(Utc::now()));
+
+        assert!(result.is_err(), "Convert JSON 'wrong string' should fails");
+        match result.err().unwrap() {
+            RParifError::UnexpectedDate(s) => assert_eq!(s, "\"wrong string\"".to_string()),
+            _ => panic!("Wrong error"),
+        }
+    }
+
+    #[test]
+    // Return yesterday day
+    fn test_convert_string_to_date_hier() {
+        let result = string_to_date("hier", paris_date(Utc::now()));
+
+        let expected = paris_date(Utc::now());
+        let expected = expected.checked_sub_signed(Duration::days(1));
+        assert!(result.is_ok(), "Convert string 'hier' fails");
+        assert_eq!(result.ok(), expected);
+    }
+
+    #[test]
+    // Return today
+    fn test_convert_string_to_date_jour() {
+        let result = string_to_date("jour", paris_date(Utc::now()));
+
+        let expected = paris_date(Utc::now());
+        assert!(result.is_ok(), "Convert string 'jour' fails");
+        assert_eq!(result.ok(), Some(expected));
+    }
+
+    #[test]
+    // Return tomorrow
+    fn test_convert_string_to_date_demain() {
+        let result = string_to_date("demain", paris_date(Utc::now()));
+
+        let expected = paris_date(Utc::now());
+        let expected = expected.checked_add_signed(Duration::days(1));
+        assert!(result.is_ok(), "Convert string 'demain' fails");
+        assert_eq!(result.ok(), expected);
+    }
+
+    #[test]
+    // Return an error because date isn't 'hier', 'jour' or 'demain'
+    fn test_convert_string_to_date_wrong() {
+        let result = string_to_date("wrong string", paris_date(Utc::now()));
+
+        assert!(
+            result.is_err(),
+            "Convert string 'wrong string' should fails"
+        );
+        match result.err().unwrap() {
+            RParifError::UnexpectedDate(s) => assert_eq!(s, "wrong string".to_string()),
+            _ => panic!("Wrong error"),
+        }
+    }
+
+    #[test]
+    // Get number value from JSON return an error because
+    // the JSON key doesn't exists
+    fn test_get_number_value_no_key() {
+        let data = object! {
+            wrong_key: 12
+        };
+
+        let result = number_value("key", &data);
+
+        assert!(result.is_err());
+        match result.err().unwrap() {
+            RParifError::MissingJsonKey { key, json } => {
+                assert_eq!(key, "key".to_string());
+                assert_eq!(json, "{\"wrong_key\":12}")
+            }
+            _ => panic!("Wrong error"),
+        }
+    }
+
+    #[test]
+    // Get number value from JSON return an error because
+    // the value is not a string
+    fn test_get_number_value_wrong_type() {
+        let data = object! {
+            key: "wrong type"
+        };
+
+        let result = number_value("key", &data);
+
+        assert!(result.is_err());
+        match result.err().unwrap() {
+            RParifError::WrongJsonType { expected, json, .. } => {
+                assert_eq!(expected, "number".to_string());
+                assert_eq!(json, "\"wrong type\"")
+            }
+            _ => panic!("Wrong error"),
+        }
+    }
+
+    #[test]
+    // Get number value from JSON ok
+    fn test_get_number_value() {
+        let data = object! {
+            key: 12
+        };
+
+        let result = number_value("key", &data);
+        assert!(result.is_ok());
+        assert_eq!(result.ok(), Some(12));
+    }
+
+    #[test]
+    // Get string value from JSON return an error because
+    // the JSON key doesn't exists
+    fn test_get_string_value_no_key() {
+        let data = object! {
+            wrong_key: "data"
+        };
+
+        let result = string_value("key", &data);
+
+        assert!(result.is_err());
+        match result.err().unwrap() {
+            RParifError::MissingJsonKey { key, json } => {
+                assert_eq!(key, "key".to_string());
+                assert_eq!(json, "{\"wrong_key\":\"data\"}")
+            }
+            _ => panic!("Wrong error"),
+        }
+    }
+
+    #[test]
+    // Get string value from JSON return an error because
+    // the value is not a string
+    fn test_get_string_value_wrong_type() {
+        let data = object! {
+            key: 12
+        };
+
+        let result = string_value("key", &data);
+
+        assert!(result.is_err());
+        match result.err().unwrap() {
+            RParifError::WrongJsonType { expected, json, .. } => {
+                assert_eq!(expected, "string".to_string());
+                assert_eq!(json, "12")
+            }
+            _ => panic!("Wrong error"),
+        }
+    }
+
+    #[test]
+    // Get string value from JSON ok
+    fn test_get_string_value() {
+        let data = object! {
+            key: "data"
+        };
+
+        let result = string_value("key", &data);
+        assert!(result.is_ok());
+        assert_eq!(result.ok(), Some("data"));
+    }
+
+    #[test]
+    // Call return an error because reqwest return an error
+    fn test_execute_query_reqwest_error() {
+        let client = RParifClient::new("api-key");
+        let result = client.execute_query("http://localhost:5001");
+
+        assert!(result.is_err());
+        match result.err().unwrap() {
+            RParifError::RequestError(err) => {
+                assert_eq!(
+                    err.url(),
+                    Some(&Url::parse("http://localhost:5001").unwrap())
+                );
+            }
+            _ => panic!("Wrong error"),
+        }
+    }
+
+    #[test]
+    // Call return an error because the result is not a well formed JSON
+    fn test_execute_query_reqwest_not_json() {
+        let server = MockServer::start();
+        let _search_mock = server.mock(|when, then| {
+            when.method(GET).path("/path");
+            then.status(200).body("this is not a json");
+        });
+
+        let client = RParifClient::new("api-key");
+        let result = client.execute_query(&server.url("/path"));
+
+        assert!(result.is_err());
+        match result.err().unwrap() {
+            RParifError::JsonError(err) => {
+                assert_eq!(format!("{}", err), "Unexpected character: h at (1:2)")
+            }
+            _ => panic!("Wrong error"),
+        }
+    }
+
+    #[test]
+    // Call return an error because status is different from 2xx
+    fn test_execute_query_reqwest_wrong_status() {
+        let server = MockServer::start();
+        let _search_mock = server.mock(|when, then| {
+            when.method(GET).path("/path");
+            then.status(300).body("{\"data\":0}");
+        });
+
+        let client = RParifClient::new("api-key");
+        let result = client.execute_query(&server.url("/path"));
+
+        assert!(result.is_err());
+        match result.err().unwrap() {
+            RParifError::CallError {
+                url,
+                body,
+                status,
+                message,
+                retry_after,
+            } => {
+                assert_eq!(url, server.url("/path"));
+                assert_eq!(body, "{\"data\":0}".to_string());
+                assert_eq!(status, 300);
+                assert_eq!(message, None);
+                assert_eq!(retry_after, None);
+            }
+            _ => panic!("Wrong error"),
+        }
+    }
+
+    #[test]
+    // Call OK
+    fn test_execute_query_reqwest() {
+        let server = MockServer::start();
         let _search_mock = server.mock(|when, then| {
+            when.method(GET).path("/path");
+            then.status(200).body("{\"data\":0}");
+        });
+
+        let client = RParifClient::new("api-key");
+        let result = client.execute_query(&server.url("/path"));
+
+        assert_eq!(
+            result.ok(),
+            Some(object! {
+                data: 0
+            })
+        )
+    }
+
+    #[test]
+    fn test_index_to_index() {
+        let client = RParifClient::new("api-key");
+        let data = array![{
+               date: "jour",
+               indice: 35,
+               url_carte: "a"
+        }];
+
+        let result = client.config.index_to_index(data);
+
+        assert!(result.is_ok());
+        assert_eq!(
+            result.ok(),
+            Some(vec![Index::new(
+                paris_date(Utc::now()),
+                Some("a".to_string()),
+                vec!["global".to_string()],
+                35,
+                None,
+            )])
+        );
+    }
+
+    #[test]
+    // A numeric url_carte must not be stored as a string
+    fn test_for_each_index() {
+        let data = json::parse(
+            r#"[{"date":"hier","indice":35,"url_carte":"a"},{"date":"jour","indice":50,"url_carte":""}]"#,
+        )
+        .unwrap();
+        let today = paris_date(Utc::now());
+        let mut visited: Vec<(NaiveDate, String, u32, Option<String>)> = Vec::new();
+
+        for_each_index(&data, |date, pollutant, index, url| {
+            visited.push((
+                date,
+                pollutant.to_string(),
+                index,
+                url.map(|u| u.to_string()),
+            ))
+        })
+        .unwrap();
+
+        assert_eq!(
+            visited,
+            vec![
+                (
+                    today - Duration::days(1),
+                    "global".to_string(),
+                    35,
+                    Some("a".to_string())
+                ),
+                (today, "global".to_string(), 50, None),
+            ]
+        );
+        match for_each_index(&JsonValue::Null, |_, _, _, _| {})
+            .err()
+            .unwrap()
+        {
+            RParifError::WrongJsonType { expected, .. } => assert_eq!(expected, "array"),
+            _ => panic!("Wrong error"),
+        }
+    }
+
+    #[test]
+    fn test_index_to_index_url_not_a_string() {
+        let client = RParifClient::new("api-key");
+        let data = array![{
+               date: "jour",
+               indice: 35,
+               url_carte: 12
+        }];
+
+        match client.config.index_to_index(data).err().unwrap() {
+            RParifError::WrongJsonType { expected, json, .. } => {
+                assert_eq!(expected, "string");
+                assert_eq!(json, "12");
+            }
+            _ => panic!("Wrong error"),
+        }
+    }
+
+    #[test]
+    fn test_index_to_index_not_an_array() {
+        let client = RParifClient::new("api-key");
+        let data = object! {
+               date: "jour",
+               indice: 35,
+               url_carte: "a"
+        };
+
+        let result = client.config.index_to_index(data);
+        assert!(result.is_err());
+        match result.err().unwrap() {
+            RParifError::WrongJsonType { expected, json, .. } => {
+                assert_eq!(expected, "array".to_string());
+                assert_eq!(
+                    json,
+                    r#"{"date":"jour","indice":35,"url_carte":"a"}"#.to_string()
+                )
+            }
+            _ => panic!("Wrong error"),
+        };
+    }
+
+    #[test]
+    fn test_index_day_to_index() {
+        let client = RParifClient::new("api-key");
+        let data = object! {
+            date: "31/12/2019",
+            global: object! {
+                indice: 35,
+                url_carte: "a"
+            },
+            o3: object! {
+                indice: 40,
+                url_carte: "b"
+            },
+        };
+        let expected = Some(vec![
+            Index::new(
+                NaiveDate::from_ymd_opt(2019, 12, 31).unwrap(),
+                Some("a".to_string()),
+                vec!["global".to_string()],
+                35,
+                None,
+            ),
+            Index::new(
+                NaiveDate::from_ymd_opt(2019, 12, 31).unwrap(),
+                Some("b".to_string()),
+                vec!["o3".to_string()],
+                40,
+                None,
+            ),
+        ]);
+
+        let result = client.config.index_day_to_index(data, None);
+        assert!(result.is_ok());
+        assert_eq!(result.ok(), expected);
+    }
+
+    #[test]
+    // Comment applies to every pollutant, empty strings are converted into None
+    fn test_index_day_to_index_comment() {
+        let client = RParifClient::new("api-key");
+        let data = object! {
+            date: "31/12/2019",
+            commentaire: "Pic de pollution",
+            global: object! {
+                indice: 35,
+                url_carte: ""
+            },
+        };
+        let expected = Some(vec![Index::new(
+            NaiveDate::from_ymd_opt(2019, 12, 31).unwrap(),
+            None,
+            vec!["global".to_string()],
+            35,
+            None,
+        )
+        .with_comment(Some("Pic de pollution".to_string()))]);
+
+        let result = client.config.index_day_to_index(data, None).ok();
+        assert_eq!(result, expected);
+        let result = result.unwrap();
+        assert_eq!(result[0].comment(), Some("Pic de pollution".to_string()));
+        assert_eq!(result[0].map_url(), None);
+    }
+
+    #[test]
+    fn test_optional_string() {
+        let data = object! {
+            empty: "",
+            number: 12,
+            value: "a"
+        };
+
+        assert_eq!(optional_string("missing", &data).ok(), Some(None));
+        assert_eq!(optional_string("empty", &data).ok(), Some(None));
+        assert_eq!(
+            optional_string("value", &data).ok(),
+            Some(Some("a".to_string()))
+        );
+        match optional_string("number", &data).err().unwrap() {
+            RParifError::WrongJsonType { expected, json, .. } => {
+                assert_eq!(expected, "string");
+                assert_eq!(json, "12");
+            }
+            _ => panic!("Wrong error"),
+        }
+    }
+
+    #[test]
+    fn test_idxville_to_index() {
+        let client = RParifClient::new("api-key");
+        let data = array![
+            {
+                ninsee: "75101",
+                hier: {
+                    indice: 25,
+                    polluants: ["no2", "pm10"]
+                },
+                jour: {
+                    indice: 50,
+                    polluants: ["pm10"]
+                },
+                demain: {
+                    indice: 36,
+                    polluants: ["o3"]
+                },
+            },
+            {
+                ninsee: "94028",
+                hier: {
+                    indice: 100,
+                    polluants: ["no2"]
+                },
+                jour: {
+                    indice: 40,
+                    polluants: ["o3"]
+                },
+                demain: {
+                    indice: 95,
+                    polluants: ["o3","no2","pm10"]
+                },
+            }
+        ];
+
+        let result = client.config.idxville_to_index(data);
+
+        let today = paris_date(Utc::now());
+        let yesterday = today.checked_sub_signed(Duration::days(1)).unwrap();
+        let tomorrow = today.checked_add_signed(Duration::days(1)).unwrap();
+        let expected = vec![
+            Index::new(
+                NaiveDate::from_ymd_opt(yesterday.year(), yesterday.month(), yesterday.day())
+                    .unwrap(),
+                None,
+                vec!["no2".to_string(), "pm10".to_string()],
+                25,
+                "75101".parse().ok(),
+            ),
+            Index::new(
+                NaiveDate::from_ymd_opt(today.year(), today.month(), today.day()).unwrap(),
+                None,
+                vec!["pm10".to_string()],
+                50,
+                "75101".parse().ok(),
+            ),
+            Index::new(
+                NaiveDate::from_ymd_opt(tomorrow.year(), tomorrow.month(), tomorrow.day()).unwrap(),
+                None,
+                vec!["o3".to_string()],
+                36,
+                "75101".parse().ok(),
+            ),
+            Index::new(
+                NaiveDate::from_ymd_opt(yesterday.year(), yesterday.month(), yesterday.day())
+                    .unwrap(),
+                None,
+                vec!["no2".to_string()],
+                100,
+                "94028".parse().ok(),
+            ),
+            Index::new(
+                NaiveDate::from_ymd_opt(today.year(), today.month(), today.day()).unwrap(),
+                None,
+                vec!["o3".to_string()],
+                40,
+                "94028".parse().ok(),
+            ),
+            Index::new(
+                NaiveDate::from_ymd_opt(tomorrow.year(), tomorrow.month(), tomorrow.day()).unwrap(),
+                None,
+                vec!["o3".to_string(), "no2".to_string(), "pm10".to_string()],
+                95,
+                "94028".parse().ok(),
+            ),
+        ];
+
+        assert!(result.is_ok());
+        assert_eq!(result.ok(), Some(expected));
+    }
+
+    #[test]
+    fn test_idxville_to_index_not_an_array() {
+        let client = RParifClient::new("api-key");
+        let data = object! {
+               date: "jour",
+               indice: 35,
+               url_carte: "a"
+        };
+
+        let result = client.config.idxville_to_index(data);
+        assert!(result.is_err());
+        match result.err().unwrap() {
+            RParifError::WrongJsonType { expected, json, .. } => {
+                assert_eq!(expected, "array".to_string());
+                assert_eq!(
+                    json,
+                    r#"{"date":"jour","indice":35,"url_carte":"a"}"#.to_string()
+                )
+            }
+            _ => panic!("Wrong error"),
+        };
+    }
+
+    #[test]
+    fn test_index_day_to_index_not_an_object() {
+        let client = RParifClient::new("api-key");
+        let data = array![object! {
+            date: "18/05/2020",
+            global: object! { indice: 35 }
+        }];
+
+        let result = client.config.index_day_to_index(data, None);
+        match result.err().unwrap() {
+            RParifError::WrongJsonType { expected, json, .. } => {
+                assert_eq!(expected, "object".to_string());
+                assert_eq!(
+                    json,
+                    r#"[{"date":"18/05/2020","global":{"indice":35}}]"#.to_string()
+                )
+            }
+            _ => panic!("Wrong error"),
+        };
+    }
+
+    #[test]
+    fn test_episode_to_episode() {
+        let client = RParifClient::new("api-key");
+        let data = array![
+             {
+                date: "hier",
+                detail: "",
+                o3: {
+                    type: "constate",
+                    niveau: "info",
+                    criteres: ["km","pop"]
+                },
+                so2: {
+                    type: "constate",
+                    niveau: "alerte",
+                    criteres: ["pop"]
+
+                }
+             },
+             {
+                date: "jour",
+                detail: "Il est conseillé d'éviter les déplacements en Ile de France",
+                no2: {
+                    type: "constate",
+                    niveau: "normal",
+                    criteres: ["km"]
+                },
+                so2: {
+                    type: "constate",
+                    niveau: "alerte",
+                    criteres: ["km"]
+                }
+             },
+             {
+                date: "demain",
+                detail:""
+             }
+        ];
+
+        let result = client.config.episode_to_episode(data);
+
+        let today = paris_date(Utc::now());
+        let yesterday = today.checked_sub_signed(Duration::days(1)).unwrap();
+        let tomorrow = today.checked_add_signed(Duration::days(1)).unwrap();
+        let mut expected = Vec::new();
+        let mut episode = Episode::new(
+            NaiveDate::from_ymd_opt(yesterday.year(), yesterday.month(), yesterday.day()).unwrap(),
+            None,
+        );
+        episode.add(
+            "o3".to_string(),
+            Type::Observed,
+            Level::Info,
+            vec![Criteria::Area, Criteria::Population],
+        );
+        episode.add(
+            "so2".to_string(),
+            Type::Observed,
+            Level::Alert,
+            vec![Criteria::Population],
+        );
+        expected.push(episode);
+        let mut episode = Episode::new(
+            NaiveDate::from_ymd_opt(today.year(), today.month(), today.day()).unwrap(),
+            Some("Il est conseillé d'éviter les déplacements en Ile de France".to_string()),
+        );
+        episode.add(
+            "no2".to_string(),
+            Type::Observed,
+            Level::Normal,
+            vec![Criteria::Area],
+        );
+        episode.add(
+            "so2".to_string(),
+            Type::Observed,
+            Level::Alert,
+            vec![Criteria::Area],
+        );
+        expected.push(episode);
+        let episode = Episode::new(
+            NaiveDate::from_ymd_opt(tomorrow.year(), tomorrow.month(), tomorrow.day()).unwrap(),
+            None,
+        );
+        expected.push(episode);
+
+        assert!(result.is_ok());
+        assert_eq!(result.ok(), Some(expected));
+    }
+
+    #[test]
+    fn test_episode_to_episode_not_an_array() {
+        let client = RParifClient::new("api-key");
+        let data = object! {
+        date: "jour",
+        indice: 35,
+        url_carte: "a"
+        };
+
+        let result = client.config.episode_to_episode(data);
+        assert!(result.is_err());
+        match result.err().unwrap() {
+            RParifError::WrongJsonType { expected, json, .. } => {
+                assert_eq!(expected, "array".to_string());
+                assert_eq!(
+                    json,
+                    r#"{"date":"jour","indice":35,"url_carte":"a"}"#.to_string()
+                )
+            }
+            _ => panic!("Wrong error"),
+        };
+    }
+
+    #[test]
+    #[cfg(feature = "postal")]
+    fn test_index_city_by_postal_unknown() {
+        let client = RParifClient::new("api-key");
+        let result = client.index_city_by_postal(vec!["75001", "99999"], &PostalCodes::embedded());
+
+        assert!(result.is_err());
+        match result.err().unwrap() {
+            RParifError::UnknownPostalCode(code) => assert_eq!(code, "99999"),
+            _ => panic!("Wrong error"),
+        }
+    }
+
+    #[test]
+    // Unknown criteria must be an error instead of a panic
+    fn test_episode_to_episode_unknown_criteria() {
+        let client = RParifClient::new("api-key");
+        let data = array![
+             {
+                date: "jour",
+                detail: "",
+                o3: {
+                    type: "constate",
+                    niveau: "info",
+                    criteres: ["km", "wrong"]
+                }
+             }
+        ];
+
+        let result = client.config.episode_to_episode(data);
+        assert!(result.is_err());
+        match result.err().unwrap() {
+            RParifError::UnkownEnumValue(token) => assert_eq!(token, "wrong".to_string()),
+            _ => panic!("Wrong error"),
+        };
+    }
+
+    #[test]
+    fn test_episode_to_episode_criteria_not_a_string() {
+        let client = RParifClient::new("api-key");
+        let data = array![
+             {
+                date: "jour",
+                detail: "",
+                o3: {
+                    type: "constate",
+                    niveau: "info",
+                    criteres: [12]
+                }
+             }
+        ];
+
+        let result = client.config.episode_to_episode(data);
+        assert!(result.is_err());
+        match result.err().unwrap() {
+            RParifError::WrongJsonType { expected, json, .. } => {
+                assert_eq!(expected, "string".to_string());
+                assert_eq!(json, "12".to_string());
+            }
+            _ => panic!("Wrong error"),
+        };
+    }
+
+    #[test]
+    // Lenient client keeps unknown values
+    fn test_episode_to_episode_lenient() {
+        let client = RParifClient::builder("api-key")
+            .lenient(true)
+            .build()
+            .unwrap();
+        let data = array![
+             {
+                date: "jour",
+                detail: "",
+                o3: {
+                    type: "estime",
+                    niveau: "urgence",
+                    criteres: ["km", "duree"]
+                }
+             }
+        ];
+
+        let result = client.config.episode_to_episode(data);
+
+        let mut episode = Episode::new(paris_date(Utc::now()), None);
+        episode.add(
+            "o3".to_string(),
+            Type::Unknown("estime".to_string()),
+            Level::Unknown("urgence".to_string()),
+            vec![Criteria::Area, Criteria::Unknown("duree".to_string())],
+        );
+        assert_eq!(result.ok(), Some(vec![episode]));
+    }
+
+    #[test]
+    fn test_execute_query_user_agent() {
+        let server = MockServer::start();
+        let default_mock = server.mock(|when, then| {
             when.method(GET)
-                .path("/path");
-            then.status(300)
-                .body("{\"data\":0}");
+                .path("/default")
+                .header("user-agent", DEFAULT_USER_AGENT);
+            then.status(200).body("{\"data\":0}");
+        });
+        let custom_mock = server.mock(|when, then| {
+            when.method(GET)
+                .path("/custom")
+                .header("user-agent", "my-app/1.0");
+            then.status(200).body("{\"data\":0}");
+        });
+
+        let client = RParifClient::new("api-key");
+        let result = client.execute_query(&server.url("/default"));
+        assert!(result.is_ok());
+        default_mock.assert();
+
+        let client = RParifClient::builder("api-key")
+            .user_agent("my-app/1.0")
+            .build()
+            .unwrap();
+        let result = client.execute_query(&server.url("/custom"));
+        assert!(result.is_ok());
+        custom_mock.assert();
+    }
+
+    #[test]
+    fn test_request_url() {
+        let client = RParifClient::new_test("secret", "http://localhost");
+
+        let url = client.index_url();
+        assert_eq!(url.as_str(), "http://localhost/indice?key=secret");
+        assert_eq!(format!("{}", url), "http://localhost/indice?key=REDACTED");
+        assert!(!format!("{:?}", url).contains("secret"));
+
+        let url = client.index_day_url(Day::Tomorrow);
+        assert_eq!(
+            url.as_str(),
+            "http://localhost/indiceJour?date=demain&key=secret"
+        );
+        assert_eq!(
+            format!("{}", url),
+            "http://localhost/indiceJour?date=demain&key=REDACTED"
+        );
+
+        let url = client.index_city_url(vec!["75101", "94028"]);
+        assert_eq!(
+            url.as_str(),
+            "http://localhost/idxville?villes=75101,94028&key=secret"
+        );
+        assert_eq!(
+            format!("{}", url),
+            "http://localhost/idxville?villes=75101,94028&key=REDACTED"
+        );
+
+        let url = client.episode_url();
+        assert_eq!(url.as_str(), "http://localhost/episode?key=secret");
+    }
+
+    #[test]
+    fn test_encode_query_parameters() {
+        assert_eq!(encode_query_value("75101"), "75101");
+        assert_eq!(encode_query_value("a-b.c_d~"), "a-b.c_d~");
+        assert_eq!(encode_query_value("a b&c=d,é"), "a%20b%26c%3Dd%2C%C3%A9");
+
+        let client = RParifClient::new_test("se cret&x", "http://localhost");
+        assert_eq!(
+            client.index_city_url(["75 101", "94028&"]).as_str(),
+            "http://localhost/idxville?villes=75%20101,94028%26&key=se%20cret%26x"
+        );
+        assert_eq!(
+            client.index_day_url(Day::Today).as_str(),
+            "http://localhost/indiceJour?date=jour&key=se%20cret%26x"
+        );
+        assert_eq!(
+            client.episode_url().to_string(),
+            "http://localhost/episode?key=REDACTED"
+        );
+    }
+
+    #[test]
+    fn test_request_url_query() {
+        let config = RParifClientBuilder::new("secret")
+            .base_url("http://localhost")
+            .config;
+
+        assert_eq!(
+            config.request_url("indice", &[], false).as_str(),
+            "http://localhost/indice"
+        );
+        assert_eq!(
+            config.request_url("indice", &[], true).as_str(),
+            "http://localhost/indice?key=secret"
+        );
+        assert_eq!(
+            config
+                .request_url(
+                    "/idxville",
+                    &[("villes", &["75101", "94028"]), ("date", &["jour"])],
+                    true
+                )
+                .as_str(),
+            "http://localhost/idxville?villes=75101,94028&date=jour&key=secret"
+        );
+        assert_eq!(
+            config
+                .request_url("indiceJour", &[("date", &["a/b"])], false)
+                .as_str(),
+            "http://localhost/indiceJour?date=a%2Fb"
+        );
+    }
+
+    #[test]
+    fn test_extra_query_param() {
+        let client = RParifClient::builder("secret")
+            .base_url("http://localhost")
+            .send_key_when_optional(false)
+            .extra_query_param("lang", "en")
+            .extra_query_param("format", "a b")
+            .build()
+            .unwrap();
+
+        assert_eq!(
+            client.index_url().as_str(),
+            "http://localhost/indice?lang=en&format=a%20b"
+        );
+        assert_eq!(
+            client.index_day_url(Day::Today).as_str(),
+            "http://localhost/indiceJour?date=jour&lang=en&format=a%20b"
+        );
+        assert_eq!(
+            client.index_city_url(["75101"]).as_str(),
+            "http://localhost/idxville?villes=75101&lang=en&format=a%20b&key=secret"
+        );
+        assert_eq!(
+            client.episode_url().as_str(),
+            "http://localhost/episode?lang=en&format=a%20b&key=secret"
+        );
+    }
+
+    #[test]
+    fn test_index_city_url_iterator() {
+        let client = RParifClient::new_test("secret", "http://localhost");
+        let expected = "http://localhost/idxville?villes=75101,94028&key=secret";
+
+        let cities = vec!["75101".to_string(), "94028".to_string()];
+        assert_eq!(client.index_city_url(&cities).as_str(), expected);
+        assert_eq!(client.index_city_url(["75101", "94028"]).as_str(), expected);
+        assert_eq!(
+            client
+                .index_city_url(cities.iter().map(|c| c.as_str()))
+                .as_str(),
+            expected
+        );
+    }
+
+    #[test]
+    fn test_index_city_empty() {
+        let client = RParifClient::new_test("api-key", "http://localhost:5001");
+        let result = client.index_city(Vec::<&str>::new());
+
+        assert!(result.is_err());
+        match result.err().unwrap() {
+            RParifError::EmptyCityList => {}
+            _ => panic!("Wrong error"),
+        }
+    }
+
+    #[test]
+    fn test_current_index() {
+        let server = MockServer::start();
+        let _mock = server.mock(|when, then| {
+            when.method(GET).path("/indice");
+            then.status(200).body(
+                "[{\"date\":\"hier\",\"indice\":20},{\"date\":\"jour\",\"indice\":35},{\"date\":\"demain\",\"indice\":50}]",
+            );
         });
 
-        let client = RParifClient::new("api-key");
-        let result = client.execute_query(&server.url("/path"));
+        let base_url = server.base_url();
+        let client = RParifClient::new_test("api-key", &base_url);
+        let result = client.current_index().unwrap();
 
-        assert!(result.is_err());
-        match result.err().unwrap() {
-            RParifError::CallError { url, body, status } => {
-                assert_eq!(url, server.url("/path"));
-                assert_eq!(body, "{\"data\":0}".to_string());
-                assert_eq!(status, 300);
+        assert_eq!(result.date(), paris_date(Utc::now()));
+        assert_eq!(result.index(), 35);
+    }
+
+    #[test]
+    fn test_current_index_not_found() {
+        let server = MockServer::start();
+        let _mock = server.mock(|when, then| {
+            when.method(GET).path("/indice");
+            then.status(200).body("[{\"date\":\"hier\",\"indice\":20}]");
+        });
+
+        let base_url = server.base_url();
+        let client = RParifClient::new_test("api-key", &base_url);
+
+        match client.current_index().err().unwrap() {
+            RParifError::NotFound(_) => (),
+            _ => panic!("Wrong error"),
+        }
+    }
+
+    #[test]
+    // Late in the evening (UTC), it's already tomorrow in Paris
+    fn test_current_index_paris_date() {
+        let transport = MemoryTransport::new().with_response(
+            "indice",
+            200,
+            r#"[{"date":"hier","indice":20},{"date":"jour","indice":35},{"date":"demain","indice":50}]"#,
+        );
+        let now = NaiveDate::from_ymd_opt(2020, 5, 17)
+            .unwrap()
+            .and_hms_opt(22, 30, 0)
+            .unwrap()
+            .and_utc();
+        let client = RParifClient::builder("api-key")
+            .clock(FixedClock(now))
+            .build_with_transport(transport);
+
+        let result = client.current_index().unwrap();
+
+        assert_eq!(result.date(), NaiveDate::from_ymd_opt(2020, 5, 18).unwrap());
+        assert_eq!(result.index(), 35);
+    }
+
+    #[test]
+    // Invalid codes are rejected before calling AirParif
+    fn test_index_city_invalid_insee() {
+        let client = RParifClient::new_test("api-key", "http://localhost:5001");
+
+        match client.index_city(vec!["75101", "Paris"]).err().unwrap() {
+            RParifError::InvalidInsee(code) => assert_eq!(code, "Paris"),
+            _ => panic!("Wrong error"),
+        }
+    }
+
+    #[test]
+    // Relative dates are resolved against the reference date
+    fn test_reference_date() {
+        let reference = NaiveDate::from_ymd_opt(2019, 12, 31).unwrap();
+        let client = RParifClient::builder("api-key")
+            .with_reference_date(reference)
+            .build()
+            .unwrap();
+        let data = array![
+            { date: "hier", indice: 20 },
+            { date: "jour", indice: 35 },
+            { date: "demain", indice: 50 }
+        ];
+
+        let dates: Vec<NaiveDate> = client
+            .config
+            .index_to_index(data)
+            .unwrap()
+            .iter()
+            .map(|i| i.date())
+            .collect();
+
+        assert_eq!(
+            dates,
+            vec![
+                NaiveDate::from_ymd_opt(2019, 12, 30).unwrap(),
+                reference,
+                NaiveDate::from_ymd_opt(2020, 1, 1).unwrap()
+            ]
+        );
+    }
+
+    #[test]
+    fn test_pool_settings_and_shared_client() {
+        let server = MockServer::start();
+        let mock = server.mock(|when, then| {
+            when.method(GET).path("/indice");
+            then.status(200).body("[{\"date\":\"jour\",\"indice\":35}]");
+        });
+
+        let base_url = server.base_url();
+        let pooled = RParifClient::builder("api-key")
+            .base_url(&base_url)
+            .pool_idle_timeout(std::time::Duration::from_secs(10))
+            .pool_max_idle_per_host(0)
+            .build()
+            .unwrap();
+        assert!(pooled.index().is_ok());
+        for _ in 0..2 {
+            let shared = RParifClient::builder("api-key")
+                .base_url(&base_url)
+                .shared_client(true)
+                .build()
+                .unwrap();
+            assert!(shared.index().is_ok());
+        }
+
+        mock.assert_hits(3);
+    }
+
+    #[test]
+    // Only requested pollutants are kept, whatever the case
+    fn test_index_day_filtered() {
+        let server = MockServer::start();
+        let mock = server.mock(|when, then| {
+            when.method(GET).path("/indiceJour");
+            then.status(200).body(
+                r#"{"date":"31/12/2019","global":{"indice":35},"o3":{"indice":40},"NO2":{"indice":20}}"#,
+            );
+        });
+
+        let base_url = server.base_url();
+        let client = RParifClient::new_test("api-key", &base_url);
+        let result = client
+            .index_day_filtered(Day::Today, &["o3", "no2"])
+            .unwrap();
+
+        mock.assert_hits(1);
+        let pollutants: Vec<Vec<String>> = result.iter().map(|i| i.pollutants()).collect();
+        assert_eq!(
+            pollutants,
+            vec![vec!["o3".to_string()], vec!["no2".to_string()]]
+        );
+    }
+
+    #[test]
+    // AirParif error message is extracted from `erreur` or `message` members
+    fn test_read_response_message() {
+        for body in &[
+            r#"{"erreur":"Clé invalide"}"#,
+            r#"{"message":"Clé invalide"}"#,
+        ] {
+            match read_response("http://localhost/indice", 403, false, None, body) {
+                Err(RParifError::CallError { message, .. }) => {
+                    assert_eq!(message, Some("Clé invalide".to_string()))
+                }
+                _ => panic!("Wrong error"),
             }
+        }
+        match read_response("http://localhost/indice", 500, false, None, "[]") {
+            Err(RParifError::CallError { message, .. }) => assert_eq!(message, None),
             _ => panic!("Wrong error"),
         }
     }
 
     #[test]
-    // Call OK
-    fn test_execute_query_reqwest() {
+    // Only indices of the requested city are kept
+    fn test_index_city_one() {
         let server = MockServer::start();
-        let _search_mock = server.mock(|when, then| {
+        let mock = server.mock(|when, then| {
             when.method(GET)
-                .path("/path");
-            then.status(200)
-                .body("{\"data\":0}");
+                .path("/idxville")
+                .query_param("villes", "75101");
+            then.status(200).body(
+                r#"[{"ninsee":"75101","jour":{"indice":50,"polluants":["pm10"]}},{"ninsee":"75102","jour":{"indice":40,"polluants":["o3"]}}]"#,
+            );
         });
 
-        let client = RParifClient::new("api-key");
-        let result = client.execute_query(&server.url("/path"));
+        let base_url = server.base_url();
+        let client = RParifClient::new_test("api-key", &base_url);
+        let result = client.index_city_one("75101").unwrap();
+
+        mock.assert_hits(1);
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].insee(), "75101".parse().ok());
+
+        match client.index_city_one("7510").err().unwrap() {
+            RParifError::InvalidInsee(code) => assert_eq!(code, "7510"),
+            _ => panic!("Wrong error"),
+        }
+    }
 
+    #[test]
+    fn test_api_version() {
+        let client = RParifClient::new("secret");
         assert_eq!(
-            result.ok(),
-            Some(object! {
-                data: 0
-            })
-        )
+            client.episode_url().as_str(),
+            "https://www.airparif.asso.fr/services/api/1.1/episode?key=secret"
+        );
+
+        let client = RParifClient::builder("secret").api_version("2.0").build();
+        assert_eq!(
+            client.unwrap().episode_url().as_str(),
+            "https://www.airparif.asso.fr/services/api/2.0/episode?key=secret"
+        );
+
+        let client = RParifClient::builder("secret")
+            .base_url("http://localhost/")
+            .api_version("2.0")
+            .build();
+        assert_eq!(
+            client.unwrap().index_url().as_str(),
+            "http://localhost/2.0/indice?key=secret"
+        );
     }
 
     #[test]
-    fn test_index_to_index() {
+    // Retry-After is captured on 503, even if body isn't JSON
+    fn test_execute_query_retry_after() {
+        let server = MockServer::start();
+        let _mock = server.mock(|when, then| {
+            when.method(GET).path("/path");
+            then.status(503)
+                .header("Retry-After", "120")
+                .body("Service unavailable");
+        });
+
         let client = RParifClient::new("api-key");
-        let data = array![{
-               date: "jour",
-               indice: 35,
-               url_carte: "a"
-        }];
+        match client.execute_query(&server.url("/path")).err().unwrap() {
+            RParifError::CallError {
+                body, retry_after, ..
+            } => {
+                assert_eq!(body, "Service unavailable");
+                assert_eq!(retry_after, Some(std::time::Duration::from_secs(120)));
+            }
+            _ => panic!("Wrong error"),
+        }
+    }
 
-        let result = client.index_to_index(data);
+    #[test]
+    fn test_execute_query_rate_limited() {
+        let server = MockServer::start();
+        let _mock = server.mock(|when, then| {
+            when.method(GET).path("/path");
+            then.status(429)
+                .header("Retry-After", "30")
+                .body("Too many requests");
+        });
+
+        let client = RParifClient::new("api-key");
+        match client.execute_query(&server.url("/path")).err().unwrap() {
+            RParifError::RateLimited { retry_after } => {
+                assert_eq!(retry_after, Some(std::time::Duration::from_secs(30)))
+            }
+            _ => panic!("Wrong error"),
+        }
+    }
+
+    #[test]
+    fn test_parse_retry_after() {
+        let now = DateTime::parse_from_rfc2822("Wed, 21 Oct 2015 07:28:00 GMT")
+            .unwrap()
+            .with_timezone(&Utc);
 
-        assert!(result.is_ok());
         assert_eq!(
-            result.ok(),
-            Some(vec![Index::new(
-                Utc::today().naive_utc(),
-                Some("a".to_string()),
-                vec!["global".to_string()],
-                35,
-                None,
-            )])
+            parse_retry_after(" 30 ", now),
+            Some(std::time::Duration::from_secs(30))
+        );
+        assert_eq!(
+            parse_retry_after("Wed, 21 Oct 2015 07:30:00 GMT", now),
+            Some(std::time::Duration::from_secs(120))
         );
+        assert_eq!(
+            parse_retry_after("Wed, 21 Oct 2015 07:00:00 GMT", now),
+            Some(std::time::Duration::from_secs(0))
+        );
+        assert_eq!(parse_retry_after("soon", now), None);
     }
 
     #[test]
-    fn test_index_to_index_not_an_array() {
-        let client = RParifClient::new("api-key");
-        let data = object! {
-               date: "jour",
-               indice: 35,
-               url_carte: "a"
-        };
+    fn test_send_key_when_optional() {
+        let client = RParifClient::builder("secret")
+            .base_url("http://localhost")
+            .send_key_when_optional(false)
+            .build()
+            .unwrap();
 
-        let result = client.index_to_index(data);
-        assert!(result.is_err());
-        match result.err().unwrap() {
-            RParifError::WrongJsonType { expected, json } => {
-                assert_eq!(expected, "array".to_string());
-                assert_eq!(
-                    json,
-                    r#"{"date":"jour","indice":35,"url_carte":"a"}"#.to_string()
-                )
+        assert_eq!(client.index_url().as_str(), "http://localhost/indice");
+        assert_eq!(
+            client.index_day_url(Day::Today).as_str(),
+            "http://localhost/indiceJour?date=jour"
+        );
+        assert_eq!(
+            client.episode_url().as_str(),
+            "http://localhost/episode?key=secret"
+        );
+    }
+
+    #[test]
+    // Parsing and error handling don't depend on the transport
+    fn test_build_with_transport() {
+        let transport = MemoryTransport::new()
+            .with_response("episode", 200, r#"[{"date":"jour","detail":""}]"#)
+            .with_response("indice", 403, r#"{"erreur":"Clé invalide"}"#);
+        let client = RParifClient::builder("api-key").build_with_transport(transport);
+
+        assert_eq!(client.episode().unwrap().len(), 1);
+        match client.index().err().unwrap() {
+            RParifError::CallError {
+                status, message, ..
+            } => {
+                assert_eq!(status, 403);
+                assert_eq!(message, Some("Clé invalide".to_string()));
             }
             _ => panic!("Wrong error"),
-        };
+        }
+        match client.index_day(Day::Today).err().unwrap() {
+            RParifError::CallError { status, .. } => assert_eq!(status, 404),
+            _ => panic!("Wrong error"),
+        }
     }
 
     #[test]
-    fn test_index_day_to_index() {
+    fn test_index_city_with_episodes() {
+        let transport = MemoryTransport::new()
+            .with_response(
+                "idxville",
+                200,
+                r#"[{"ninsee":"75101","jour":{"indice":80,"polluants":["o3"]},"demain":{"indice":40,"polluants":["o3"]}}]"#,
+            )
+            .with_response(
+                "episode",
+                200,
+                r#"[{"date":"jour","detail":"","o3":{"type":"constate","niveau":"alerte","criteres":["pop"]}}]"#,
+            );
+        let client = RParifClient::builder("api-key").build_with_transport(transport);
+
+        let result = client.index_city_with_episodes(vec!["75101"]).unwrap();
+
+        assert_eq!(result.len(), 2);
+        assert_eq!(result[0].episode().map(|e| e.level()), Some(Level::Alert));
+        assert_eq!(result[1].episode(), None);
+    }
+
+    #[test]
+    // Pollutants with the same index as global one drive it
+    fn test_index_day_to_index_drives_global() {
         let client = RParifClient::new("api-key");
         let data = object! {
             date: "31/12/2019",
-            global: object! {
-                indice: 35,
-                url_carte: "a"
-            },
-            o3: object! {
-                indice: 40,
-                url_carte: "b"
-            },
+            global: object! { indice: 40 },
+            o3: object! { indice: 40 },
+            no2: object! { indice: 25 },
         };
-        let expected = Some(vec![
-            Index::new(
-                NaiveDate::from_ymd(2019, 12, 31),
-                Some("a".to_string()),
-                vec!["global".to_string()],
-                35,
-                None,
-            ),
-            Index::new(
-                NaiveDate::from_ymd(2019, 12, 31),
-                Some("b".to_string()),
-                vec!["o3".to_string()],
-                40,
-                None,
-            ),
-        ]);
 
-        let result = client.index_day_to_index(data);
-        assert!(result.is_ok());
-        assert_eq!(result.ok(), expected);
+        let result = client.config.index_day_to_index(data, None).unwrap();
+        let drivers: Vec<(String, bool)> = result
+            .iter()
+            .map(|i| (i.pollutants()[0].clone(), i.drives_global()))
+            .collect();
+
+        assert_eq!(
+            drivers,
+            vec![
+                ("global".to_string(), false),
+                ("o3".to_string(), true),
+                ("no2".to_string(), false),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_functions() {
+        let today = paris_date(Utc::now());
+
+        let result = parse_indices(r#"[{"date":"jour","indice":35}]"#).unwrap();
+        assert_eq!(result[0].date(), today);
+        let result = parse_index_day(r#"{"date":"31/12/2019","o3":{"indice":40}}"#).unwrap();
+        assert_eq!(result[0].index(), 40);
+        let result =
+            parse_city_indices(r#"[{"ninsee":"75101","jour":{"indice":50,"polluants":["pm10"]}}]"#)
+                .unwrap();
+        assert_eq!(result[0].insee(), "75101".parse().ok());
+        let result =
+            parse_episodes(r#"[{"date":"jour","so2":{"type":"constate","niveau":"alerte"}}]"#)
+                .unwrap();
+        assert_eq!(result[0].pollutant("so2").unwrap().level(), Level::Alert);
+
+        match parse_episodes("not json").err().unwrap() {
+            RParifError::JsonError(_) => {}
+            _ => panic!("Wrong error"),
+        }
+    }
+
+    #[test]
+    fn test_use_env_proxy() {
+        let server = MockServer::start();
+        let mock = server.mock(|when, then| {
+            when.method(GET).path("/indice");
+            then.status(200).body("[{\"date\":\"jour\",\"indice\":35}]");
+        });
+
+        let base_url = server.base_url();
+        let client = RParifClient::builder("api-key")
+            .base_url(&base_url)
+            .use_env_proxy(false)
+            .build()
+            .unwrap();
+
+        assert!(client.index().is_ok());
+        mock.assert_hits(1);
+    }
+
+    #[test]
+    fn test_metrics() {
+        let transport = MemoryTransport::new()
+            .with_response("indice", 200, r#"[{"date":"jour","indice":35}]"#)
+            .with_response("episode", 200, "not json");
+        let metrics = Arc::new(Mutex::new(Vec::new()));
+        let recorded = metrics.clone();
+        let client = RParifClient::builder("api-key")
+            .metrics(move |m| recorded.lock().unwrap().push(m.clone()))
+            .build_with_transport(transport);
+
+        assert!(client.index().is_ok());
+        assert!(client.episode().is_err());
+        assert!(client.index_day(Day::Today).is_err());
+
+        let metrics = metrics.lock().unwrap();
+        let summary: Vec<(&str, Option<u16>, bool)> = metrics
+            .iter()
+            .map(|m| (m.endpoint(), m.status(), m.success()))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("indice", Some(200), true),
+                ("episode", Some(200), false),
+                ("indiceJour", Some(404), false),
+            ]
+        );
+    }
+
+    #[test]
+    // On 304 status, the last response is used
+    fn test_conditional_requests() {
+        let server = MockServer::start();
+        let mut full = server.mock(|when, then| {
+            when.method(GET).path("/episode");
+            then.status(200)
+                .header("Last-Modified", "Mon, 18 May 2020 08:00:00 GMT")
+                .body("[{\"date\":\"jour\",\"o3\":{\"type\":\"prevu\",\"niveau\":\"info\"}}]");
+        });
+
+        let base_url = server.base_url();
+        let client = RParifClient::builder("api-key")
+            .base_url(&base_url)
+            .conditional_requests(true)
+            .build()
+            .unwrap();
+        let first = client.episode().unwrap();
+        full.assert_hits(1);
+        full.delete();
+
+        let not_modified = server.mock(|when, then| {
+            when.method(GET)
+                .path("/episode")
+                .header("If-Modified-Since", "Mon, 18 May 2020 08:00:00 GMT");
+            then.status(304);
+        });
+        let second = client.episode().unwrap();
+        not_modified.assert_hits(1);
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn test_only_pollutants() {
+        let transport = MemoryTransport::new()
+            .with_response(
+                "indiceJour",
+                200,
+                r#"{"date":"18/05/2020","global":{"indice":50},"no2":{"indice":50},"o3":{"indice":30},"pm10":{"indice":20}}"#,
+            )
+            .with_response(
+                "idxville",
+                200,
+                r#"[{"ninsee":"75101","hier":{"indice":25,"polluants":["no2"]},"jour":{"indice":50,"polluants":["PM10","no2"]}}]"#,
+            );
+        let client = RParifClient::builder("api-key")
+            .only_pollutants(&["PM10", "o3"])
+            .build_with_transport(transport);
+
+        let result = client.index_day(Day::Today).unwrap();
+        let names: Vec<Vec<String>> = result.iter().map(|i| i.pollutants()).collect();
+        assert_eq!(names, vec![vec!["o3"], vec!["pm10"]]);
+        let result = client
+            .index_day_filtered(Day::Today, &["o3", "no2"])
+            .unwrap();
+        assert_eq!(result.len(), 1);
+
+        let result = client.index_city(vec!["75101"]).unwrap();
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].index(), 50);
+    }
+
+    #[test]
+    fn test_episode_for_cities() {
+        let transport = MemoryTransport::new().with_response(
+            "episode",
+            200,
+            r#"[{"date":"jour","o3":{"type":"prevu","niveau":"info"}}]"#,
+        );
+        let client = RParifClient::builder("api-key").build_with_transport(transport);
+
+        assert_eq!(
+            client
+                .episode_for_cities(&["2A004", "94028"])
+                .unwrap()
+                .len(),
+            1
+        );
+        assert!(client.episode_for_cities(&["2A004"]).unwrap().is_empty());
+        match client.episode_for_cities(&[]).err().unwrap() {
+            RParifError::EmptyCityList => {}
+            _ => panic!("Wrong error"),
+        }
+        match client.episode_for_cities(&["751"]).err().unwrap() {
+            RParifError::InvalidInsee(code) => assert_eq!(code, "751"),
+            _ => panic!("Wrong error"),
+        }
+    }
+
+    #[test]
+    fn test_endpoint_paths() {
+        let client = RParifClient::builder("api-key")
+            .base_url("http://gateway/airparif")
+            .endpoint_paths(EndpointPaths {
+                indice: "/air/global".to_string(),
+                episode: "air/alerts".to_string(),
+                ..EndpointPaths::default()
+            })
+            .build()
+            .unwrap();
+
+        assert_eq!(
+            client.index_url().as_str(),
+            "http://gateway/airparif/air/global?key=api-key"
+        );
+        assert_eq!(
+            client.index_day_url(Day::Today).as_str(),
+            "http://gateway/airparif/indiceJour?date=jour&key=api-key"
+        );
+        assert_eq!(
+            client.index_city_url(vec!["75101"]).as_str(),
+            "http://gateway/airparif/idxville?villes=75101&key=api-key"
+        );
+        assert_eq!(
+            client.episode_url().as_str(),
+            "http://gateway/airparif/air/alerts?key=api-key"
+        );
+    }
+
+    #[test]
+    fn test_environment() {
+        let client = RParifClient::builder("api-key")
+            .environment(Environment::Custom("http://localhost:5000".to_string()))
+            .build()
+            .unwrap();
+        assert_eq!(
+            client.episode_url().as_str(),
+            "http://localhost:5000/episode?key=api-key"
+        );
+
+        let client = RParifClient::builder("api-key")
+            .base_url("http://localhost:5000")
+            .environment(Environment::Production)
+            .build()
+            .unwrap();
+        assert_eq!(
+            client.episode_url().as_str(),
+            format!("{}/1.1/episode?key=api-key", PROD_BASE_URL)
+        );
+    }
+
+    #[test]
+    fn test_max_response_bytes() {
+        let server = MockServer::start();
+        let mock = server.mock(|when, then| {
+            when.method(GET).path("/indice");
+            then.status(200).body("[{\"date\":\"jour\",\"indice\":35}]");
+        });
+
+        let base_url = server.base_url();
+        let client = RParifClient::builder("secret")
+            .base_url(&base_url)
+            .max_response_bytes(10)
+            .build()
+            .unwrap();
+        match client.index().err().unwrap() {
+            RParifError::ResponseTooLarge { url, limit } => {
+                assert_eq!(limit, 10);
+                assert!(!url.contains("secret"));
+            }
+            _ => panic!("Wrong error"),
+        }
+
+        let client = RParifClient::builder("secret")
+            .base_url(&base_url)
+            .max_response_bytes(29)
+            .build()
+            .unwrap();
+        assert_eq!(client.index().unwrap()[0].index(), 35);
+        mock.assert_hits(2);
     }
 
     #[test]
-    fn test_idxville_to_index() {
-        let client = RParifClient::new("api-key");
-        let data = array![
-            {
-                ninsee: "75101",
-                hier: {
-                    indice: 25,
-                    polluants: ["no2", "pm10"]
-                },
-                jour: {
-                    indice: 50,
-                    polluants: ["pm10"]
-                },
-                demain: {
-                    indice: 36,
-                    polluants: ["o3"]
-                },
-            },
-            {
-                ninsee: "94028",
-                hier: {
-                    indice: 100,
-                    polluants: ["no2"]
-                },
-                jour: {
-                    indice: 40,
-                    polluants: ["o3"]
-                },
-                demain: {
-                    indice: 95,
-                    polluants: ["o3","no2","pm10"]
-                },
-            }
-        ];
+    fn test_body_buffer() {
+        let mut buffer = BodyBuffer::new(4, None);
+        assert!(buffer.push(b"ab"));
+        assert!(buffer.push(b"cd"));
+        assert!(!buffer.push(b"e"));
+        assert!(buffer.into_text("http://host/indice").is_err());
 
-        let result = client.idxville_to_index(data);
+        let mut buffer = BodyBuffer::new(4, Some(5));
+        assert!(buffer.exceeded());
+        assert!(!buffer.push(b"a"));
+        let buffer = BodyBuffer::new(4, Some(4));
+        assert_eq!(buffer.into_text("http://host/indice").unwrap(), "");
+    }
 
-        let today = Utc::today();
-        let yesterday = today.checked_sub_signed(Duration::days(1)).unwrap();
-        let tomorrow = today.checked_add_signed(Duration::days(1)).unwrap();
-        let expected = vec![
-            Index::new(
-                NaiveDate::from_ymd_opt(yesterday.year(), yesterday.month(), yesterday.day())
-                    .unwrap(),
-                None,
-                vec!["no2".to_string(), "pm10".to_string()],
-                25,
-                Some("75101".to_string()),
-            ),
-            Index::new(
-                NaiveDate::from_ymd_opt(today.year(), today.month(), today.day()).unwrap(),
-                None,
-                vec!["pm10".to_string()],
-                50,
-                Some("75101".to_string()),
-            ),
-            Index::new(
-                NaiveDate::from_ymd_opt(tomorrow.year(), tomorrow.month(), tomorrow.day()).unwrap(),
-                None,
-                vec!["o3".to_string()],
-                36,
-                Some("75101".to_string()),
-            ),
-            Index::new(
-                NaiveDate::from_ymd_opt(yesterday.year(), yesterday.month(), yesterday.day())
-                    .unwrap(),
-                None,
-                vec!["no2".to_string()],
-                100,
-                Some("94028".to_string()),
-            ),
-            Index::new(
-                NaiveDate::from_ymd_opt(today.year(), today.month(), today.day()).unwrap(),
-                None,
-                vec!["o3".to_string()],
-                40,
-                Some("94028".to_string()),
-            ),
-            Index::new(
-                NaiveDate::from_ymd_opt(tomorrow.year(), tomorrow.month(), tomorrow.day()).unwrap(),
-                None,
-                vec!["o3".to_string(), "no2".to_string(), "pm10".to_string()],
-                95,
-                Some("94028".to_string()),
-            ),
-        ];
+    #[test]
+    fn test_new_owned() {
+        let server = MockServer::start();
+        let mock = server.mock(|when, then| {
+            when.method(GET)
+                .path("/episode")
+                .query_param("key", "api-key");
+            then.status(200).body("[]");
+        });
 
-        assert!(result.is_ok());
-        assert_eq!(result.ok(), Some(expected));
+        let client: Arc<RParifClient<'static>> = Arc::new(
+            RParifClientBuilder::new_owned(String::from("api-key"))
+                .base_url(&server.base_url())
+                .build()
+                .unwrap(),
+        );
+        let handles: Vec<_> = (0..2)
+            .map(|_| {
+                let client = Arc::clone(&client);
+                std::thread::spawn(move || client.episode())
+            })
+            .collect();
+        for handle in handles {
+            assert_eq!(handle.join().unwrap().unwrap(), vec![]);
+        }
+        mock.assert_hits(2);
     }
 
     #[test]
-    fn test_idxville_to_index_not_an_array() {
-        let client = RParifClient::new("api-key");
-        let data = object! {
-               date: "jour",
-               indice: 35,
-               url_carte: "a"
+    fn test_from_file() {
+        let directory =
+            std::env::temp_dir().join(format!("rparif-from-file-{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        let file = |name: &str, content: &str| {
+            let path = directory.join(name);
+            fs::write(&path, content).unwrap();
+            path
         };
 
-        let result = client.idxville_to_index(data);
-        assert!(result.is_err());
-        match result.err().unwrap() {
-            RParifError::WrongJsonType { expected, json } => {
-                assert_eq!(expected, "array".to_string());
-                assert_eq!(
-                    json,
-                    r#"{"date":"jour","indice":35,"url_carte":"a"}"#.to_string()
-                )
-            }
+        let indices = index_from_file(file("indice.json", r#"[{"date":"jour","indice":35}]"#));
+        assert_eq!(indices.unwrap()[0].pollutants(), vec!["global"]);
+        let indices = index_from_file(file(
+            "indiceJour.json",
+            r#"{"date":"18/05/2020","global":{"indice":35},"o3":{"indice":30}}"#,
+        ))
+        .unwrap();
+        assert_eq!(indices.len(), 2);
+        assert_eq!(
+            indices[0].date(),
+            NaiveDate::from_ymd_opt(2020, 5, 18).unwrap()
+        );
+        let indices = index_from_file(file(
+            "idxville.json",
+            r#"[{"ninsee":"75101","jour":{"indice":50,"polluants":["o3"]}}]"#,
+        ))
+        .unwrap();
+        assert_eq!(indices[0].insee(), "75101".parse().ok());
+        let episodes = episodes_from_file(file(
+            "episode.json",
+            r#"[{"date":"jour","o3":{"type":"prevu","niveau":"info"}}]"#,
+        ))
+        .unwrap();
+        assert_eq!(episodes[0].pollutant("o3").unwrap().level(), Level::Info);
+
+        match index_from_file(directory.join("missing.json"))
+            .err()
+            .unwrap()
+        {
+            RParifError::IoError(err) => assert_eq!(err.kind(), std::io::ErrorKind::NotFound),
             _ => panic!("Wrong error"),
-        };
+        }
+        match episodes_from_file(file("broken.json", "[")).err().unwrap() {
+            RParifError::JsonError(_) => {}
+            _ => panic!("Wrong error"),
+        }
+        fs::remove_dir_all(&directory).unwrap();
     }
 
     #[test]
-    fn test_episode_to_episode() {
-        let client = RParifClient::new("api-key");
-        let data = array![
-             {
-                date: "hier",
-                detail: "",
-                o3: {
-                    type: "constate",
-                    niveau: "info",
-                    criteres: ["km","pop"]
-                },
-                so2: {
-                    type: "constate",
-                    niveau: "alerte",
-                    criteres: ["pop"]
+    fn test_active_alerts() {
+        let transport = MemoryTransport::new().with_response(
+            "episode",
+            200,
+            r#"[{"date":"hier","o3":{"type":"constate","niveau":"normal"}},
+            {"date":"jour","o3":{"type":"constate","niveau":"normal"},"no2":{"type":"prevu","niveau":"info"}},
+            {"date":"demain"}]"#,
+        );
+        let client = RParifClient::builder("api-key").build_with_transport(transport);
 
+        assert_eq!(client.episode().unwrap().len(), 3);
+        let result = client.active_alerts().unwrap();
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].date(), paris_date(Utc::now()));
+        assert_eq!(result[0].max_level(), Level::Info);
+    }
+
+    #[test]
+    fn test_parse_day_date() {
+        let expected = NaiveDate::from_ymd_opt(2020, 5, 18).unwrap();
+        for value in [
+            "18/05/2020",
+            "2020-05-18",
+            "18/05/2020 14:30",
+            "18/05/2020 14:30:15",
+            "2020-05-18 14:30:15",
+            "2020-05-18T14:30:15",
+        ]
+        .iter()
+        {
+            assert_eq!(parse_day_date(value).unwrap(), expected, "{}", value);
+        }
+
+        for value in ["18-05-2020", "2020/05/18", "31/02/2020", ""].iter() {
+            let err = parse_day_date(value).err().unwrap();
+            match &err {
+                RParifError::DateParseError {
+                    value: v, formats, ..
+                } => {
+                    assert_eq!(v, value);
+                    assert_eq!(formats.len(), DAY_DATE_FORMATS.len());
+                    assert_eq!(formats[0], "%d/%m/%Y");
                 }
-             },
-             {
-                date: "jour",
-                detail: "Il est conseillé d'éviter les déplacements en Ile de France",
-                no2: {
-                    type: "constate",
-                    niveau: "normal",
-                    criteres: ["km"]
-                },
-                so2: {
-                    type: "constate",
-                    niveau: "alerte",
-                    criteres: ["km"]
-                }
-             },
-             {
-                date: "demain",
-                detail:""
-             }
-        ];
+                _ => panic!("Wrong error for {}", value),
+            }
+            let message = format!("{}", err);
+            for format in DAY_DATE_FORMATS.iter() {
+                assert!(message.contains(format), "{} not in {}", format, message);
+            }
+        }
 
-        let result = client.episode_to_episode(data);
+        let client = RParifClient::new_test("api-key", "http://localhost");
+        let json = json::parse(r#"{"date":"2020-05-18","global":{"indice":35}}"#).unwrap();
+        let result = client.config.index_day_to_index(json, None).unwrap();
+        assert_eq!(result[0].date(), expected);
+    }
 
-        let today = Utc::today();
-        let yesterday = today.checked_sub_signed(Duration::days(1)).unwrap();
-        let tomorrow = today.checked_add_signed(Duration::days(1)).unwrap();
-        let mut expected = Vec::new();
-        let mut episode = Episode::new(
-            NaiveDate::from_ymd_opt(yesterday.year(), yesterday.month(), yesterday.day()).unwrap(),
-            None,
+    #[test]
+    fn test_default_user_agent_version() {
+        assert_eq!(DEFAULT_USER_AGENT, format!("r-parif/{}", crate::version()));
+    }
+
+    #[test]
+    // Conversions report the path of the value with the wrong type
+    fn test_wrong_json_type_path() {
+        let path = |result: Result<Vec<Index>, RParifError>| match result.err().unwrap() {
+            RParifError::WrongJsonType { path, .. } => path,
+            _ => panic!("Wrong error"),
+        };
+
+        assert_eq!(
+            path(parse_indices(
+                r#"[{"date":"hier","indice":35},{"date":"jour","indice":"a"}]"#
+            )),
+            Some("[1].indice".to_string())
         );
-        episode.add(
-            "o3".to_string(),
-            Type::Observed,
-            Level::Info,
-            vec![Criteria::Area, Criteria::Population],
+        assert_eq!(
+            path(parse_index_day(
+                r#"{"date":"18/05/2020","o3":{"indice":35,"url_carte":1}}"#
+            )),
+            Some("o3.url_carte".to_string())
         );
-        episode.add(
-            "so2".to_string(),
-            Type::Observed,
-            Level::Alert,
-            vec![Criteria::Population],
+        assert_eq!(
+            path(parse_city_indices(
+                r#"[{"ninsee":"75101","jour":{"indice":true}}]"#
+            )),
+            Some("[0].jour.indice".to_string())
         );
-        expected.push(episode);
-        let mut episode = Episode::new(
-            NaiveDate::from_ymd_opt(today.year(), today.month(), today.day()).unwrap(),
-            Some("Il est conseillé d'éviter les déplacements en Ile de France".to_string()),
+        // Must not panic when a pollutant isn't a string
+        assert_eq!(
+            path(parse_city_indices(
+                r#"[{"ninsee":"75101","jour":{"indice":35}},{"ninsee":"75102","hier":{"indice":35,"polluants":["o3",null]}}]"#
+            )),
+            Some("[1].hier.polluants[1]".to_string())
         );
-        episode.add(
-            "no2".to_string(),
-            Type::Observed,
-            Level::Normal,
-            vec![Criteria::Area],
+        assert_eq!(path(parse_city_indices(r#"{"ninsee":"75101"}"#)), None);
+        match parse_episodes(
+            r#"[{"date":"jour"},{"date":"jour","o3":{"type":"prevu","niveau":"info","criteres":["km",2]}}]"#,
+        )
+        .err()
+        .unwrap()
+        {
+            RParifError::WrongJsonType { path, .. } => {
+                assert_eq!(path, Some("[1].o3.criteres[1]".to_string()))
+            }
+            _ => panic!("Wrong error"),
+        }
+    }
+
+    #[test]
+    fn test_timeout() {
+        let server = MockServer::start();
+        let mock = server.mock(|when, then| {
+            when.method(GET).path("/indice");
+            then.status(200)
+                .delay(std::time::Duration::from_millis(500))
+                .body("[{\"date\":\"jour\",\"indice\":35}]");
+        });
+
+        let base_url = server.base_url();
+        let client = RParifClient::builder("api-key")
+            .base_url(&base_url)
+            .connect_timeout(std::time::Duration::from_secs(5))
+            .timeout(std::time::Duration::from_millis(100))
+            .build()
+            .unwrap();
+
+        match client.index().err().unwrap() {
+            RParifError::RequestError(err) => assert!(err.is_timeout()),
+            _ => panic!("Wrong error"),
+        }
+        mock.assert_hits(1);
+    }
+
+    #[test]
+    // Days are matched by date, missing days are None
+    fn test_forecast() {
+        let transport = MemoryTransport::new().with_response(
+            "indice",
+            200,
+            r#"[{"date":"demain","indice":70},{"date":"hier","indice":35}]"#,
         );
-        episode.add(
-            "so2".to_string(),
-            Type::Observed,
-            Level::Alert,
-            vec![Criteria::Area],
+        let client = RParifClient::builder("api-key")
+            .with_reference_date(NaiveDate::from_ymd_opt(2020, 5, 17).unwrap())
+            .build_with_transport(transport);
+
+        let result = client.forecast().unwrap();
+
+        let days: Vec<Day> = result.iter().map(|(day, _)| *day).collect();
+        assert_eq!(days, vec![Day::Yesterday, Day::Today, Day::Tomorrow]);
+        assert_eq!(result[0].1.as_ref().map(|i| i.index()), Some(35));
+        assert_eq!(result[1].1, None);
+        assert_eq!(
+            result[2].1.as_ref().map(|i| i.date()),
+            NaiveDate::from_ymd_opt(2020, 5, 18)
         );
-        expected.push(episode);
-        let episode = Episode::new(
-            NaiveDate::from_ymd_opt(tomorrow.year(), tomorrow.month(), tomorrow.day()).unwrap(),
-            None,
+    }
+
+    #[test]
+    // Days are matched against the date in Paris, not in UTC
+    fn test_forecast_paris_date() {
+        let transport = MemoryTransport::new().with_response(
+            "indice",
+            200,
+            r#"[{"date":"hier","indice":20},{"date":"jour","indice":35},{"date":"demain","indice":50}]"#,
         );
-        expected.push(episode);
+        let now = NaiveDate::from_ymd_opt(2020, 1, 15)
+            .unwrap()
+            .and_hms_opt(23, 30, 0)
+            .unwrap()
+            .and_utc();
+        let client = RParifClient::builder("api-key")
+            .clock(FixedClock(now))
+            .build_with_transport(transport);
 
-        assert!(result.is_ok());
-        assert_eq!(result.ok(), Some(expected));
+        let result = client.forecast().unwrap();
+
+        assert_eq!(
+            result[1].1.as_ref().map(|i| i.date()),
+            NaiveDate::from_ymd_opt(2020, 1, 16)
+        );
+        let indices: Vec<Option<u32>> = result
+            .iter()
+            .map(|(_, index)| index.as_ref().map(|i| i.index()))
+            .collect();
+        assert_eq!(indices, vec![Some(20), Some(35), Some(50)]);
     }
 
     #[test]
-    fn test_episode_to_episode_not_an_array() {
-        let client = RParifClient::new("api-key");
-        let data = object! {
-        date: "jour",
-        indice: 35,
-        url_carte: "a"
-        };
+    // Known pollutants are lowercased, unknown ones are kept as is
+    fn test_normalize_pollutants() {
+        let result =
+            parse_index_day(r#"{"date":"31/12/2019","GLOBAL":{"indice":40},"Pm10":{"indice":40},"CO":{"indice":10}}"#)
+                .unwrap();
+        let pollutants: Vec<String> = result.iter().map(|i| i.pollutants()[0].clone()).collect();
+        assert_eq!(pollutants, vec!["global", "pm10", "CO"]);
+        assert!(result[1].drives_global());
 
-        let result = client.episode_to_episode(data);
-        assert!(result.is_err());
-        match result.err().unwrap() {
-            RParifError::WrongJsonType { expected, json } => {
-                assert_eq!(expected, "array".to_string());
-                assert_eq!(
-                    json,
-                    r#"{"date":"jour","indice":35,"url_carte":"a"}"#.to_string()
-                )
-            }
+        let result = parse_city_indices(
+            r#"[{"ninsee":"75101","jour":{"indice":50,"polluants":["PM10","o3","Benzene"]}}]"#,
+        )
+        .unwrap();
+        assert_eq!(result[0].pollutants(), vec!["pm10", "o3", "Benzene"]);
+
+        let result =
+            parse_episodes(r#"[{"date":"jour","SO2":{"type":"constate","niveau":"alerte"}}]"#)
+                .unwrap();
+        assert!(result[0].pollutant("so2").is_some());
+    }
+
+    #[test]
+    fn test_validate_key() {
+        let client = RParifClient::builder("api-key")
+            .build_with_transport(MemoryTransport::new().with_response("episode", 200, "[]"));
+        assert!(client.validate_key().unwrap());
+
+        let client = RParifClient::builder("api-key").build_with_transport(
+            MemoryTransport::new().with_response("episode", 403, r#"{"erreur":"Clé invalide"}"#),
+        );
+        assert!(!client.validate_key().unwrap());
+
+        let client = RParifClient::builder("api-key")
+            .build_with_transport(MemoryTransport::new().with_response("episode", 500, "{}"));
+        match client.validate_key().err().unwrap() {
+            RParifError::CallError { status, .. } => assert_eq!(status, 500),
             _ => panic!("Wrong error"),
-        };
+        }
     }
 }
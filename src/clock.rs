@@ -0,0 +1,113 @@
+//! Time source of the clients
+//!
+//! Clients get current time through the [Clock](./trait.Clock.html) trait, so that time
+//! dependent behaviors (resolving `jour`, expiring [cached](../cache/index.html) results, ...)
+//! can be tested without real delays by giving another implementation to
+//! [`RParifClientBuilder::clock`](../client/struct.RParifClientBuilder.html#method.clock).
+//!
+//! AirParif's relative dates (`hier`, `jour`, `demain`) refer to the date in Paris, see
+//! [paris_date](./fn.paris_date.html).
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::{DateTime, Datelike, Days, NaiveDate, TimeDelta, Utc};
+
+/// Source of current time, able to wait
+pub trait Clock: fmt::Debug + Send + Sync {
+    /// Return current time
+    fn now(&self) -> DateTime<Utc>;
+
+    /// Block current thread for `duration`
+    ///
+    /// # Arguments
+    ///
+    /// * `duration` - How long to wait
+    fn sleep(&self, duration: Duration);
+}
+
+/// Share a clock, e.g. to keep a handle on a fake clock given to a client
+impl<C: Clock + ?Sized> Clock for Arc<C> {
+    fn now(&self) -> DateTime<Utc> {
+        self.as_ref().now()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        self.as_ref().sleep(duration);
+    }
+}
+
+/// [Clock](./trait.Clock.html) relying on system time, used by default
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        std::thread::sleep(duration);
+    }
+}
+
+/// Return the last Sunday of a month that has 31 days
+///
+/// # Arguments
+///
+/// * `year` - Year
+///
+/// * `month` - Month, March or October
+fn last_sunday(year: i32, month: u32) -> NaiveDate {
+    let last = NaiveDate::from_ymd_opt(year, month, 31).expect("Month has 31 days");
+    last - Days::new(last.weekday().num_days_from_sunday() as u64)
+}
+
+/// Return the date in Paris (`Europe/Paris` time zone) at a given time, which is the date
+/// AirParif's relative dates refer to. Paris is UTC+1, or UTC+2 during daylight saving time
+/// (from the last Sunday of March to the last Sunday of October, changes happening at 01:00 UTC).
+///
+/// # Arguments
+///
+/// * `now` - Time to convert, e.g. [`Clock::now`](./trait.Clock.html#tymethod.now)
+pub fn paris_date(now: DateTime<Utc>) -> NaiveDate {
+    let change = |month: u32| {
+        last_sunday(now.year(), month)
+            .and_hms_opt(1, 0, 0)
+            .expect("01:00 is a valid time")
+            .and_utc()
+    };
+    let offset = if now >= change(3) && now < change(10) {
+        2
+    } else {
+        1
+    };
+    (now + TimeDelta::hours(offset)).date_naive()
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    #[test]
+    fn test_paris_date() {
+        let utc = |y, m, d, h, min| Utc.with_ymd_and_hms(y, m, d, h, min, 0).unwrap();
+        let date = |y, m, d| NaiveDate::from_ymd_opt(y, m, d).unwrap();
+
+        // Winter : UTC+1
+        assert_eq!(paris_date(utc(2020, 1, 15, 22, 59)), date(2020, 1, 15));
+        assert_eq!(paris_date(utc(2020, 1, 15, 23, 0)), date(2020, 1, 16));
+        assert_eq!(paris_date(utc(2020, 12, 31, 23, 30)), date(2021, 1, 1));
+        // Summer : UTC+2
+        assert_eq!(paris_date(utc(2020, 5, 17, 21, 59)), date(2020, 5, 17));
+        assert_eq!(paris_date(utc(2020, 5, 17, 22, 0)), date(2020, 5, 18));
+        // Daylight saving time boundaries in 2020 : March 29th and October 25th
+        assert_eq!(last_sunday(2020, 3), date(2020, 3, 29));
+        assert_eq!(last_sunday(2020, 10), date(2020, 10, 25));
+        assert_eq!(paris_date(utc(2020, 3, 29, 0, 59)), date(2020, 3, 29));
+        assert_eq!(paris_date(utc(2020, 10, 24, 22, 30)), date(2020, 10, 25));
+        assert_eq!(paris_date(utc(2020, 10, 25, 23, 30)), date(2020, 10, 26));
+    }
+}
@@ -1,20 +1,32 @@
 //! rparif errors
 use core::fmt;
 use std::error;
+use std::io;
+use std::time::Duration;
 
 use chrono::ParseError;
 use json::Error as JsonError;
 use reqwest::Error as RequestError;
+use reqwest::Url;
+
+use crate::client::redact_key;
 
 /// Errors that can be raised
 #[derive(Debug)]
 pub enum RParifError {
-    /// Error from reqwest lib
+    /// Error from reqwest lib. API key is replaced by `REDACTED` in its URL
     RequestError(RequestError),
     /// Error from json lib
     JsonError(JsonError),
-    /// Date error from chrono lib
-    DateParseError(ParseError),
+    /// Raised when an absolute date matches none of the accepted formats
+    DateParseError {
+        /// Date that can't be parsed
+        value: String,
+        /// Attempted formats, in chrono's syntax (e.g. `%d/%m/%Y`)
+        formats: Vec<String>,
+        /// Error from chrono lib for the first format
+        error: ParseError,
+    },
     /// String can't be converted into enum value
     /// it contains the wrong token
     UnkownEnumValue(String),
@@ -24,18 +36,30 @@ pub enum RParifError {
         expected: String,
         /// Actual JSON
         json: String,
+        /// Path of the value in the response, e.g. `[2].indice` for `indice` member of the
+        /// third element, `None` for the whole response
+        path: Option<String>,
     },
     /// Raised when string date can't be converted.  
     /// Accepted string are `hier`, `jour` and `demain`
     UnexpectedDate(String),
     /// Raised when the API call return status code other than 2XX
     CallError {
-        /// URL that raise the error
+        /// URL that raise the error, API key is replaced by `REDACTED`
         url: String,
         /// HTTP body of AirParif API error
         body: String,
         /// HTTP status code
         status: u16,
+        /// Error message sent by AirParif (`erreur` or `message` member of the body), if any
+        message: Option<String>,
+        /// Delay requested by `Retry-After` header on 503 status, if any
+        retry_after: Option<Duration>,
+    },
+    /// Raised when the API call return 429 status (too many requests)
+    RateLimited {
+        /// Delay requested by `Retry-After` header, if any
+        retry_after: Option<Duration>,
     },
     /// Raised when key doesn't exists in json response
     MissingJsonKey {
@@ -44,6 +68,27 @@ pub enum RParifError {
         /// Actual JSON
         json: String,
     },
+    /// Raised when a postal code can't be converted into INSEE codes.
+    /// It contains the postal code
+    UnknownPostalCode(String),
+    /// Raised when querying cities without giving any city
+    EmptyCityList,
+    /// Raised when AirParif response doesn't contain the requested data.
+    /// It contains a description of what was looked for
+    NotFound(String),
+    /// Raised when a string isn't a valid INSEE city code.
+    /// It contains the wrong code
+    InvalidInsee(String),
+    /// Raised when the body of a response exceeds the maximum size (see
+    /// [`max_response_bytes`](../client/struct.RParifClientBuilder.html#method.max_response_bytes))
+    ResponseTooLarge {
+        /// URL called, API key is replaced by `REDACTED`
+        url: String,
+        /// Maximum size in bytes
+        limit: usize,
+    },
+    /// Raised when a file can't be read. It contains the underlying error
+    IoError(io::Error),
 }
 
 impl fmt::Display for RParifError {
@@ -53,7 +98,17 @@ impl fmt::Display for RParifError {
         match self {
             RequestError(err) => err.fmt(f),
             JsonError(err) => err.fmt(f),
-            DateParseError(err) => err.fmt(f),
+            DateParseError {
+                value,
+                formats,
+                error,
+            } => write!(
+                f,
+                "Can't parse date {:?} ({}), attempted formats : {}",
+                value,
+                error,
+                formats.join(", ")
+            ),
             UnkownEnumValue(token) => {
                 write!(f, "Error parsing enum valu : unexpected value {}", token)
             }
@@ -66,20 +121,40 @@ impl fmt::Display for RParifError {
             WrongJsonType {
                 ref expected,
                 json: ref actual,
-            } => write!(
-                f,
-                "Unexpected type value in JSON : expected {} but got {} which is not a number",
-                expected, actual
-            ),
+                ref path,
+            } => {
+                write!(
+                    f,
+                    "Unexpected type value in JSON : expected {} but got {} which is not a number",
+                    expected, actual
+                )?;
+                match path {
+                    Some(path) => write!(f, " (path : {})", path),
+                    None => Ok(()),
+                }
+            }
             CallError {
                 ref url,
                 ref body,
                 ref status,
+                ..
             } => write!(
                 f,
                 "Unexpected HTTP response : url={}, status={}, body={:?}, ",
                 url, status, body
             ),
+            RateLimited { retry_after } => match retry_after {
+                Some(delay) => write!(f, "Rate limited : retry after {:?}", delay),
+                None => write!(f, "Rate limited"),
+            },
+            UnknownPostalCode(code) => write!(f, "Unknown postal code {}", code),
+            EmptyCityList => write!(f, "List of cities is empty"),
+            NotFound(what) => write!(f, "Not found in AirParif response : {}", what),
+            InvalidInsee(code) => write!(f, "Invalid INSEE code {}", code),
+            ResponseTooLarge { url, limit } => {
+                write!(f, "Response too large : url={}, limit={} bytes", url, limit)
+            }
+            IoError(err) => err.fmt(f),
         }
     }
 }
@@ -91,19 +166,61 @@ impl error::Error for RParifError {
         match self {
             RequestError(..) => "Error calling HTTP API",
             JsonError(..) => "Error parsing JSON response",
-            DateParseError(..) => "Error parsing date",
+            DateParseError { .. } => "Error parsing date",
             UnkownEnumValue(..) => "Error parsing enum value",
             UnexpectedDate(..) => "Wrong date : expect on of 'hier', 'jour', 'demain'",
             WrongJsonType { .. } => "Unexpected type value in JSON",
             CallError { .. } => "Unexpected HTTP response",
+            RateLimited { .. } => "Rate limited",
             MissingJsonKey { .. } => "Missing key in json",
+            UnknownPostalCode(..) => "Unknown postal code",
+            EmptyCityList => "List of cities is empty",
+            NotFound(..) => "Not found in AirParif response",
+            InvalidInsee(..) => "Invalid INSEE code",
+            ResponseTooLarge { .. } => "Response too large",
+            IoError(..) => "Error reading file",
+        }
+    }
+}
+
+impl RParifError {
+    /// Prepend `segment` to the path of a [WrongJsonType](#variant.WrongJsonType) error, other
+    /// errors are returned as is. Used by conversions when leaving an element of the response
+    ///
+    /// # Arguments
+    ///
+    /// * `segment` - Member name (e.g. `o3`) or array position (e.g. `[2]`) of the element
+    pub(crate) fn at(self, segment: &str) -> RParifError {
+        match self {
+            RParifError::WrongJsonType {
+                expected,
+                json,
+                path,
+            } => {
+                let path = match path {
+                    Some(path) if path.starts_with('[') => format!("{}{}", segment, path),
+                    Some(path) => format!("{}.{}", segment, path),
+                    None => segment.to_string(),
+                };
+                RParifError::WrongJsonType {
+                    expected,
+                    json,
+                    path: Some(path),
+                }
+            }
+            err => err,
         }
     }
 }
 
 #[doc(hidden)]
 impl From<RequestError> for RParifError {
-    fn from(err: RequestError) -> Self {
+    fn from(mut err: RequestError) -> Self {
+        if let Some(url) = err.url_mut() {
+            if let Ok(redacted) = Url::parse(&redact_key(url.as_str())) {
+                *url = redacted;
+            }
+        }
         RParifError::RequestError(err)
     }
 }
@@ -116,8 +233,69 @@ impl From<JsonError> for RParifError {
 }
 
 #[doc(hidden)]
-impl From<ParseError> for RParifError {
-    fn from(err: ParseError) -> Self {
-        RParifError::DateParseError(err)
+impl From<io::Error> for RParifError {
+    fn from(err: io::Error) -> Self {
+        RParifError::IoError(err)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    #[cfg(feature = "blocking")]
+    use crate::client::RParifClient;
+
+    use super::*;
+
+    #[test]
+    fn test_wrong_json_type_path() {
+        let err = RParifError::WrongJsonType {
+            expected: "number".to_string(),
+            json: "\"a\"".to_string(),
+            path: Some("indice".to_string()),
+        };
+        let err = err.at("o3").at("[2]");
+        match &err {
+            RParifError::WrongJsonType { path, .. } => {
+                assert_eq!(path.as_deref(), Some("[2].o3.indice"))
+            }
+            _ => panic!("Wrong error"),
+        }
+        assert!(format!("{}", err).ends_with("(path : [2].o3.indice)"));
+
+        match RParifError::EmptyCityList.at("[0]") {
+            RParifError::EmptyCityList => {}
+            _ => panic!("Wrong error"),
+        }
+    }
+
+    #[test]
+    // API key must not appear when displaying errors
+    fn test_display_redact_key() {
+        let err = RParifError::CallError {
+            url: redact_key("http://localhost/idxville?villes=75101&key=secret"),
+            body: "{}".to_string(),
+            status: 403,
+            message: None,
+            retry_after: None,
+        };
+        let display = format!("{}", err);
+        assert!(!display.contains("secret"));
+        assert!(display.contains("villes=75101&key=REDACTED"));
+        assert!(!format!("{:?}", err).contains("secret"));
+    }
+
+    #[test]
+    #[cfg(feature = "blocking")]
+    // API key must not appear in errors raised by reqwest
+    fn test_request_error_redact_key() {
+        let client = RParifClient::new_test("secret", "http://localhost:5001");
+        let err = client.index().err().unwrap();
+        match err {
+            RParifError::RequestError(_) => {
+                assert!(!format!("{}", err).contains("secret"));
+                assert!(!format!("{:?}", err).contains("secret"));
+            }
+            _ => panic!("Wrong error"),
+        }
     }
 }
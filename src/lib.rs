@@ -7,10 +7,16 @@
 //! * idxville : returns indice and pollutant for given cities for previous, current and next day
 //! * episode : returns pollution alerts
 //!
+//! Results can be kept in memory for a while using [CachedRParifClient](./cache/struct.CachedRParifClient.html).
+//! With `async` feature, [AsyncRParifClient](./async_client/struct.AsyncRParifClient.html) offers the same
+//! endpoints asynchronously, including on `wasm32-unknown-unknown` (with `default-features = false`).
+//!
 //! # Examples
 //!
 //! Getting and displaying global pollution indices :
 //! ```rust,no_run
+//! # #[cfg(feature = "blocking")]
+//! # fn main() -> Result<(), rparif::error::RParifError> {
 //! use rparif::client::RParifClient;
 //!
 //! let client = RParifClient::new("my-api-key");
@@ -18,11 +24,16 @@
 //! for index in indices.into_iter() {
 //!     println!("{}", index);
 //! }
-//! # Ok::<(), rparif::error::RParifError>(())
+//! # Ok(())
+//! # }
+//! # #[cfg(not(feature = "blocking"))]
+//! # fn main() {}
 //! ```
 //!
 //! Fetching pollution alert :
 //! ```rust,no_run
+//! # #[cfg(feature = "blocking")]
+//! # fn main() -> Result<(), rparif::error::RParifError> {
 //! use rparif::client::RParifClient;
 //!
 //! let client = RParifClient::new("my-api-key");
@@ -30,11 +41,16 @@
 //! for episode in episodes.into_iter() {
 //!     println!("{}", episode);
 //! }
-//! # Ok::<(), rparif::error::RParifError>(())
+//! # Ok(())
+//! # }
+//! # #[cfg(not(feature = "blocking"))]
+//! # fn main() {}
 //! ```
 #![doc(html_root_url = "https://docs.rs/rparif/0.1.1/")]
 #![deny(missing_docs)]
 #![cfg_attr(test, deny(warnings))]
+// Without any client, conversion code is unused
+#![cfg_attr(not(any(feature = "blocking", feature = "async")), allow(dead_code))]
 #![warn(
     missing_copy_implementations,
     missing_debug_implementations,
@@ -51,16 +67,34 @@
 extern crate json;
 #[macro_use]
 extern crate log;
-#[cfg(feature = "serde")]
-extern crate serde;
 
+#[cfg(feature = "blocking")]
 use crate::client::RParifClient;
+#[cfg(feature = "blocking")]
 use crate::error::RParifError;
+#[cfg(feature = "blocking")]
 use crate::objects::{Day, Episode, Index};
 
+#[cfg(feature = "async")]
+pub mod async_client;
+#[cfg(feature = "blocking")]
+pub mod cache;
 pub mod client;
+pub mod clock;
 pub mod error;
 pub mod objects;
+#[cfg(feature = "postal")]
+pub mod postal;
+#[cfg(feature = "serde")]
+pub mod response;
+#[cfg(feature = "blocking")]
+pub mod transport;
+
+/// Return the version of this library, e.g. to log which version produced data or to report
+/// an issue. The default User-Agent header is `r-parif/<version>`
+pub fn version() -> &'static str {
+    env!("CARGO_PKG_VERSION")
+}
 
 /// Convenient function that allow easy to access [`indice`](./client/struct.RParifClient.html#method.indice) endpoint.  
 /// If multiple calls needs to be made to HTTP API, use [RParifClient](./client/struct.RParifClient.html)
@@ -72,22 +106,26 @@ pub mod objects;
 /// # Errors
 ///
 /// * [RParifError::RequestError](../error/enum.RParifError.html#variant.RequestError) when reqwest lib
-/// fails. It contains the underlying error.
+///   fails. It contains the underlying error.
 ///
 /// * [RParifError::CallError](../error/enum.RParifError.html#variant.CallError) when HTTP status is
-/// other than 2XX. It contains the URL called, the HTTP status and the body response
+///   other than 2XX. It contains the URL called, the HTTP status and the body response
+///
+/// * [RParifError::RateLimited](../error/enum.RParifError.html#variant.RateLimited) when HTTP status
+///   is 429. It contains the `Retry-After` delay, if any
 ///
 /// * [RParifError::UnexpectedDate](../error/enum.RParifError.html#variant.UnexpectedDate)
-/// if the date can't be parsed (see [`convert_json_to_date`](#method.convert_json_to_date))
+///   if the date can't be parsed
 ///
 /// * [RParifError::MissingJsonKey](../error/enum.RParifError.html#variant.MissingJsonKey) if missing
-/// JSON is missing `indice` pollution
+///   JSON is missing `indice` pollution
 ///
 /// * [RParifError::WrongJsonType](../error/enum.RParifError.html#variant.WrongJsonType) if `indice`
-/// pollution is not a number or if JSON is not as expected
+///   pollution is not a number or if JSON is not as expected
 ///
 /// * [RParifError::JsonError](../error/enum.RParifError.html#variant.JsonError) if response isn't a well
-/// formed JSON
+///   formed JSON
+#[cfg(feature = "blocking")]
 pub fn indice(api_key: &str) -> Result<Vec<Index>, RParifError> {
     RParifClient::new(api_key).index()
 }
@@ -104,22 +142,26 @@ pub fn indice(api_key: &str) -> Result<Vec<Index>, RParifError> {
 /// # Errors
 ///
 /// * [RParifError::RequestError](../error/enum.RParifError.html#variant.RequestError) when reqwest lib
-/// fails. It contains the underlying error.
+///   fails. It contains the underlying error.
 ///
 /// * [RParifError::CallError](../error/enum.RParifError.html#variant.CallError) when HTTP status is
-/// other than 2XX. It contains the URL called, the HTTP status and the body response
+///   other than 2XX. It contains the URL called, the HTTP status and the body response
+///
+/// * [RParifError::RateLimited](../error/enum.RParifError.html#variant.RateLimited) when HTTP status
+///   is 429. It contains the `Retry-After` delay, if any
 ///
 /// * [RParifError::MissingJsonKey](../error/enum.RParifError.html#variant.MissingJsonKey) if missing
-/// JSON is missing `indice` pollution or `date`
+///   JSON is missing `indice` pollution or `date`
 ///
 /// * [RParifError::WrongJsonType](../error/enum.RParifError.html#variant.WrongJsonType) if `indice`
-/// pollution is not a number or if `date` isn't a string
+///   pollution is not a number or if `date` isn't a string
 ///
 /// * [RParifError::DateParseError](../error/enum.RParifError.html#variant.DateParseError) if `date`
-/// is not in `dd/mm/yyyy` format
+///   is not in `dd/mm/yyyy` format
 ///
 /// * [RParifError::JsonError](../error/enum.RParifError.html#variant.JsonError) if response isn't a well
-/// formed JSON
+///   formed JSON
+#[cfg(feature = "blocking")]
 pub fn indice_day(api_key: &str, day: Day) -> Result<Vec<Index>, RParifError> {
     RParifClient::new(api_key).index_day(day)
 }
@@ -131,29 +173,40 @@ pub fn indice_day(api_key: &str, day: Day) -> Result<Vec<Index>, RParifError> {
 ///
 /// * `api_key` - API key
 ///
-/// * `cities` - List of INSEE city code. See [here](https://data.opendatasoft.com/explore/dataset/correspondance-code-insee-code-postal%40public/table/)
-/// or [here](https://www.data.gouv.fr/en/datasets/correspondance-entre-les-codes-postaux-et-codes-insee-des-communes-francaises/) to find corresponding code
+/// * `cities` - List of INSEE city code (`Vec`, slice or iterator of `&str` or `String`). See [here](https://data.opendatasoft.com/explore/dataset/correspondance-code-insee-code-postal%40public/table/)
+///   or [here](https://www.data.gouv.fr/en/datasets/correspondance-entre-les-codes-postaux-et-codes-insee-des-communes-francaises/) to find corresponding code
 ///
 /// # Errors
 ///
+/// * [RParifError::EmptyCityList](../error/enum.RParifError.html#variant.EmptyCityList) when `cities`
+///   is empty
+///
 /// * [RParifError::RequestError](../error/enum.RParifError.html#variant.RequestError) when reqwest lib
-/// fails. It contains the underlying error.
+///   fails. It contains the underlying error.
 ///
 /// * [RParifError::CallError](../error/enum.RParifError.html#variant.CallError) when HTTP status is
-/// other than 2XX. It contains the URL called, the HTTP status and the body response
+///   other than 2XX. It contains the URL called, the HTTP status and the body response
+///
+/// * [RParifError::RateLimited](../error/enum.RParifError.html#variant.RateLimited) when HTTP status
+///   is 429. It contains the `Retry-After` delay, if any
 ///
 /// * [RParifError::UnexpectedDate](../error/enum.RParifError.html#variant.UnexpectedDate)
-/// if the date can't be parsed (see [`convert_string_to_date`](#method.convert_string_to_date))
+///   if a date isn't `hier`, `jour` or `demain`
 ///
 /// * [RParifError::MissingJsonKey](../error/enum.RParifError.html#variant.MissingJsonKey) if missing
-/// JSON is missing `ninsee` or `indice`
+///   JSON is missing `ninsee` or `indice`
 ///
 /// * [RParifError::WrongJsonType](../error/enum.RParifError.html#variant.WrongJsonType) if `ninsee`
-/// isn't a string or `indice` is not a number or if JSON is not as expected
+///   isn't a string or `indice` is not a number or if JSON is not as expected
 ///
 /// * [RParifError::JsonError](../error/enum.RParifError.html#variant.JsonError) if response isn't a well
-/// formed JSON
-pub fn indice_city(api_key: &str, cities: Vec<&str>) -> Result<Vec<Index>, RParifError> {
+///   formed JSON
+#[cfg(feature = "blocking")]
+pub fn indice_city<I, S>(api_key: &str, cities: I) -> Result<Vec<Index>, RParifError>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<str>,
+{
     RParifClient::new(api_key).index_city(cities)
 }
 
@@ -165,27 +218,31 @@ pub fn indice_city(api_key: &str, cities: Vec<&str>) -> Result<Vec<Index>, RPari
 /// * `api_key` - API key
 ///
 /// * `cities` - List of INSEE city code. See [here](https://data.opendatasoft.com/explore/dataset/correspondance-code-insee-code-postal%40public/table/)
-/// or [here](https://www.data.gouv.fr/en/datasets/correspondance-entre-les-codes-postaux-et-codes-insee-des-communes-francaises/) to find corresponding code
+///   or [here](https://www.data.gouv.fr/en/datasets/correspondance-entre-les-codes-postaux-et-codes-insee-des-communes-francaises/) to find corresponding code
 ///
 /// # Errors
 ///
 /// * [RParifError::RequestError](../error/enum.RParifError.html#variant.RequestError) when reqwest lib
-/// fails. It contains the underlying error.
+///   fails. It contains the underlying error.
 ///
 /// * [RParifError::CallError](../error/enum.RParifError.html#variant.CallError) when HTTP status is
-/// other than 2XX. It contains the URL called, the HTTP status and the body response
+///   other than 2XX. It contains the URL called, the HTTP status and the body response
+///
+/// * [RParifError::RateLimited](../error/enum.RParifError.html#variant.RateLimited) when HTTP status
+///   is 429. It contains the `Retry-After` delay, if any
 ///
 /// * [RParifError::UnexpectedDate](../error/enum.RParifError.html#variant.UnexpectedDate)
-/// if the date can't be parsed (see [`convert_string_to_date`](#method.convert_string_to_date))
+///   if a date isn't `hier`, `jour` or `demain`
 ///
 /// * [RParifError::MissingJsonKey](../error/enum.RParifError.html#variant.MissingJsonKey) if missing
-/// JSON is missing `ninsee` or `indice`
+///   JSON is missing `ninsee` or `indice`
 ///
 /// * [RParifError::WrongJsonType](../error/enum.RParifError.html#variant.WrongJsonType) if `ninsee`
-/// isn't a string or `indice` is not a number or if JSON is not as expected
+///   isn't a string or `indice` is not a number or if JSON is not as expected
 ///
 /// * [RParifError::JsonError](../error/enum.RParifError.html#variant.JsonError) if response isn't a well
-/// formed JSON
+///   formed JSON
+#[cfg(feature = "blocking")]
 pub fn episode(api_key: &str) -> Result<Vec<Episode>, RParifError> {
     RParifClient::new(api_key).episode()
 }
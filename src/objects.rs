@@ -1,19 +1,74 @@
 //! Several objects used to represent pollution index and alerts
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::convert::TryFrom;
 use std::fmt;
+use std::hash::{Hash, Hasher};
 use std::str::FromStr;
 
-use chrono::NaiveDate;
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime, Utc};
+use json::JsonValue;
 #[cfg(feature = "serde")]
 use serde::{Deserialize, Serialize};
 
+use crate::client::{json_to_date, number_value, optional_string, parse_enum, string_value};
+use crate::clock::paris_date;
 use crate::error::RParifError;
 
-/// This struct represent a pollution index
-#[derive(Clone, PartialEq, Hash, Debug)]
+/// Pollutant names known by this library, in their canonical (lowercase) form
+const KNOWN_POLLUTANTS: [&str; 6] = ["global", "o3", "no2", "pm10", "pm25", "so2"];
+
+/// Pollutants for which the European Air Quality Index is defined
+const EAQI_POLLUTANTS: [&str; 6] = ["global", "pm10", "pm25", "o3", "no2", "so2"];
+
+/// Lower bound of AirParif index for each European Air Quality Index level
+const EAQI_BANDS: [(u32, Quality); 6] = [
+    (0, Quality::Good),
+    (25, Quality::Fair),
+    (50, Quality::Moderate),
+    (75, Quality::Poor),
+    (100, Quality::VeryPoor),
+    (150, Quality::ExtremelyPoor),
+];
+
+/// Departments of Île-de-France, the region covered by AirParif
+const ILE_DE_FRANCE_DEPARTMENTS: [&str; 8] = ["75", "77", "78", "91", "92", "93", "94", "95"];
+
+/// (De)serialize dates in ISO-8601 format (`YYYY-MM-DD`)
+#[cfg(feature = "serde")]
+mod iso_date {
+    use chrono::NaiveDate;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    /// Date format
+    const FORMAT: &str = "%Y-%m-%d";
+
+    /// Serialize a date as `YYYY-MM-DD`
+    pub fn serialize<S: Serializer>(date: &NaiveDate, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&date.format(FORMAT).to_string())
+    }
+
+    /// Deserialize a `YYYY-MM-DD` date
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<NaiveDate, D::Error> {
+        let value = String::deserialize(deserializer)?;
+        NaiveDate::parse_from_str(&value, FORMAT).map_err(serde::de::Error::custom)
+    }
+}
+
+/// This struct represent a pollution index.
+///
+/// Two indices are equal (and have the same hash) when their date, time, city, pollutants and
+/// index are equal : map URL, comment and [`drives_global`](#method.drives_global) are ignored, so that deduplicating indices
+/// (e.g. with a `HashSet` or [dedup_indices](./fn.dedup_indices.html)) doesn't depend on them.
+#[derive(Clone, Eq, Debug)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Index {
     /// Date of mesure
+    #[cfg_attr(feature = "serde", serde(with = "iso_date"))]
     date: NaiveDate,
+    /// Time of mesure, AirParif only gives dates so it's `None` unless set
+    #[cfg_attr(feature = "serde", serde(default))]
+    time: Option<NaiveTime>,
     /// An url (if any) to a map show the global pollution
     url: Option<String>,
     /// Pollutants (could be global, o3, no2, pm10, so2)
@@ -21,7 +76,13 @@ pub struct Index {
     /// Index
     index: u32,
     /// City INSEE code
-    insee: Option<String>,
+    insee: Option<Insee>,
+    /// Comment or advisory sent along with the index
+    #[cfg_attr(feature = "serde", serde(default))]
+    comment: Option<String>,
+    /// Whether this pollutant index is the one the global index comes from
+    #[cfg_attr(feature = "serde", serde(default))]
+    drives_global: bool,
 }
 
 impl Index {
@@ -45,17 +106,52 @@ impl Index {
         url: Option<String>,
         pollutants: Vec<String>,
         index: u32,
-        insee: Option<String>,
+        insee: Option<Insee>,
     ) -> Index {
         Index {
             date,
+            time: None,
             url,
             pollutants,
             index,
             insee,
+            comment: None,
+            drives_global: false,
         }
     }
 
+    /// Set the comment of the index. An empty comment is stored as `None`.
+    ///
+    /// # Arguments
+    ///
+    /// * `comment` - Comment or advisory sent by AirParif
+    pub fn with_comment(mut self, comment: Option<String>) -> Index {
+        self.comment = comment.filter(|c| !c.is_empty());
+        self
+    }
+
+    /// Set the time of the index, e.g. a known publication hour, to order indices
+    /// of the same day or align them with other time series
+    ///
+    /// # Arguments
+    ///
+    /// * `time` - Time of mesure
+    pub fn with_time(mut self, time: Option<NaiveTime>) -> Index {
+        self.time = time;
+        self
+    }
+
+    /// Return the time of pollution index. AirParif API only gives dates, so it's `None`
+    /// unless set with [`with_time`](#method.with_time)
+    pub fn time(&self) -> Option<NaiveTime> {
+        self.time
+    }
+
+    /// Return the date and time of pollution index, `None` if time isn't known
+    pub fn date_time(&self) -> Option<NaiveDateTime> {
+        self.time.map(|time| self.date.and_time(time))
+    }
+
     /// Return the date of pollution index
     pub fn date(&self) -> NaiveDate {
         self.date
@@ -66,20 +162,140 @@ impl Index {
         self.url.clone()
     }
 
-    /// List of pollutants that are used to compute index
+    /// Same as [`map_url`](#method.map_url) without cloning
+    pub fn map_url_ref(&self) -> Option<&str> {
+        self.url.as_deref()
+    }
+
+    /// List of pollutants that are used to compute index. Known pollutants (`global`, `o3`,
+    /// `no2`, `pm10`, `pm25` and `so2`) are lowercase whatever the case sent by AirParif
     pub fn pollutants(&self) -> Vec<String> {
         self.pollutants.to_vec()
     }
 
+    /// Same as [`pollutants`](#method.pollutants) without cloning
+    pub fn pollutants_ref(&self) -> &[String] {
+        &self.pollutants
+    }
+
     /// Pollution index
     pub fn index(&self) -> u32 {
         self.index
     }
 
     /// INSEE city code
-    pub fn insee(&self) -> Option<String> {
+    pub fn insee(&self) -> Option<Insee> {
         self.insee.clone()
     }
+
+    /// Same as [`insee`](#method.insee) without cloning
+    pub fn insee_ref(&self) -> Option<&Insee> {
+        self.insee.as_ref()
+    }
+
+    /// Return the index as key/value pairs, e.g. to emit it into structured logs :
+    /// `date` (ISO-8601), `index` and `pollutants` (comma separated) are always present,
+    /// `time`, `insee`, `map_url` and `comment` only when set
+    pub fn as_fields(&self) -> Vec<(&'static str, String)> {
+        let mut fields = vec![("date", self.date.format("%Y-%m-%d").to_string())];
+        if let Some(time) = self.time {
+            fields.push(("time", time.to_string()));
+        }
+        fields.push(("index", self.index.to_string()));
+        fields.push(("pollutants", self.pollutants.join(",")));
+        if let Some(insee) = &self.insee {
+            fields.push(("insee", insee.to_string()));
+        }
+        if let Some(url) = &self.url {
+            fields.push(("map_url", url.clone()));
+        }
+        if let Some(comment) = &self.comment {
+            fields.push(("comment", comment.clone()));
+        }
+        fields
+    }
+
+    /// Return the number of pollutants the index is computed from (1 for the global index)
+    pub fn pollutant_count(&self) -> usize {
+        self.pollutants.len()
+    }
+
+    /// Whether `name` is one of the pollutants of the index (compared case-insensitively)
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - Pollutant name, e.g. `pm10` or `global`
+    pub fn pollutant_is(&self, name: &str) -> bool {
+        self.pollutants.iter().any(|p| p.eq_ignore_ascii_case(name))
+    }
+
+    /// Whether the index is the global index (computed from all pollutants) rather than
+    /// the index of some pollutants
+    pub fn is_global(&self) -> bool {
+        self.pollutants.len() == 1 && self.pollutants[0].eq_ignore_ascii_case("global")
+    }
+
+    /// Whether this pollutant index is the one driving the global index of the day.
+    /// AirParif doesn't tell which pollutant it is, so it's computed from `indiceJour`
+    /// results : pollutants whose index is equal to the global index are marked.
+    /// Always `false` for other endpoints and for the global index itself.
+    pub fn drives_global(&self) -> bool {
+        self.drives_global
+    }
+
+    /// Comment or advisory sent by AirParif (`commentaire` member), if any.
+    /// Only `indice` and `indiceJour` endpoints populate it, `idxville` never does.
+    pub fn comment(&self) -> Option<String> {
+        self.comment.clone()
+    }
+
+    /// Translate the index into the [European Air Quality Index](https://airindex.eea.europa.eu/)
+    /// scale, from 1 (good) to 6 (extremely poor), using the following bands :
+    ///
+    /// | AirParif index | EAQI                |
+    /// |----------------|---------------------|
+    /// | 0 - 24         | 1 (good)            |
+    /// | 25 - 49        | 2 (fair)            |
+    /// | 50 - 74        | 3 (moderate)        |
+    /// | 75 - 99        | 4 (poor)            |
+    /// | 100 - 149      | 5 (very poor)       |
+    /// | 150 and more   | 6 (extremely poor)  |
+    ///
+    /// As EAQI is only defined for `pm10`, `pm25`, `o3`, `no2` and `so2`, it returns `None`
+    /// if the index has no pollutant or if one of its pollutants is not one of them
+    /// (`global` is accepted as it's computed from these pollutants).
+    pub fn to_eaqi(&self) -> Option<u8> {
+        let defined = !self.pollutants.is_empty()
+            && self.pollutants.iter().all(|p| {
+                EAQI_POLLUTANTS
+                    .iter()
+                    .any(|e| e.eq_ignore_ascii_case(p.as_str()))
+            });
+        if !defined {
+            return None;
+        }
+        Some(Quality::from_index(self.index).eaqi_level())
+    }
+}
+
+impl PartialEq for Index {
+    fn eq(&self, other: &Self) -> bool {
+        self.date == other.date
+            && self.time == other.time
+            && self.insee == other.insee
+            && self.pollutants == other.pollutants
+            && self.index == other.index
+    }
+}
+
+impl Hash for Index {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.date.hash(state);
+        self.time.hash(state);
+        self.insee.hash(state);
+        self.pollutants.hash(state);
+        self.index.hash(state);
+    }
 }
 
 impl fmt::Display for Index {
@@ -92,8 +308,518 @@ impl fmt::Display for Index {
     }
 }
 
-/// Represent a date as use in the HTTP API
-#[derive(Clone, PartialEq, Hash, Debug, Copy)]
+impl TryFrom<&JsonValue> for Index {
+    type Error = RParifError;
+
+    /// Convert an element of `indice` endpoint response, e.g.
+    /// `{"date":"jour","indice":35,"url_carte":"..."}`. Empty `url_carte` or `commentaire`
+    /// are converted into `None`.
+    ///
+    /// # Errors
+    ///
+    /// * [RParifError::UnexpectedDate](../error/enum.RParifError.html#variant.UnexpectedDate)
+    ///   if `date` isn't `hier`, `jour` or `demain`
+    ///
+    /// * [RParifError::MissingJsonKey](../error/enum.RParifError.html#variant.MissingJsonKey) if
+    ///   JSON is missing `indice`
+    ///
+    /// * [RParifError::WrongJsonType](../error/enum.RParifError.html#variant.WrongJsonType) if `indice`
+    ///   is not a number or if `url_carte` is not a string
+    fn try_from(json: &JsonValue) -> Result<Self, Self::Error> {
+        Index::from_json(json, paris_date(Utc::now()))
+    }
+}
+
+impl Index {
+    /// Convert an element of `indice` endpoint response
+    ///
+    /// # Arguments
+    ///
+    /// * `json` - JSON object with `date`, `indice` and optionally `url_carte` and `commentaire`
+    ///
+    /// * `today` - date `jour` refers to
+    pub(crate) fn from_json(json: &JsonValue, today: NaiveDate) -> Result<Index, RParifError> {
+        let date = json_to_date(&json["date"], today)?;
+        let url = optional_string("url_carte", json)?;
+        let index = number_value("indice", json)?;
+
+        Ok(
+            Index::new(date, url, vec!["global".to_string()], index, None)
+                .with_comment(optional_string("commentaire", json)?),
+        )
+    }
+}
+
+/// Return the first index of a date, if any
+///
+/// # Arguments
+///
+/// * `indices` - Indices to look into
+///
+/// * `date` - Date of the index
+pub fn find_by_date(indices: &[Index], date: NaiveDate) -> Option<&Index> {
+    indices.iter().find(|index| index.date == date)
+}
+
+/// Return the first index of a date for a pollutant (compared case-insensitively), if any.
+/// An index computed from several pollutants matches any of them.
+///
+/// # Arguments
+///
+/// * `indices` - Indices to look into
+///
+/// * `date` - Date of the index
+///
+/// * `pollutant` - Pollutant name, e.g. `o3` or `global`
+pub fn find<'a>(indices: &'a [Index], date: NaiveDate, pollutant: &str) -> Option<&'a Index> {
+    indices
+        .iter()
+        .find(|index| index.date == date && index.pollutant_is(pollutant))
+}
+
+/// Group indices by date, keeping their order inside a date
+///
+/// # Arguments
+///
+/// * `indices` - Indices to group
+pub fn group_by_date(indices: Vec<Index>) -> BTreeMap<NaiveDate, Vec<Index>> {
+    let mut result: BTreeMap<NaiveDate, Vec<Index>> = BTreeMap::new();
+    for index in indices {
+        result.entry(index.date).or_default().push(index);
+    }
+    result
+}
+
+/// Group indices by INSEE city code, keeping their order inside a city.
+/// Indices without city (global indices) are grouped under `None`.
+///
+/// # Arguments
+///
+/// * `indices` - Indices to group
+pub fn group_by_insee(indices: Vec<Index>) -> HashMap<Option<Insee>, Vec<Index>> {
+    let mut result: HashMap<Option<Insee>, Vec<Index>> = HashMap::new();
+    for index in indices {
+        result.entry(index.insee.clone()).or_default().push(index);
+    }
+    result
+}
+
+/// Return index bands used by [`Quality::from_index`](./enum.Quality.html#method.from_index) and
+/// [`Index::to_eaqi`](./struct.Index.html#method.to_eaqi) : the lower bound of AirParif index for
+/// each European Air Quality Index level, from good to extremely poor, in ascending order.
+/// Useful to render scales or legends.
+pub fn index_bands() -> &'static [(u32, Quality)] {
+    &EAQI_BANDS
+}
+
+/// Return pollutant names known by this library, in their canonical (lowercase) form,
+/// including `global`. Pollutants aren't an enum as AirParif may send other names.
+pub fn known_pollutants() -> &'static [&'static str] {
+    &KNOWN_POLLUTANTS
+}
+
+/// Return names of pollutants present in indices, sorted and without duplicates, e.g. to
+/// fill a pollutant filter after [`index_city`](../client/struct.RParifClient.html#method.index_city)
+///
+/// # Arguments
+///
+/// * `indices` - Indices to look into
+///
+/// * `include_global` - Whether `global` is returned when present
+pub fn distinct_pollutants(indices: &[Index], include_global: bool) -> Vec<String> {
+    indices
+        .iter()
+        .flat_map(|index| index.pollutants.iter())
+        .filter(|p| include_global || !p.eq_ignore_ascii_case("global"))
+        .cloned()
+        .collect::<BTreeSet<String>>()
+        .into_iter()
+        .collect()
+}
+
+/// Return requested cities for which there's no index, e.g. cities unknown to AirParif
+/// that `idxville` endpoint silently dropped. Cities are returned once, in the order of
+/// `requested`.
+///
+/// # Arguments
+///
+/// * `requested` - INSEE city codes given to
+///   [`index_city`](../client/struct.RParifClient.html#method.index_city)
+///
+/// * `indices` - Indices returned for these cities
+pub fn missing_cities<I, S>(requested: I, indices: &[Index]) -> Vec<String>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<str>,
+{
+    let returned: HashSet<&str> = indices
+        .iter()
+        .filter_map(|index| index.insee.as_ref().map(|insee| insee.as_ref()))
+        .collect();
+    let mut seen: HashSet<String> = HashSet::new();
+    requested
+        .into_iter()
+        .map(|city| city.as_ref().to_string())
+        .filter(|city| !returned.contains(city.as_str()) && seen.insert(city.clone()))
+        .collect()
+}
+
+/// Return the canonical (lowercase) name of a known pollutant whatever its case
+/// (e.g. `O3` gives `o3`), unknown pollutants are kept as sent by AirParif
+///
+/// # Arguments
+///
+/// * `name` - Pollutant name sent by AirParif
+pub(crate) fn normalize_pollutant(name: &str) -> String {
+    KNOWN_POLLUTANTS
+        .iter()
+        .find(|known| known.eq_ignore_ascii_case(name))
+        .map(|known| known.to_string())
+        .unwrap_or_else(|| name.to_string())
+}
+
+/// Mark pollutant indices whose value is equal to the global index of the same date
+/// (see [`Index::drives_global`](./struct.Index.html#method.drives_global))
+///
+/// # Arguments
+///
+/// * `indices` - Indices of `indiceJour` endpoint
+pub(crate) fn mark_global_drivers(indices: &mut [Index]) {
+    let globals: Vec<(NaiveDate, u32)> = indices
+        .iter()
+        .filter(|index| index.is_global())
+        .map(|index| (index.date, index.index))
+        .collect();
+    for index in indices.iter_mut().filter(|index| !index.is_global()) {
+        index.drives_global = globals.contains(&(index.date, index.index));
+    }
+}
+
+/// Return the overall index of a day from the indices of its pollutants, e.g. `indiceJour`
+/// results. It's the **maximum** of the indices (not an average), which approximates the
+/// ATMO methodology. It returns 0 if there's no index.
+///
+/// # Arguments
+///
+/// * `indices` - Indices of a single day
+pub fn overall_index(indices: &[Index]) -> u32 {
+    indices.iter().map(|index| index.index).max().unwrap_or(0)
+}
+
+/// Return, for each date, pollutants and index of the highest index (e.g. to display the main
+/// pollutant of the day from `idxville` results of a city). When several indices have the
+/// same value, the first one is kept.
+///
+/// # Arguments
+///
+/// * `indices` - Indices to look through
+pub fn worst_pollutant_by_day(indices: &[Index]) -> BTreeMap<NaiveDate, (Vec<String>, u32)> {
+    let mut result: BTreeMap<NaiveDate, (Vec<String>, u32)> = BTreeMap::new();
+    for index in indices {
+        let worst = result
+            .entry(index.date)
+            .or_insert_with(|| (index.pollutants.clone(), index.index));
+        if index.index > worst.1 {
+            *worst = (index.pollutants.clone(), index.index);
+        }
+    }
+    result
+}
+
+/// Remove duplicated indices, keeping the first occurrence and the order of indices.
+/// Useful after merging results of several endpoints or requests. See [Index](./struct.Index.html)
+/// for equality rules.
+///
+/// # Arguments
+///
+/// * `indices` - Indices to deduplicate
+pub fn dedup_indices(indices: Vec<Index>) -> Vec<Index> {
+    let mut seen: HashSet<Index> = HashSet::new();
+    indices
+        .into_iter()
+        .filter(|index| seen.insert(index.clone()))
+        .collect()
+}
+
+/// Flatten episodes into a list of pollutant alerts paired with the date of their episode,
+/// keeping episodes and pollutants order. Useful for tabular rendering.
+///
+/// # Arguments
+///
+/// * `episodes` - Episodes to flatten
+pub fn flatten_episodes(episodes: Vec<Episode>) -> Vec<(NaiveDate, PollutantEpisode)> {
+    episodes
+        .into_iter()
+        .flat_map(|episode| {
+            let date = episode.date;
+            episode.into_iter().map(move |p| (date, p))
+        })
+        .collect()
+}
+
+/// Return names of pollutants whose alert is at or above `level` in any of the episodes
+/// (see [Level](./enum.Level.html) for severity order), e.g. to know whether `o3` is
+/// in alert today
+///
+/// # Arguments
+///
+/// * `episodes` - Alerts from `episode` endpoint
+///
+/// * `level` - Minimum level of alerts
+pub fn alerting_pollutants(episodes: &[Episode], level: Level) -> HashSet<String> {
+    episodes
+        .iter()
+        .flat_map(|episode| episode.pollutants.iter())
+        .filter(|p| p.level >= level)
+        .map(|p| p.pollutant.clone())
+        .collect()
+}
+
+/// Key identifying an alert already seen by
+/// [for_each_new_episode](./fn.for_each_new_episode.html) : date of the episode, pollutant name
+/// and level of the alert. An alert whose level changes is therefore seen as new.
+pub type EpisodeKey = (NaiveDate, String, Level);
+
+/// Call `f` for each pollutant alert of `episodes` that isn't in `seen`, then add it to `seen`.
+/// Keep `seen` between polls of `episode` endpoint to react only to new alerts.
+///
+/// # Arguments
+///
+/// * `episodes` - Alerts from `episode` endpoint
+///
+/// * `seen` - Alerts already seen, see [EpisodeKey](./type.EpisodeKey.html)
+///
+/// * `f` - Called with the date of the episode and the new pollutant alert
+pub fn for_each_new_episode<F>(episodes: &[Episode], seen: &mut HashSet<EpisodeKey>, mut f: F)
+where
+    F: FnMut(NaiveDate, &PollutantEpisode),
+{
+    for episode in episodes {
+        for pollutant in episode {
+            let key = (
+                episode.date,
+                pollutant.pollutant.clone(),
+                pollutant.level.clone(),
+            );
+            if seen.insert(key) {
+                f(episode.date, pollutant);
+            }
+        }
+    }
+}
+
+/// Changes between two lists of episodes (see [episode_diff](./fn.episode_diff.html)).
+/// Alerts are identified by the date of their episode and their pollutant.
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct EpisodeDiff {
+    /// Alerts only in the new list, with the date of their episode
+    added: Vec<(NaiveDate, PollutantEpisode)>,
+    /// Alerts only in the old list, with the date of their episode
+    removed: Vec<(NaiveDate, PollutantEpisode)>,
+    /// Alerts in both lists with a different level : date, old alert and new alert
+    level_changed: Vec<(NaiveDate, PollutantEpisode, PollutantEpisode)>,
+}
+
+impl EpisodeDiff {
+    /// Return alerts only in the new list, with the date of their episode
+    pub fn added(&self) -> &[(NaiveDate, PollutantEpisode)] {
+        &self.added
+    }
+
+    /// Return alerts only in the old list, with the date of their episode
+    pub fn removed(&self) -> &[(NaiveDate, PollutantEpisode)] {
+        &self.removed
+    }
+
+    /// Return alerts whose level changed : date of the episode, old alert and new alert
+    pub fn level_changed(&self) -> &[(NaiveDate, PollutantEpisode, PollutantEpisode)] {
+        &self.level_changed
+    }
+
+    /// Whether there's no change at all
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.level_changed.is_empty()
+    }
+}
+
+impl fmt::Display for EpisodeDiff {
+    /// One line per change : `+` for added alerts, `-` for removed alerts and `~` for level
+    /// changes
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_empty() {
+            return write!(f, "No change");
+        }
+        let mut lines: Vec<String> = Vec::new();
+        for (date, alert) in &self.added {
+            lines.push(format!("+ {} {}", date, alert));
+        }
+        for (date, alert) in &self.removed {
+            lines.push(format!("- {} {}", date, alert));
+        }
+        for (date, old, new) in &self.level_changed {
+            lines.push(format!(
+                "~ {} {} : {} -> {}",
+                date, new.pollutant, old.level, new.level
+            ));
+        }
+        write!(f, "{}", lines.join("\n"))
+    }
+}
+
+/// Compare two lists of episodes, e.g. results of two polls of `episode` endpoint, and report
+/// added alerts, removed alerts and alerts whose level changed. Alerts are matched by date and
+/// pollutant, a change of [Type](./enum.Type.html) or criteria alone isn't reported.
+/// Added and changed alerts follow the order of `new`, removed alerts the order of `old`.
+///
+/// # Arguments
+///
+/// * `old` - Previous alerts from `episode` endpoint
+///
+/// * `new` - Current alerts from `episode` endpoint
+pub fn episode_diff(old: &[Episode], new: &[Episode]) -> EpisodeDiff {
+    let old_alerts = flatten_episodes(old.to_vec());
+    let new_alerts = flatten_episodes(new.to_vec());
+    let find = |alerts: &[(NaiveDate, PollutantEpisode)], date: NaiveDate, pollutant: &str| {
+        alerts
+            .iter()
+            .find(|(d, p)| *d == date && p.pollutant == pollutant)
+            .map(|(_, p)| p.clone())
+    };
+
+    let mut diff = EpisodeDiff::default();
+    for (date, alert) in &new_alerts {
+        match find(&old_alerts, *date, &alert.pollutant) {
+            None => diff.added.push((*date, alert.clone())),
+            Some(previous) if previous.level != alert.level => {
+                diff.level_changed.push((*date, previous, alert.clone()))
+            }
+            Some(_) => {}
+        }
+    }
+    for (date, alert) in &old_alerts {
+        if find(&new_alerts, *date, &alert.pollutant).is_none() {
+            diff.removed.push((*date, alert.clone()));
+        }
+    }
+    diff
+}
+
+/// Serialize indices into a JSON array (requires `serde` feature). Dates are written
+/// in ISO-8601 format (`YYYY-MM-DD`)
+///
+/// # Arguments
+///
+/// * `indices` - Indices to serialize
+#[cfg(feature = "serde")]
+pub fn indices_to_json(indices: &[Index]) -> String {
+    serde_json::to_string(indices).expect("Index is always serializable")
+}
+
+/// Serialize episodes into a JSON array (requires `serde` feature). Dates are written
+/// in ISO-8601 format (`YYYY-MM-DD`)
+///
+/// # Arguments
+///
+/// * `episodes` - Episodes to serialize
+#[cfg(feature = "serde")]
+pub fn episodes_to_json(episodes: &[Episode]) -> String {
+    serde_json::to_string(episodes).expect("Episode is always serializable")
+}
+
+/// INSEE city code, as expected by `idxville` endpoint.
+///
+/// Codes are made of 5 digits, except for Corsica where they start with `2A` or `2B`
+/// (e.g. `2A004`). Note that postal codes also have 5 digits, see [postal](../postal/index.html)
+/// module to convert them.
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "String", into = "String"))]
+pub struct Insee(String);
+
+impl Insee {
+    /// Return the code
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// Return the department of the city (first two characters of the code, e.g. `75`)
+    pub fn department(&self) -> &str {
+        &self.0[..2]
+    }
+
+    /// Return `true` if the city is in Île-de-France, the region covered by AirParif
+    pub fn is_ile_de_france(&self) -> bool {
+        ILE_DE_FRANCE_DEPARTMENTS.contains(&self.department())
+    }
+}
+
+impl TryFrom<&str> for Insee {
+    type Error = RParifError;
+
+    /// # Errors
+    ///
+    /// * [RParifError::InvalidInsee](../error/enum.RParifError.html#variant.InvalidInsee) if
+    ///   `value` isn't made of 5 digits (or `2A`/`2B` followed by 3 digits)
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        let value = value.trim();
+        let department = value.get(..2).unwrap_or_default();
+        let valid = value.len() == 5
+            && value.is_ascii()
+            && value[2..].chars().all(|c| c.is_ascii_digit())
+            && (department.chars().all(|c| c.is_ascii_digit())
+                || department == "2A"
+                || department == "2B");
+        if valid {
+            Ok(Insee(value.to_string()))
+        } else {
+            Err(RParifError::InvalidInsee(value.to_string()))
+        }
+    }
+}
+
+impl TryFrom<String> for Insee {
+    type Error = RParifError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        Insee::try_from(value.as_str())
+    }
+}
+
+impl FromStr for Insee {
+    type Err = RParifError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Insee::try_from(s)
+    }
+}
+
+impl From<Insee> for String {
+    fn from(insee: Insee) -> Self {
+        insee.0
+    }
+}
+
+impl AsRef<str> for Insee {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for Insee {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// Represent a date as use in the HTTP API.
+///
+/// Days are ordered chronologically.
+///
+/// With serde feature, variants are (de)serialized as `yesterday`, `today` and `tomorrow`
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
 pub enum Day {
     /// Variant for yesterday
     Yesterday,
@@ -103,11 +829,66 @@ pub enum Day {
     Tomorrow,
 }
 
+impl Day {
+    /// Number of days from current day : -1 for yesterday, 0 for today and 1 for tomorrow
+    pub fn offset_days(&self) -> i64 {
+        match self {
+            Day::Yesterday => -1,
+            Day::Today => 0,
+            Day::Tomorrow => 1,
+        }
+    }
+
+    /// Return the day matching an offset from current day (see [`offset_days`](#method.offset_days)),
+    /// `None` if AirParif doesn't provide data for it
+    ///
+    /// # Arguments
+    ///
+    /// * `offset` - Number of days from current day
+    pub fn from_offset(offset: i64) -> Option<Day> {
+        match offset {
+            -1 => Some(Day::Yesterday),
+            0 => Some(Day::Today),
+            1 => Some(Day::Tomorrow),
+            _ => None,
+        }
+    }
+}
+
+impl TryFrom<i64> for Day {
+    type Error = RParifError;
+
+    /// Convert an offset from current day (see [`Day::from_offset`](#method.from_offset))
+    ///
+    /// # Errors
+    ///
+    /// * [RParifError::UnkownEnumValue](../error/enum.RParifError.html#variant.UnkownEnumValue) if
+    ///   `value` isn't -1, 0 or 1
+    fn try_from(value: i64) -> Result<Self, Self::Error> {
+        Day::from_offset(value).ok_or_else(|| RParifError::UnkownEnumValue(value.to_string()))
+    }
+}
+
+impl TryFrom<u8> for Day {
+    type Error = RParifError;
+
+    /// Convert a number of days after current day : 0 for today and 1 for tomorrow
+    ///
+    /// # Errors
+    ///
+    /// * [RParifError::UnkownEnumValue](../error/enum.RParifError.html#variant.UnkownEnumValue) if
+    ///   `value` isn't 0 or 1
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        Day::try_from(i64::from(value))
+    }
+}
+
 /// Represent a pollution alert
-#[derive(Clone, PartialEq, Hash, Debug)]
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Episode {
     /// Alert date
+    #[cfg_attr(feature = "serde", serde(with = "iso_date"))]
     date: NaiveDate,
     /// Alert details
     detail: Option<String>,
@@ -161,10 +942,130 @@ impl Episode {
         self.detail.clone()
     }
 
+    /// Same as [`detail`](#method.detail) without cloning
+    pub fn detail_ref(&self) -> Option<&str> {
+        self.detail.as_deref()
+    }
+
     /// Return the list of pollutant
     pub fn pollutants(&self) -> Vec<PollutantEpisode> {
         self.pollutants.to_vec()
     }
+
+    /// Return the details of the alert for a pollutant (if any), names are compared
+    /// case-insensitively
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - Name of the pollutant (o3, no2, so2, pm10)
+    pub fn pollutant(&self, name: &str) -> Option<&PollutantEpisode> {
+        self.pollutants
+            .iter()
+            .find(|p| p.pollutant.eq_ignore_ascii_case(name))
+    }
+
+    /// Return the most severe level across all pollutants (see [Level](./enum.Level.html)
+    /// for severity order). It returns [Level::Normal](./enum.Level.html#variant.Normal)
+    /// if there's no pollutant.
+    pub fn max_level(&self) -> Level {
+        self.pollutants
+            .iter()
+            .map(|p| p.level.clone())
+            .fold(Level::Normal, Level::max)
+    }
+
+    /// Return the distinct criteria across all pollutants, in order of first appearance,
+    /// e.g. to know whether alerts were raised by area, population or both
+    pub fn criteria_union(&self) -> Vec<Criteria> {
+        let mut result: Vec<Criteria> = Vec::new();
+        for criteria in self.pollutants.iter().flat_map(|p| p.criteria.iter()) {
+            if !result.contains(criteria) {
+                result.push(criteria.clone());
+            }
+        }
+        result
+    }
+
+    /// Iterate through pollutants without consuming the episode
+    pub fn iter(&self) -> std::slice::Iter<'_, PollutantEpisode> {
+        self.pollutants.iter()
+    }
+
+    /// Convert an element of `episode` endpoint response
+    ///
+    /// # Arguments
+    ///
+    /// * `json` - JSON object with `date`, `detail` and a member per pollutant
+    ///
+    /// * `lenient` - whether unknown `type`, `niveau` or `criteres` values are kept
+    ///   (see [Level::from_str_lenient](./enum.Level.html#method.from_str_lenient))
+    ///
+    /// * `today` - date `jour` refers to
+    pub(crate) fn from_json(
+        json: &JsonValue,
+        lenient: bool,
+        today: NaiveDate,
+    ) -> Result<Episode, RParifError> {
+        let date = json_to_date(&json["date"], today)?;
+        let detail = optional_string("detail", json)?;
+        let mut episode = Episode::new(date, detail);
+        for (key, value) in json.entries() {
+            if key != "date" && key != "detail" {
+                let at = |err: RParifError| err.at(key);
+                let kind = parse_enum(
+                    string_value("type", value).map_err(at)?,
+                    lenient,
+                    Type::from_str_lenient,
+                )?;
+                let level = parse_enum(
+                    string_value("niveau", value).map_err(at)?,
+                    lenient,
+                    Level::from_str_lenient,
+                )?;
+                let criteria: Vec<Criteria> = match &value["criteres"] {
+                    JsonValue::Array(v) => v
+                        .iter()
+                        .enumerate()
+                        .map(|(position, v)| match v.as_str() {
+                            Some(c) => parse_enum(c, lenient, Criteria::from_str_lenient),
+                            None => Err(RParifError::WrongJsonType {
+                                expected: "string".to_string(),
+                                json: v.dump(),
+                                path: Some(format!("{}.criteres[{}]", key, position)),
+                            }),
+                        })
+                        .collect::<Result<Vec<Criteria>, RParifError>>()?,
+                    _ => Vec::new(),
+                };
+                episode.add(normalize_pollutant(key), kind, level, criteria);
+            }
+        }
+        Ok(episode)
+    }
+}
+
+impl TryFrom<&JsonValue> for Episode {
+    type Error = RParifError;
+
+    /// Convert an element of `episode` endpoint response, e.g.
+    /// `{"date":"jour","detail":"","o3":{"type":"prevu","niveau":"info","criteres":["km"]}}`
+    ///
+    /// # Errors
+    ///
+    /// * [RParifError::UnexpectedDate](../error/enum.RParifError.html#variant.UnexpectedDate)
+    ///   if `date` isn't `hier`, `jour` or `demain`
+    ///
+    /// * [RParifError::MissingJsonKey](../error/enum.RParifError.html#variant.MissingJsonKey) if
+    ///   a pollutant is missing `type` or `niveau`
+    ///
+    /// * [RParifError::UnkownEnumValue](../error/enum.RParifError.html#variant.UnkownEnumValue) if `type`,
+    ///   `niveau` or one of `criteres` can't be converted into corresponding enum variant
+    ///
+    /// * [RParifError::WrongJsonType](../error/enum.RParifError.html#variant.WrongJsonType) if `type`,
+    ///   `niveau` or one of `criteres` is not a string
+    fn try_from(json: &JsonValue) -> Result<Self, Self::Error> {
+        Episode::from_json(json, false, paris_date(Utc::now()))
+    }
 }
 
 impl fmt::Display for Episode {
@@ -183,38 +1084,145 @@ impl IntoIterator for Episode {
 
     fn into_iter(self) -> Self::IntoIter {
         PollutantEpisodeIter {
-            episode: self,
-            i: 0,
+            pollutants: self.pollutants.into_iter(),
         }
     }
 }
 
+impl<'a> IntoIterator for &'a Episode {
+    type Item = &'a PollutantEpisode;
+    type IntoIter = std::slice::Iter<'a, PollutantEpisode>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
 /// Allow to iterate through PollutantEpisode of an Episode
-#[derive(Clone, PartialEq, Hash, Debug)]
+#[derive(Clone, Debug)]
 pub struct PollutantEpisodeIter {
-    episode: Episode,
-    i: usize,
+    /// Remaining pollutants of the episode
+    pollutants: std::vec::IntoIter<PollutantEpisode>,
 }
 
 impl Iterator for PollutantEpisodeIter {
     type Item = PollutantEpisode;
 
     fn next(&mut self) -> Option<Self::Item> {
-        if self.i < self.episode.pollutants.len() {
-            // TODO handle error ?
-            let episode: PollutantEpisode = self.episode.pollutants().get(self.i).unwrap().clone();
-            self.i += 1;
-            Some(episode)
-        } else {
-            None
-        }
+        self.pollutants.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.pollutants.size_hint()
     }
 }
 
-/// Details of pollution alert
-#[derive(Clone, PartialEq, Hash, Debug)]
+/// An index paired with the alert of one of its pollutants, if any
+/// (see [join_episodes](./fn.join_episodes.html))
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
-pub struct PollutantEpisode {
+pub struct IndexAlert {
+    /// Pollution index
+    index: Index,
+    /// Alert of the index pollutant on the same day
+    episode: Option<PollutantEpisode>,
+}
+
+impl IndexAlert {
+    /// Return the pollution index
+    pub fn index(&self) -> &Index {
+        &self.index
+    }
+
+    /// Return the alert matching the index, if any
+    pub fn episode(&self) -> Option<&PollutantEpisode> {
+        self.episode.as_ref()
+    }
+}
+
+/// Pair each index with the alert of the same date and pollutant (compared case-insensitively).
+/// If an index has several pollutants, the alert of the first one under alert is kept.
+/// Indices without matching alert get `None`.
+///
+/// # Arguments
+///
+/// * `indices` - Indices, e.g. from `idxville` endpoint
+///
+/// * `episodes` - Alerts from `episode` endpoint
+pub fn join_episodes(indices: Vec<Index>, episodes: &[Episode]) -> Vec<IndexAlert> {
+    indices
+        .into_iter()
+        .map(|index| {
+            let episode = episodes
+                .iter()
+                .filter(|e| e.date == index.date)
+                .flat_map(|e| e.pollutants.iter())
+                .find(|p| index.pollutant_is(&p.pollutant))
+                .cloned();
+            IndexAlert { index, episode }
+        })
+        .collect()
+}
+
+/// Indices of a city per date (see [city_forecasts](./fn.city_forecasts.html))
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct CityForecast {
+    /// INSEE city code
+    insee: Insee,
+    /// Index of the city per date
+    days: BTreeMap<NaiveDate, Index>,
+}
+
+impl CityForecast {
+    /// Return INSEE city code
+    pub fn insee(&self) -> &Insee {
+        &self.insee
+    }
+
+    /// Return indices of the city per date
+    pub fn days(&self) -> &BTreeMap<NaiveDate, Index> {
+        &self.days
+    }
+
+    /// Return the index of the city for a date, if any
+    ///
+    /// # Arguments
+    ///
+    /// * `date` - Date of the index
+    pub fn day(&self, date: NaiveDate) -> Option<&Index> {
+        self.days.get(&date)
+    }
+}
+
+/// Merge indices into one [CityForecast](./struct.CityForecast.html) per city, ordered by
+/// INSEE code. Indices without city (global indices) are excluded. If a city has several
+/// indices for the same date, the first one is kept.
+///
+/// # Arguments
+///
+/// * `indices` - Indices, e.g. from `idxville` endpoint
+pub fn city_forecasts(indices: Vec<Index>) -> Vec<CityForecast> {
+    let mut cities: BTreeMap<Insee, BTreeMap<NaiveDate, Index>> = BTreeMap::new();
+    for index in indices {
+        if let Some(insee) = index.insee.clone() {
+            cities
+                .entry(insee)
+                .or_default()
+                .entry(index.date)
+                .or_insert(index);
+        }
+    }
+    cities
+        .into_iter()
+        .map(|(insee, days)| CityForecast { insee, days })
+        .collect()
+}
+
+/// Details of pollution alert
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct PollutantEpisode {
     /// Name of the pollutant o3, no2, so2, pm10
     pollutant: String,
     /// Type of the alert
@@ -245,20 +1253,138 @@ impl PollutantEpisode {
     pub fn criteria(&self) -> Vec<Criteria> {
         self.criteria.to_vec()
     }
+
+    /// Return public health advice for the alert level of the pollutant.
+    /// See [Level::recommendation](./enum.Level.html#method.recommendation)
+    ///
+    /// # Arguments
+    ///
+    /// * `lang` - Language of the advice
+    pub fn advice(&self, lang: Lang) -> &'static str {
+        self.level.recommendation(lang)
+    }
 }
 
 impl fmt::Display for PollutantEpisode {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let criteria: Vec<String> = self.criteria.iter().map(|c| c.to_string()).collect();
         write!(
             f,
-            "{} : {:?} {:?} {:?}",
-            self.pollutant, self.kind, self.level, self.criteria
+            "{} : {} {} ({})",
+            self.pollutant,
+            self.kind,
+            self.level,
+            criteria.join(", ")
         )
     }
 }
 
-/// Level of pollution alert
-#[derive(Clone, PartialEq, Hash, Debug, Copy)]
+/// Air quality band of an AirParif index, following the
+/// [European Air Quality Index](https://airindex.eea.europa.eu/) levels (see
+/// [`Index::to_eaqi`](./struct.Index.html#method.to_eaqi) for band boundaries). Bands and
+/// colors are the ones published by the European Environment Agency, they may differ from
+/// the ones displayed by AirParif.
+///
+/// Qualities are ordered from the best to the worst.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Quality {
+    /// Good (EAQI level 1)
+    Good,
+    /// Fair (EAQI level 2)
+    Fair,
+    /// Moderate (EAQI level 3)
+    Moderate,
+    /// Poor (EAQI level 4)
+    Poor,
+    /// Very poor (EAQI level 5)
+    VeryPoor,
+    /// Extremely poor (EAQI level 6)
+    ExtremelyPoor,
+}
+
+impl Quality {
+    /// Return the quality band of an AirParif index, using [`index_bands`](./fn.index_bands.html)
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - AirParif index
+    pub fn from_index(index: u32) -> Quality {
+        EAQI_BANDS
+            .iter()
+            .rev()
+            .find(|(lower, _)| index >= *lower)
+            .map(|(_, quality)| *quality)
+            .unwrap_or(Quality::Good)
+    }
+
+    /// Return the European Air Quality Index level of the band, from 1 (good) to 6 (extremely poor)
+    pub fn eaqi_level(&self) -> u8 {
+        match self {
+            Quality::Good => 1,
+            Quality::Fair => 2,
+            Quality::Moderate => 3,
+            Quality::Poor => 4,
+            Quality::VeryPoor => 5,
+            Quality::ExtremelyPoor => 6,
+        }
+    }
+
+    /// Label of the band in the given language
+    ///
+    /// # Arguments
+    ///
+    /// * `lang` - Language of the label
+    pub fn localized(&self, lang: Lang) -> &'static str {
+        match (self, lang) {
+            (Quality::Good, Lang::Fr) => "Bon",
+            (Quality::Good, Lang::En) => "Good",
+            (Quality::Fair, Lang::Fr) => "Moyen",
+            (Quality::Fair, Lang::En) => "Fair",
+            (Quality::Moderate, Lang::Fr) => "Dégradé",
+            (Quality::Moderate, Lang::En) => "Moderate",
+            (Quality::Poor, Lang::Fr) => "Mauvais",
+            (Quality::Poor, Lang::En) => "Poor",
+            (Quality::VeryPoor, Lang::Fr) => "Très mauvais",
+            (Quality::VeryPoor, Lang::En) => "Very poor",
+            (Quality::ExtremelyPoor, Lang::Fr) => "Extrêmement mauvais",
+            (Quality::ExtremelyPoor, Lang::En) => "Extremely poor",
+        }
+    }
+
+    /// Return the color of the band, as defined by the European Environment Agency for the
+    /// [European Air Quality Index](https://airindex.eea.europa.eu/), in hexadecimal (`#rrggbb`)
+    pub fn color(&self) -> &'static str {
+        match self {
+            Quality::Good => "#50f0e6",
+            Quality::Fair => "#50ccaa",
+            Quality::Moderate => "#f0e641",
+            Quality::Poor => "#ff5050",
+            Quality::VeryPoor => "#960032",
+            Quality::ExtremelyPoor => "#7d2181",
+        }
+    }
+}
+
+/// Language of labels, see `localized` methods of [Level](./enum.Level.html),
+/// [Type](./enum.Type.html), [Criteria](./enum.Criteria.html) and [Quality](./enum.Quality.html)
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Lang {
+    /// French, as displayed by AirParif (default)
+    #[default]
+    Fr,
+    /// English
+    En,
+}
+
+/// Level of pollution alert.
+///
+/// Levels are ordered by severity (not alphabetically), from the least to the most severe :
+/// [Normal](#variant.Normal) < [Info](#variant.Info) < [Alert](#variant.Alert).
+/// As its severity can't be known, [Unknown](#variant.Unknown) is considered less severe
+/// than [Normal](#variant.Normal), unknown values being ordered by their name.
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum Level {
     /// Information level
@@ -267,17 +1393,104 @@ pub enum Level {
     Alert,
     /// Normal level
     Normal,
+    /// Level not known by this library, it contains the value sent by AirParif
+    Unknown(String),
+}
+
+impl Level {
+    /// Return all levels known by this library, from the least to the most severe
+    /// ([Unknown](#variant.Unknown) is excluded), e.g. to fill a drop-down list
+    pub fn all() -> &'static [Level] {
+        static ALL: [Level; 3] = [Level::Normal, Level::Info, Level::Alert];
+        &ALL
+    }
+
+    /// Rank of the level, the higher the more severe
+    fn severity(&self) -> u8 {
+        match self {
+            Level::Unknown(_) => 0,
+            Level::Normal => 1,
+            Level::Info => 2,
+            Level::Alert => 3,
+        }
+    }
+
+    /// Return public health recommendation associated with the level
+    ///
+    /// # Arguments
+    ///
+    /// * `lang` - Language of the recommendation
+    pub fn recommendation(&self, lang: Lang) -> &'static str {
+        match (self, lang) {
+            (Level::Normal, Lang::Fr) => "Aucune recommandation particulière.",
+            (Level::Normal, Lang::En) => "No particular recommendation.",
+            (Level::Info, Lang::Fr) => "Les personnes sensibles doivent limiter les activités physiques et sportives intenses en plein air.",
+            (Level::Info, Lang::En) => "Sensitive people should reduce intense outdoor physical activities.",
+            (Level::Alert, Lang::Fr) => "Toute la population doit réduire les activités physiques et sportives intenses en plein air, les personnes sensibles doivent les éviter.",
+            (Level::Alert, Lang::En) => "Everyone should reduce intense outdoor physical activities, sensitive people should avoid them.",
+            (Level::Unknown(_), Lang::Fr) => "Niveau inconnu, se référer aux recommandations d'AirParif.",
+            (Level::Unknown(_), Lang::En) => "Unknown level, refer to AirParif recommendations.",
+        }
+    }
+
+    /// Same as [`from_str`](#method.from_str) but unexpected values are kept in
+    /// [Level::Unknown](#variant.Unknown) instead of raising an error
+    ///
+    /// # Arguments
+    ///
+    /// * `s` - value sent by AirParif
+    pub fn from_str_lenient(s: &str) -> Level {
+        s.parse().unwrap_or_else(|_| Level::Unknown(s.to_string()))
+    }
+
+    /// Label of the level in the given language, unknown levels are returned as sent by AirParif
+    ///
+    /// # Arguments
+    ///
+    /// * `lang` - Language of the label
+    pub fn localized(&self, lang: Lang) -> &str {
+        match (self, lang) {
+            (Level::Info, _) => "Information",
+            (Level::Alert, Lang::Fr) => "Alerte",
+            (Level::Alert, Lang::En) => "Alert",
+            (Level::Normal, _) => "Normal",
+            (Level::Unknown(value), _) => value,
+        }
+    }
+}
+
+impl Ord for Level {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self, other) {
+            (Level::Unknown(a), Level::Unknown(b)) => a.cmp(b),
+            _ => self.severity().cmp(&other.severity()),
+        }
+    }
+}
+
+impl PartialOrd for Level {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl fmt::Display for Level {
+    /// French label (`Information`, `Alerte` or `Normal`), unknown levels are displayed as sent by AirParif.
+    /// Labels can be parsed back with [`from_str`](#method.from_str)
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.localized(Lang::Fr))
+    }
 }
 
 impl FromStr for Level {
     type Err = RParifError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        if s == "info" {
+        if s == "info" || s == "Information" {
             Ok(Level::Info)
-        } else if s == "alerte" {
+        } else if s == "alerte" || s == "Alerte" {
             Ok(Level::Alert)
-        } else if s == "normal" {
+        } else if s == "normal" || s == "Normal" {
             Ok(Level::Normal)
         } else {
             Err(RParifError::UnkownEnumValue(s.to_string()))
@@ -286,22 +1499,65 @@ impl FromStr for Level {
 }
 
 /// Type of alert
-#[derive(Clone, PartialEq, Hash, Debug, Copy)]
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum Type {
     /// alert was forecast
     Forecast,
     /// alert was observed
     Observed,
+    /// Type not known by this library, it contains the value sent by AirParif
+    Unknown(String),
+}
+
+impl Type {
+    /// Return all types known by this library ([Unknown](#variant.Unknown) is excluded)
+    pub fn all() -> &'static [Type] {
+        static ALL: [Type; 2] = [Type::Forecast, Type::Observed];
+        &ALL
+    }
+
+    /// Same as [`from_str`](#method.from_str) but unexpected values are kept in
+    /// [Type::Unknown](#variant.Unknown) instead of raising an error
+    ///
+    /// # Arguments
+    ///
+    /// * `s` - value sent by AirParif
+    pub fn from_str_lenient(s: &str) -> Type {
+        s.parse().unwrap_or_else(|_| Type::Unknown(s.to_string()))
+    }
+
+    /// Label of the type in the given language, unknown types are returned as sent by AirParif
+    ///
+    /// # Arguments
+    ///
+    /// * `lang` - Language of the label
+    pub fn localized(&self, lang: Lang) -> &str {
+        match (self, lang) {
+            (Type::Forecast, Lang::Fr) => "Prévu",
+            (Type::Forecast, Lang::En) => "Forecast",
+            (Type::Observed, Lang::Fr) => "Constaté",
+            (Type::Observed, Lang::En) => "Observed",
+            (Type::Unknown(value), _) => value,
+        }
+    }
+}
+
+impl fmt::Display for Type {
+    /// French label (`Prévu` or `Constaté`), unknown types are displayed as sent by AirParif.
+    /// Labels can be parsed back with [`from_str`](#method.from_str)
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.localized(Lang::Fr))
+    }
 }
 
 impl FromStr for Type {
     type Err = RParifError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        if s == "prevu" {
+        if s == "prevu" || s == "Prévu" {
             Ok(Type::Forecast)
-        } else if s == "constate" {
+        } else if s == "constate" || s == "Constaté" {
             Ok(Type::Observed)
         } else {
             Err(RParifError::UnkownEnumValue(s.to_string()))
@@ -310,22 +1566,65 @@ impl FromStr for Type {
 }
 
 /// Criteria that can raise an alert
-#[derive(Clone, PartialEq, Hash, Debug, Copy)]
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum Criteria {
     /// More than 100km²
     Area,
     /// More than 10% of population
     Population,
+    /// Criteria not known by this library, it contains the value sent by AirParif
+    Unknown(String),
+}
+
+impl Criteria {
+    /// Return all criteria known by this library ([Unknown](#variant.Unknown) is excluded)
+    pub fn all() -> &'static [Criteria] {
+        static ALL: [Criteria; 2] = [Criteria::Area, Criteria::Population];
+        &ALL
+    }
+
+    /// Same as [`from_str`](#method.from_str) but unexpected values are kept in
+    /// [Criteria::Unknown](#variant.Unknown) instead of raising an error
+    ///
+    /// # Arguments
+    ///
+    /// * `s` - value sent by AirParif
+    pub fn from_str_lenient(s: &str) -> Criteria {
+        s.parse()
+            .unwrap_or_else(|_| Criteria::Unknown(s.to_string()))
+    }
+
+    /// Label of the criteria in the given language, unknown criteria are returned as sent by AirParif
+    ///
+    /// # Arguments
+    ///
+    /// * `lang` - Language of the label
+    pub fn localized(&self, lang: Lang) -> &str {
+        match (self, lang) {
+            (Criteria::Area, Lang::Fr) => "Superficie",
+            (Criteria::Area, Lang::En) => "Area",
+            (Criteria::Population, _) => "Population",
+            (Criteria::Unknown(value), _) => value,
+        }
+    }
+}
+
+impl fmt::Display for Criteria {
+    /// French label (`Superficie` or `Population`), unknown criteria are displayed as sent by AirParif.
+    /// Labels can be parsed back with [`from_str`](#method.from_str)
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.localized(Lang::Fr))
+    }
 }
 
 impl FromStr for Criteria {
     type Err = RParifError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        if s == "km" {
+        if s == "km" || s == "Superficie" {
             Ok(Criteria::Area)
-        } else if s == "pop" {
+        } else if s == "pop" || s == "Population" {
             Ok(Criteria::Population)
         } else {
             Err(RParifError::UnkownEnumValue(s.to_string()))
@@ -335,13 +1634,24 @@ impl FromStr for Criteria {
 
 #[cfg(test)]
 mod test {
-    use chrono::{Datelike, NaiveDate, Utc};
+    use std::collections::HashSet;
+    use std::convert::TryFrom;
 
-    use crate::objects::{Criteria, Episode, Level, Type};
+    use chrono::{Datelike, NaiveDate, NaiveTime, Utc};
+
+    use crate::clock::paris_date;
+    use crate::error::RParifError;
+    use crate::objects::{
+        alerting_pollutants, city_forecasts, dedup_indices, distinct_pollutants, episode_diff,
+        find, find_by_date, flatten_episodes, for_each_new_episode, group_by_date, group_by_insee,
+        index_bands, join_episodes, known_pollutants, missing_cities, normalize_pollutant,
+        overall_index, worst_pollutant_by_day, Criteria, Episode, Index, Insee, Lang, Level,
+        Quality, Type,
+    };
 
     #[test]
     fn test_episode_iterator() {
-        let today = Utc::today();
+        let today = paris_date(Utc::now());
         let mut episode = Episode::new(
             NaiveDate::from_ymd_opt(today.year(), today.month(), today.day()).unwrap(),
             None,
@@ -365,13 +1675,959 @@ mod test {
             vec![Criteria::Area],
         );
 
-        let mut i: usize = 0;
-        for pollution_episode in episode.clone() {
+        for (i, pollution_episode) in episode.clone().into_iter().enumerate() {
             assert_eq!(
                 pollution_episode,
                 episode.pollutants().get(i).unwrap().clone()
             );
-            i += 1;
+        }
+    }
+
+    #[test]
+    fn test_episode_iter() {
+        let mut episode = Episode::new(NaiveDate::from_ymd_opt(2020, 5, 18).unwrap(), None);
+        episode.add("o3".to_string(), Type::Forecast, Level::Info, vec![]);
+        episode.add("no2".to_string(), Type::Observed, Level::Alert, vec![]);
+
+        let names: Vec<String> = episode.iter().map(|p| p.pollutant_name()).collect();
+        assert_eq!(names, vec!["o3".to_string(), "no2".to_string()]);
+        let mut count = 0;
+        for pollutant in &episode {
+            assert!(!pollutant.pollutant_name().is_empty());
+            count += 1;
+        }
+        assert_eq!(count, 2);
+        assert_eq!(episode.into_iter().size_hint(), (2, Some(2)));
+    }
+
+    #[test]
+    fn test_alerting_pollutants() {
+        let date = NaiveDate::from_ymd_opt(2020, 5, 18).unwrap();
+        let mut today = Episode::new(date, None);
+        today.add("o3".to_string(), Type::Forecast, Level::Info, vec![]);
+        today.add("so2".to_string(), Type::Forecast, Level::Normal, vec![]);
+        let mut tomorrow = Episode::new(date.succ_opt().unwrap(), None);
+        tomorrow.add("no2".to_string(), Type::Forecast, Level::Alert, vec![]);
+        let episodes = vec![today, tomorrow];
+
+        let alert = alerting_pollutants(&episodes, Level::Alert);
+        assert_eq!(alert, ["no2".to_string()].iter().cloned().collect());
+        let info = alerting_pollutants(&episodes, Level::Info);
+        assert_eq!(
+            info,
+            ["o3".to_string(), "no2".to_string()]
+                .iter()
+                .cloned()
+                .collect()
+        );
+        assert_eq!(alerting_pollutants(&episodes, Level::Normal).len(), 3);
+    }
+
+    #[test]
+    fn test_criteria_union() {
+        let mut episode = Episode::new(NaiveDate::from_ymd_opt(2020, 5, 18).unwrap(), None);
+        assert!(episode.criteria_union().is_empty());
+        episode.add(
+            "o3".to_string(),
+            Type::Forecast,
+            Level::Info,
+            vec![Criteria::Population],
+        );
+        episode.add("so2".to_string(), Type::Forecast, Level::Info, vec![]);
+        episode.add(
+            "no2".to_string(),
+            Type::Observed,
+            Level::Alert,
+            vec![Criteria::Area, Criteria::Population],
+        );
+
+        assert_eq!(
+            episode.criteria_union(),
+            vec![Criteria::Population, Criteria::Area]
+        );
+    }
+
+    #[test]
+    fn test_recommendation() {
+        let mut episode = Episode::new(NaiveDate::from_ymd_opt(2020, 5, 18).unwrap(), None);
+        episode.add("o3".to_string(), Type::Forecast, Level::Alert, vec![]);
+        let pollutant = episode.pollutants().remove(0);
+
+        assert_eq!(
+            pollutant.advice(Lang::Fr),
+            Level::Alert.recommendation(Lang::Fr)
+        );
+        assert_eq!(
+            pollutant.advice(Lang::En),
+            Level::Alert.recommendation(Lang::En)
+        );
+        assert_ne!(
+            Level::Normal.recommendation(Lang::Fr),
+            Level::Info.recommendation(Lang::Fr)
+        );
+        assert_ne!(
+            Level::Info.recommendation(Lang::En),
+            Level::Alert.recommendation(Lang::En)
+        );
+        assert_ne!(
+            Level::Info.recommendation(Lang::Fr),
+            Level::Info.recommendation(Lang::En)
+        );
+    }
+
+    #[test]
+    fn test_from_str_lenient() {
+        assert_eq!(Level::from_str_lenient("alerte"), Level::Alert);
+        assert_eq!(
+            Level::from_str_lenient("urgence"),
+            Level::Unknown("urgence".to_string())
+        );
+        assert_eq!(Type::from_str_lenient("prevu"), Type::Forecast);
+        assert_eq!(
+            Type::from_str_lenient("estime"),
+            Type::Unknown("estime".to_string())
+        );
+        assert_eq!(Criteria::from_str_lenient("pop"), Criteria::Population);
+        assert_eq!(
+            Criteria::from_str_lenient("duree"),
+            Criteria::Unknown("duree".to_string())
+        );
+    }
+
+    #[test]
+    // Labels can be parsed back
+    fn test_display_round_trip() {
+        for level in [Level::Info, Level::Alert, Level::Normal] {
+            assert_eq!(level.to_string().parse::<Level>().ok(), Some(level));
+        }
+        for kind in [Type::Forecast, Type::Observed] {
+            assert_eq!(kind.to_string().parse::<Type>().ok(), Some(kind));
+        }
+        for criteria in [Criteria::Area, Criteria::Population] {
+            assert_eq!(
+                criteria.to_string().parse::<Criteria>().ok(),
+                Some(criteria)
+            );
+        }
+        assert_eq!(Level::Alert.to_string(), "Alerte");
+        assert_eq!(Type::Forecast.to_string(), "Prévu");
+        assert_eq!(
+            Level::from_str_lenient(&Level::Unknown("urgence".to_string()).to_string()),
+            Level::Unknown("urgence".to_string())
+        );
+    }
+
+    #[test]
+    fn test_episode_pollutant_and_max_level() {
+        let mut episode = Episode::new(NaiveDate::from_ymd_opt(2020, 5, 18).unwrap(), None);
+        assert_eq!(episode.max_level(), Level::Normal);
+
+        episode.add("o3".to_string(), Type::Forecast, Level::Info, vec![]);
+        episode.add(
+            "no2".to_string(),
+            Type::Observed,
+            Level::Unknown("urgence".to_string()),
+            vec![],
+        );
+        assert_eq!(episode.max_level(), Level::Info);
+
+        episode.add("pm10".to_string(), Type::Observed, Level::Alert, vec![]);
+        episode.add("so2".to_string(), Type::Observed, Level::Normal, vec![]);
+        assert_eq!(episode.max_level(), Level::Alert);
+
+        assert_eq!(
+            episode.pollutant("o3").map(|p| p.level()),
+            Some(Level::Info)
+        );
+        assert_eq!(
+            episode.pollutant("PM10").map(|p| p.level()),
+            Some(Level::Alert)
+        );
+        assert!(episode.pollutant("co").is_none());
+    }
+
+    #[test]
+    fn test_level_order() {
+        assert!(Level::Normal < Level::Info);
+        assert!(Level::Info < Level::Alert);
+        assert!(Level::Unknown("urgence".to_string()) < Level::Normal);
+        assert!(Level::Unknown("a".to_string()) < Level::Unknown("b".to_string()));
+        assert_eq!(
+            vec![Level::Info, Level::Alert, Level::Normal]
+                .into_iter()
+                .max(),
+            Some(Level::Alert)
+        );
+    }
+
+    #[test]
+    fn test_group_by_date() {
+        let day_1 = NaiveDate::from_ymd_opt(2020, 5, 17).unwrap();
+        let day_2 = NaiveDate::from_ymd_opt(2020, 5, 18).unwrap();
+        let first = Index::new(day_2, None, vec!["o3".to_string()], 40, None);
+        let second = Index::new(day_1, None, vec!["pm10".to_string()], 30, None);
+        let third = Index::new(day_2, None, vec!["no2".to_string()], 20, None);
+
+        let result = group_by_date(vec![first.clone(), second.clone(), third.clone()]);
+
+        assert_eq!(result.keys().collect::<Vec<_>>(), vec![&day_1, &day_2]);
+        assert_eq!(result[&day_1], vec![second]);
+        assert_eq!(result[&day_2], vec![first, third]);
+    }
+
+    #[test]
+    fn test_insee() {
+        assert_eq!(Insee::try_from("75101").unwrap().to_string(), "75101");
+        assert_eq!(" 2A004 ".parse::<Insee>().unwrap().as_str(), "2A004");
+        for wrong in &["7510", "751011", "7510a", "2C004", "", "75 01"] {
+            match Insee::try_from(*wrong).err().unwrap() {
+                RParifError::InvalidInsee(code) => assert_eq!(code, wrong.trim()),
+                _ => panic!("Wrong error"),
+            }
+        }
+    }
+
+    #[test]
+    fn test_insee_department() {
+        let paris: Insee = "75101".parse().unwrap();
+        assert_eq!(paris.department(), "75");
+        assert!(paris.is_ile_de_france());
+        let ajaccio: Insee = "2A004".parse().unwrap();
+        assert_eq!(ajaccio.department(), "2A");
+        assert!(!ajaccio.is_ile_de_france());
+    }
+
+    #[test]
+    fn test_to_eaqi() {
+        let date = NaiveDate::from_ymd_opt(2020, 5, 18).unwrap();
+        let eaqi = |pollutant: &str, index: u32| {
+            Index::new(date, None, vec![pollutant.to_string()], index, None).to_eaqi()
+        };
+
+        assert_eq!(eaqi("global", 0), Some(1));
+        assert_eq!(eaqi("global", 24), Some(1));
+        assert_eq!(eaqi("o3", 25), Some(2));
+        assert_eq!(eaqi("PM10", 74), Some(3));
+        assert_eq!(eaqi("no2", 75), Some(4));
+        assert_eq!(eaqi("so2", 149), Some(5));
+        assert_eq!(eaqi("pm25", 200), Some(6));
+        assert_eq!(eaqi("co", 30), None);
+        assert_eq!(Index::new(date, None, vec![], 30, None).to_eaqi(), None);
+    }
+
+    #[test]
+    fn test_index_bands() {
+        let bands = index_bands();
+        assert_eq!(bands.len(), 6);
+        assert_eq!(bands[0], (0, Quality::Good));
+        let date = NaiveDate::from_ymd_opt(2020, 5, 18).unwrap();
+        for (level, (lower, quality)) in bands.iter().enumerate() {
+            let index = Index::new(date, None, vec!["global".to_string()], *lower, None);
+            assert_eq!(index.to_eaqi(), Some(level as u8 + 1));
+            assert_eq!(quality.eaqi_level(), level as u8 + 1);
+        }
+    }
+
+    #[test]
+    fn test_city_forecasts() {
+        let day = |d: u32| NaiveDate::from_ymd_opt(2020, 5, d).unwrap();
+        let index = |d: u32, value: u32, insee: &str| {
+            Index::new(
+                day(d),
+                None,
+                vec!["o3".to_string()],
+                value,
+                insee.parse().ok(),
+            )
+        };
+        let indices = vec![
+            index(18, 40, "94028"),
+            index(17, 30, "75101"),
+            index(18, 35, "75101"),
+            index(18, 99, "75101"),
+            Index::new(day(18), None, vec!["global".to_string()], 50, None),
+        ];
+
+        let forecasts = city_forecasts(indices);
+
+        assert_eq!(forecasts.len(), 2);
+        assert_eq!(forecasts[0].insee().as_ref(), "75101");
+        assert_eq!(forecasts[0].days().len(), 2);
+        assert_eq!(forecasts[0].day(day(17)).map(|i| i.index()), Some(30));
+        assert_eq!(forecasts[0].day(day(18)).map(|i| i.index()), Some(35));
+        assert_eq!(forecasts[1].insee().as_ref(), "94028");
+        assert_eq!(forecasts[1].day(day(17)), None);
+    }
+
+    #[test]
+    fn test_for_each_new_episode() {
+        let date = NaiveDate::from_ymd_opt(2020, 5, 18).unwrap();
+        let mut episode = Episode::new(date, None);
+        episode.add("o3".to_string(), Type::Forecast, Level::Info, vec![]);
+        let mut seen = HashSet::new();
+        let mut new_alerts = Vec::new();
+
+        for_each_new_episode(&[episode.clone()], &mut seen, |d, p| {
+            new_alerts.push((d, p.pollutant_name(), p.level()))
+        });
+        assert_eq!(new_alerts, vec![(date, "o3".to_string(), Level::Info)]);
+
+        // Same alert polled again plus an escalation and a new pollutant
+        episode.add("no2".to_string(), Type::Observed, Level::Info, vec![]);
+        let mut escalated = Episode::new(date, None);
+        escalated.add("o3".to_string(), Type::Observed, Level::Alert, vec![]);
+        new_alerts.clear();
+        for_each_new_episode(&[episode, escalated], &mut seen, |d, p| {
+            new_alerts.push((d, p.pollutant_name(), p.level()))
+        });
+        assert_eq!(
+            new_alerts,
+            vec![
+                (date, "no2".to_string(), Level::Info),
+                (date, "o3".to_string(), Level::Alert)
+            ]
+        );
+        assert_eq!(seen.len(), 3);
+    }
+
+    #[test]
+    fn test_missing_cities() {
+        let date = NaiveDate::from_ymd_opt(2020, 5, 18).unwrap();
+        let indices = vec![
+            Index::new(date, None, vec!["o3".to_string()], 40, "75101".parse().ok()),
+            Index::new(date, None, vec!["global".to_string()], 50, None),
+        ];
+
+        assert_eq!(
+            missing_cities(vec!["99999", "75101", "94028", "99999"], &indices),
+            vec!["99999".to_string(), "94028".to_string()]
+        );
+        assert!(missing_cities(["75101"], &indices).is_empty());
+    }
+
+    #[test]
+    fn test_find() {
+        let day = |d: u32| NaiveDate::from_ymd_opt(2020, 5, d).unwrap();
+        let indices = vec![
+            Index::new(day(17), None, vec!["global".to_string()], 30, None),
+            Index::new(day(18), None, vec!["global".to_string()], 40, None),
+            Index::new(
+                day(18),
+                None,
+                vec!["no2".to_string(), "o3".to_string()],
+                50,
+                None,
+            ),
+        ];
+
+        assert_eq!(find_by_date(&indices, day(18)).map(|i| i.index()), Some(40));
+        assert_eq!(find_by_date(&indices, day(19)), None);
+        assert_eq!(find(&indices, day(18), "O3").map(|i| i.index()), Some(50));
+        assert_eq!(find(&indices, day(17), "o3"), None);
+    }
+
+    #[test]
+    fn test_time() {
+        let date = NaiveDate::from_ymd_opt(2020, 5, 18).unwrap();
+        let index = Index::new(date, None, vec!["o3".to_string()], 40, None);
+        assert_eq!(index.time(), None);
+        assert_eq!(index.date_time(), None);
+
+        let time = NaiveTime::from_hms_opt(11, 0, 0).unwrap();
+        let timed = index.clone().with_time(Some(time));
+        assert_eq!(timed.time(), Some(time));
+        assert_eq!(timed.date_time(), Some(date.and_time(time)));
+        assert_ne!(timed, index);
+    }
+
+    #[test]
+    fn test_quality() {
+        let boundaries = [
+            (0, Quality::Good),
+            (24, Quality::Good),
+            (25, Quality::Fair),
+            (49, Quality::Fair),
+            (50, Quality::Moderate),
+            (74, Quality::Moderate),
+            (75, Quality::Poor),
+            (99, Quality::Poor),
+            (100, Quality::VeryPoor),
+            (149, Quality::VeryPoor),
+            (150, Quality::ExtremelyPoor),
+            (u32::MAX, Quality::ExtremelyPoor),
+        ];
+        for (index, quality) in boundaries.iter() {
+            assert_eq!(Quality::from_index(*index), *quality, "index {}", index);
+        }
+
+        let date = NaiveDate::from_ymd_opt(2020, 5, 18).unwrap();
+        for (lower, quality) in index_bands() {
+            let index = Index::new(date, None, vec!["global".to_string()], *lower, None);
+            assert_eq!(Quality::from_index(*lower), *quality);
+            assert_eq!(Some(quality.eaqi_level()), index.to_eaqi());
+        }
+
+        assert_eq!(Quality::Good.color(), "#50f0e6");
+        assert_eq!(Quality::ExtremelyPoor.color(), "#7d2181");
+        assert!(Quality::Good < Quality::ExtremelyPoor);
+    }
+
+    #[test]
+    fn test_episode_diff() {
+        let date = NaiveDate::from_ymd_opt(2020, 5, 18).unwrap();
+        let episode = |alerts: &[(&str, Level)]| {
+            let mut episode = Episode::new(date, None);
+            for (pollutant, level) in alerts {
+                episode.add(
+                    pollutant.to_string(),
+                    Type::Forecast,
+                    level.clone(),
+                    vec![Criteria::Population],
+                );
+            }
+            episode
+        };
+        let old = vec![episode(&[("o3", Level::Info), ("no2", Level::Info)])];
+        let new = vec![episode(&[("o3", Level::Alert), ("pm10", Level::Info)])];
+
+        let diff = episode_diff(&old, &new);
+
+        assert_eq!(diff.added().len(), 1);
+        assert_eq!(diff.added()[0].0, date);
+        assert_eq!(diff.added()[0].1.pollutant_name(), "pm10");
+        assert_eq!(diff.removed().len(), 1);
+        assert_eq!(diff.removed()[0].1.pollutant_name(), "no2");
+        assert_eq!(diff.level_changed().len(), 1);
+        let (changed_date, before, after) = &diff.level_changed()[0];
+        assert_eq!(*changed_date, date);
+        assert_eq!(before.level(), Level::Info);
+        assert_eq!(after.level(), Level::Alert);
+        assert!(!diff.is_empty());
+        let display = diff.to_string();
+        assert_eq!(display.lines().count(), 3);
+        assert!(display.contains("~ 2020-05-18 o3 : "));
+
+        let same = episode_diff(&old, &old);
+        assert!(same.is_empty());
+        assert_eq!(same.to_string(), "No change");
+    }
+
+    #[test]
+    fn test_distinct_pollutants() {
+        let date = NaiveDate::from_ymd_opt(2020, 5, 18).unwrap();
+        let index = |pollutants: &[&str]| {
+            Index::new(
+                date,
+                None,
+                pollutants.iter().map(|p| p.to_string()).collect(),
+                30,
+                None,
+            )
+        };
+        let indices = vec![
+            index(&["pm10", "o3"]),
+            index(&["global"]),
+            index(&["o3"]),
+            index(&[]),
+        ];
+
+        assert_eq!(
+            distinct_pollutants(&indices, true),
+            vec!["global", "o3", "pm10"]
+        );
+        assert_eq!(distinct_pollutants(&indices, false), vec!["o3", "pm10"]);
+        assert!(distinct_pollutants(&[], true).is_empty());
+    }
+
+    #[test]
+    fn test_all_values() {
+        assert_eq!(Level::all(), &[Level::Normal, Level::Info, Level::Alert]);
+        assert!(Level::all().windows(2).all(|w| w[0] < w[1]));
+        assert_eq!(Type::all(), &[Type::Forecast, Type::Observed]);
+        assert_eq!(Criteria::all(), &[Criteria::Area, Criteria::Population]);
+        assert_eq!(
+            known_pollutants(),
+            &["global", "o3", "no2", "pm10", "pm25", "so2"]
+        );
+
+        // Every known value can be parsed back from its Display
+        for level in Level::all() {
+            assert_eq!(&Level::from_str_lenient(&level.to_string()), level);
+        }
+        for kind in Type::all() {
+            assert_eq!(&Type::from_str_lenient(&kind.to_string()), kind);
+        }
+        for criteria in Criteria::all() {
+            assert_eq!(&Criteria::from_str_lenient(&criteria.to_string()), criteria);
+        }
+        for pollutant in known_pollutants() {
+            assert_eq!(normalize_pollutant(&pollutant.to_uppercase()), *pollutant);
+        }
+    }
+
+    #[test]
+    fn test_group_by_insee() {
+        let mut indices = Vec::new();
+        for city in &["75101", "94028"] {
+            for day in 17..20 {
+                indices.push(Index::new(
+                    NaiveDate::from_ymd_opt(2020, 5, day).unwrap(),
+                    None,
+                    vec!["o3".to_string()],
+                    day,
+                    city.parse().ok(),
+                ));
+            }
+        }
+        let global = Index::new(
+            NaiveDate::from_ymd_opt(2020, 5, 18).unwrap(),
+            None,
+            vec!["global".to_string()],
+            50,
+            None,
+        );
+        indices.push(global.clone());
+
+        let result = group_by_insee(indices.clone());
+
+        assert_eq!(result.len(), 3);
+        assert_eq!(result[&"75101".parse().ok()], indices[0..3].to_vec());
+        assert_eq!(result[&"94028".parse().ok()], indices[3..6].to_vec());
+        assert_eq!(result[&None], vec![global]);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_day_serde() {
+        use crate::objects::Day;
+
+        assert_eq!(
+            serde_json::to_string(&Day::Yesterday).unwrap(),
+            "\"yesterday\""
+        );
+        assert_eq!(serde_json::to_string(&Day::Today).unwrap(), "\"today\"");
+        assert_eq!(
+            serde_json::from_str::<Day>("\"tomorrow\"").unwrap(),
+            Day::Tomorrow
+        );
+    }
+
+    #[test]
+    fn test_day_order() {
+        use crate::objects::Day;
+
+        let mut days = vec![Day::Tomorrow, Day::Yesterday, Day::Today];
+        days.sort();
+        assert_eq!(days, vec![Day::Yesterday, Day::Today, Day::Tomorrow]);
+    }
+
+    #[test]
+    fn test_index_try_from_json() {
+        let json = object! {"date" => "jour", "indice" => 35, "url_carte" => "http://map"};
+
+        let result = Index::try_from(&json);
+
+        assert_eq!(
+            result.ok(),
+            Some(Index::new(
+                paris_date(Utc::now()),
+                Some("http://map".to_string()),
+                vec!["global".to_string()],
+                35,
+                None
+            ))
+        );
+    }
+
+    #[test]
+    // Empty strings are converted into None
+    fn test_index_try_from_json_empty_strings() {
+        let json =
+            object! {"date" => "jour", "indice" => 35, "url_carte" => "", "commentaire" => ""};
+
+        let result = Index::try_from(&json).unwrap();
+
+        assert_eq!(result.map_url(), None);
+        assert_eq!(result.comment(), None);
+    }
+
+    #[test]
+    fn test_index_try_from_json_missing_indice() {
+        let json = object! {"date" => "jour"};
+
+        match Index::try_from(&json).err().unwrap() {
+            RParifError::MissingJsonKey { key, json: _ } => assert_eq!(key, "indice"),
+            _ => panic!("Wrong error"),
+        }
+    }
+
+    #[test]
+    fn test_episode_try_from_json() {
+        let json = object! {
+            "date" => "jour",
+            "detail" => "",
+            "o3" => object!{"type" => "prevu", "niveau" => "info", "criteres" => array!["km"]}
+        };
+        let mut expected = Episode::new(paris_date(Utc::now()), None);
+        expected.add(
+            "o3".to_string(),
+            Type::Forecast,
+            Level::Info,
+            vec![Criteria::Area],
+        );
+
+        let result = Episode::try_from(&json);
+
+        assert_eq!(result.ok(), Some(expected));
+    }
+
+    #[test]
+    // TryFrom is strict, unknown values are rejected
+    fn test_episode_try_from_json_unknown_level() {
+        let json = object! {
+            "date" => "jour",
+            "o3" => object!{"type" => "prevu", "niveau" => "urgence"}
+        };
+
+        match Episode::try_from(&json).err().unwrap() {
+            RParifError::UnkownEnumValue(token) => assert_eq!(token, "urgence"),
+            _ => panic!("Wrong error"),
+        }
+    }
+
+    #[test]
+    fn test_flatten_episodes() {
+        let day_1 = NaiveDate::from_ymd_opt(2020, 5, 17).unwrap();
+        let day_2 = NaiveDate::from_ymd_opt(2020, 5, 18).unwrap();
+        let mut first = Episode::new(day_1, None);
+        first.add("o3".to_string(), Type::Forecast, Level::Info, vec![]);
+        first.add("no2".to_string(), Type::Observed, Level::Alert, vec![]);
+        let second = Episode::new(day_2, None);
+        let mut third = Episode::new(day_2, None);
+        third.add("pm10".to_string(), Type::Forecast, Level::Normal, vec![]);
+
+        let result: Vec<(NaiveDate, String)> = flatten_episodes(vec![first, second, third])
+            .into_iter()
+            .map(|(date, p)| (date, p.pollutant_name()))
+            .collect();
+
+        assert_eq!(
+            result,
+            vec![
+                (day_1, "o3".to_string()),
+                (day_1, "no2".to_string()),
+                (day_2, "pm10".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_hash_set() {
+        let date = NaiveDate::from_ymd_opt(2020, 5, 17).unwrap();
+        let index = Index::new(date, None, vec!["o3".to_string()], 40, None);
+        let mut episode = Episode::new(date, None);
+        episode.add("o3".to_string(), Type::Forecast, Level::Info, vec![]);
+
+        let indices: HashSet<Index> = vec![index.clone(), index].into_iter().collect();
+        let episodes: HashSet<Episode> = vec![episode.clone(), episode].into_iter().collect();
+
+        assert_eq!(indices.len(), 1);
+        assert_eq!(episodes.len(), 1);
+    }
+
+    #[test]
+    fn test_dedup_indices() {
+        let date = NaiveDate::from_ymd_opt(2020, 5, 17).unwrap();
+        let first = Index::new(date, None, vec!["o3".to_string()], 40, None);
+        let second = Index::new(date, None, vec!["no2".to_string()], 30, None);
+        let third = Index::new(date, None, vec!["o3".to_string()], 40, "75101".parse().ok());
+
+        let result = dedup_indices(vec![
+            first.clone(),
+            second.clone(),
+            first.clone(),
+            third.clone(),
+            second.clone(),
+        ]);
+
+        assert_eq!(result, vec![first, second, third]);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_to_json() {
+        use crate::objects::{episodes_to_json, indices_to_json};
+
+        let date = NaiveDate::from_ymd_opt(2020, 5, 7).unwrap();
+        let index = Index::new(date, None, vec!["o3".to_string()], 40, "75101".parse().ok());
+        let mut episode = Episode::new(date, None);
+        episode.add(
+            "o3".to_string(),
+            Type::Forecast,
+            Level::Info,
+            vec![Criteria::Area],
+        );
+
+        let json = indices_to_json(&[index]);
+        assert!(json.starts_with('['));
+        assert!(json.contains("\"date\":\"2020-05-07\""));
+        assert!(json.contains("\"insee\":\"75101\""));
+        let json = episodes_to_json(&[episode]);
+        assert!(json.starts_with('['));
+        assert!(json.contains("\"date\":\"2020-05-07\""));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_round_trip() {
+        let date = NaiveDate::from_ymd_opt(2020, 5, 7).unwrap();
+        let index = Index::new(date, None, vec!["o3".to_string()], 40, None)
+            .with_comment(Some("Pic".to_string()));
+        let mut episode = Episode::new(date, Some("detail".to_string()));
+        episode.add("o3".to_string(), Type::Observed, Level::Alert, vec![]);
+
+        let json = serde_json::to_string(&index).unwrap();
+        assert!(json.contains("\"date\":\"2020-05-07\""));
+        let result = serde_json::from_str::<Index>(&json).unwrap();
+        assert_eq!(result, index);
+        assert_eq!(result.comment(), index.comment());
+        let json = serde_json::to_string(&episode).unwrap();
+        assert!(json.contains("\"date\":\"2020-05-07\""));
+        assert_eq!(serde_json::from_str::<Episode>(&json).unwrap(), episode);
+
+        let wrong = json.replace("2020-05-07", "07/05/2020");
+        assert!(serde_json::from_str::<Episode>(&wrong).is_err());
+    }
+
+    #[test]
+    fn test_worst_pollutant_by_day() {
+        let day_1 = NaiveDate::from_ymd_opt(2020, 5, 17).unwrap();
+        let day_2 = NaiveDate::from_ymd_opt(2020, 5, 18).unwrap();
+        let indices = vec![
+            Index::new(day_2, None, vec!["o3".to_string()], 40, None),
+            Index::new(day_1, None, vec!["no2".to_string()], 30, None),
+            Index::new(day_1, None, vec!["pm10".to_string()], 55, None),
+            Index::new(day_2, None, vec!["no2".to_string()], 40, None),
+        ];
+
+        let result = worst_pollutant_by_day(&indices);
+
+        assert_eq!(result.len(), 2);
+        assert_eq!(result[&day_1], (vec!["pm10".to_string()], 55));
+        assert_eq!(result[&day_2], (vec!["o3".to_string()], 40));
+        assert!(worst_pollutant_by_day(&[]).is_empty());
+    }
+
+    #[test]
+    // Map URL and comment are ignored by equality and hash
+    fn test_index_eq_hash() {
+        let date = NaiveDate::from_ymd_opt(2020, 5, 17).unwrap();
+        let first = Index::new(
+            date,
+            Some("a".to_string()),
+            vec!["o3".to_string()],
+            40,
+            None,
+        );
+        let second = Index::new(
+            date,
+            Some("b".to_string()),
+            vec!["o3".to_string()],
+            40,
+            None,
+        )
+        .with_comment(Some("Pic".to_string()));
+        let third = Index::new(date, None, vec!["o3".to_string()], 40, "75101".parse().ok());
+
+        assert_eq!(first, second);
+        assert_ne!(first, third);
+        let set: HashSet<Index> = vec![first.clone(), second, third].into_iter().collect();
+        assert_eq!(set.len(), 2);
+        assert_eq!(dedup_indices(set.into_iter().collect()).len(), 2);
+    }
+
+    #[test]
+    fn test_overall_index() {
+        let date = NaiveDate::from_ymd_opt(2020, 5, 17).unwrap();
+        let indices = vec![
+            Index::new(date, None, vec!["o3".to_string()], 40, None),
+            Index::new(date, None, vec!["Global".to_string()], 35, None),
+            Index::new(date, None, vec!["no2".to_string()], 62, None),
+        ];
+
+        assert_eq!(overall_index(&indices), 62);
+        assert_eq!(overall_index(&[]), 0);
+        assert!(!indices[0].is_global());
+        assert!(indices[1].is_global());
+    }
+
+    #[test]
+    fn test_as_fields() {
+        let date = NaiveDate::from_ymd_opt(2020, 5, 7).unwrap();
+        let index = Index::new(
+            date,
+            None,
+            vec!["pm10".to_string(), "no2".to_string()],
+            40,
+            "75101".parse().ok(),
+        );
+
+        assert_eq!(
+            index.as_fields(),
+            vec![
+                ("date", "2020-05-07".to_string()),
+                ("index", "40".to_string()),
+                ("pollutants", "pm10,no2".to_string()),
+                ("insee", "75101".to_string()),
+            ]
+        );
+        let global = Index::new(
+            date,
+            Some("a".to_string()),
+            vec!["global".to_string()],
+            35,
+            None,
+        )
+        .with_comment(Some("Bonne qualité".to_string()));
+        assert_eq!(
+            global.as_fields()[3..],
+            [
+                ("map_url", "a".to_string()),
+                ("comment", "Bonne qualité".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn test_pollutant_is() {
+        let date = NaiveDate::from_ymd_opt(2020, 5, 17).unwrap();
+        let index = Index::new(
+            date,
+            None,
+            vec!["PM10".to_string(), "no2".to_string()],
+            40,
+            None,
+        );
+
+        assert!(index.pollutant_is("pm10"));
+        assert!(index.pollutant_is("NO2"));
+        assert!(!index.pollutant_is("o3"));
+        assert!(!index.pollutant_is("global"));
+        assert!(!index.is_global());
+        assert_eq!(index.pollutant_count(), 2);
+        assert_eq!(Index::new(date, None, vec![], 0, None).pollutant_count(), 0);
+    }
+
+    #[test]
+    fn test_localized() {
+        assert_eq!(Lang::default(), Lang::Fr);
+        assert_eq!(Level::Alert.localized(Lang::Fr), "Alerte");
+        assert_eq!(Level::Alert.localized(Lang::En), "Alert");
+        assert_eq!(Type::Observed.localized(Lang::En), "Observed");
+        assert_eq!(Type::Observed.localized(Lang::Fr), "Constaté");
+        assert_eq!(Criteria::Area.localized(Lang::En), "Area");
+        assert_eq!(Quality::VeryPoor.localized(Lang::Fr), "Très mauvais");
+        assert_eq!(Quality::VeryPoor.localized(Lang::En), "Very poor");
+        assert_eq!(
+            Criteria::Unknown("vent".to_string()).localized(Lang::En),
+            "vent"
+        );
+        assert_eq!(
+            format!("{}", Type::Forecast),
+            Type::Forecast.localized(Lang::default())
+        );
+    }
+
+    #[test]
+    fn test_borrowing_accessors() {
+        let date = NaiveDate::from_ymd_opt(2020, 5, 17).unwrap();
+        let index = Index::new(
+            date,
+            Some("a".to_string()),
+            vec!["o3".to_string()],
+            40,
+            "75101".parse().ok(),
+        );
+        let episode = Episode::new(date, Some("detail".to_string()));
+
+        assert_eq!(index.map_url_ref(), Some("a"));
+        assert_eq!(index.pollutants_ref(), &["o3".to_string()]);
+        assert_eq!(index.insee_ref().map(|i| i.as_str()), Some("75101"));
+        assert_eq!(episode.detail_ref(), Some("detail"));
+        assert_eq!(Episode::new(date, None).detail_ref(), None);
+    }
+
+    #[test]
+    fn test_join_episodes() {
+        let day_1 = NaiveDate::from_ymd_opt(2020, 5, 17).unwrap();
+        let day_2 = NaiveDate::from_ymd_opt(2020, 5, 18).unwrap();
+        let mut episode = Episode::new(day_1, None);
+        episode.add("O3".to_string(), Type::Observed, Level::Alert, vec![]);
+        let indices = vec![
+            Index::new(
+                day_1,
+                None,
+                vec!["o3".to_string()],
+                80,
+                "75101".parse().ok(),
+            ),
+            Index::new(
+                day_1,
+                None,
+                vec!["no2".to_string()],
+                30,
+                "75101".parse().ok(),
+            ),
+            Index::new(
+                day_2,
+                None,
+                vec!["o3".to_string()],
+                40,
+                "75101".parse().ok(),
+            ),
+        ];
+
+        let result = join_episodes(indices.clone(), &[episode.clone()]);
+
+        assert_eq!(result.len(), 3);
+        assert_eq!(result[0].index(), &indices[0]);
+        assert_eq!(result[0].episode(), episode.pollutant("O3"));
+        assert_eq!(result[1].episode(), None);
+        assert_eq!(result[2].episode(), None);
+    }
+
+    #[test]
+    fn test_day_offset() {
+        use crate::objects::Day;
+
+        for day in &[Day::Yesterday, Day::Today, Day::Tomorrow] {
+            assert_eq!(Day::from_offset(day.offset_days()), Some(*day));
+        }
+        assert_eq!(Day::Yesterday.offset_days(), -1);
+        assert_eq!(Day::from_offset(2), None);
+        assert_eq!(Day::from_offset(-2), None);
+    }
+
+    #[test]
+    fn test_day_try_from() {
+        use crate::objects::Day;
+
+        assert_eq!(Day::try_from(-1i64).ok(), Some(Day::Yesterday));
+        assert_eq!(Day::try_from(0i64).ok(), Some(Day::Today));
+        assert_eq!(Day::try_from(1i64).ok(), Some(Day::Tomorrow));
+        assert_eq!(Day::try_from(0u8).ok(), Some(Day::Today));
+        assert_eq!(Day::try_from(1u8).ok(), Some(Day::Tomorrow));
+        for day in &[Day::Yesterday, Day::Today, Day::Tomorrow] {
+            assert_eq!(Day::try_from(day.offset_days()).ok(), Some(*day));
+        }
+
+        match Day::try_from(2i64).err().unwrap() {
+            RParifError::UnkownEnumValue(token) => assert_eq!(token, "2"),
+            _ => panic!("Wrong error"),
+        }
+        match Day::try_from(255u8).err().unwrap() {
+            RParifError::UnkownEnumValue(token) => assert_eq!(token, "255"),
+            _ => panic!("Wrong error"),
         }
     }
 }
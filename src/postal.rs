@@ -0,0 +1,178 @@
+//! Postal code to INSEE code resolution
+//!
+//! AirParif's `idxville` endpoint expects INSEE city codes, which differ from postal codes.
+//! A postal code can be shared by several communes, so resolution returns a list of INSEE codes.
+//!
+//! Data come from La Poste's [Base officielle des codes postaux](https://www.data.gouv.fr/en/datasets/base-officielle-des-codes-postaux/)
+//! (`laposte_hexasmal.csv`, `;` separated, first column is the INSEE code and third column the postal code).
+//!
+//! This module is available with the `postal` feature (enabled by default).
+//!
+//! # Limitation
+//!
+//! **Only Paris arrondissements are embedded in the library** (`data/postal_codes.csv`), other
+//! postal codes of Île-de-France are unknown to [`PostalCodes::embedded`](./struct.PostalCodes.html#method.embedded)
+//! and [`insee_for_postal`](./fn.insee_for_postal.html) : [`PostalCodes::resolve`](./struct.PostalCodes.html#method.resolve)
+//! raises [RParifError::UnknownPostalCode](../error/enum.RParifError.html#variant.UnknownPostalCode) for them.
+//! Other communes can be resolved by loading the full file with [`PostalCodes::from_csv`](./struct.PostalCodes.html#method.from_csv).
+use std::collections::HashMap;
+
+use crate::error::RParifError;
+
+/// Embedded postal codes (Paris arrondissements)
+const EMBEDDED: &str = include_str!("../data/postal_codes.csv");
+
+/// Table that maps a postal code to INSEE codes
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct PostalCodes {
+    /// INSEE codes per postal code
+    table: HashMap<String, Vec<String>>,
+}
+
+impl PostalCodes {
+    /// Table built from the embedded dataset (Paris arrondissements only)
+    pub fn embedded() -> PostalCodes {
+        PostalCodes::from_csv(EMBEDDED)
+    }
+
+    /// Build a table from La Poste CSV content.
+    /// Lines that don't start with an INSEE code (headers, comments, empty lines) are ignored.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - CSV content, `;` separated, with INSEE code in first column and postal code in third
+    pub fn from_csv(data: &str) -> PostalCodes {
+        let mut result = PostalCodes::default();
+        for line in data.lines() {
+            let columns: Vec<&str> = line.split(';').map(|c| c.trim()).collect();
+            if columns.len() < 3 || columns[0].is_empty() || columns[0].starts_with('#') {
+                continue;
+            }
+            result.add(columns[2], columns[0]);
+        }
+        result
+    }
+
+    /// Add a mapping between a postal code and an INSEE code.
+    /// Duplicated mappings are ignored.
+    ///
+    /// # Arguments
+    ///
+    /// * `postal` - Postal code
+    ///
+    /// * `insee` - INSEE code of a commune using this postal code
+    pub fn add(&mut self, postal: &str, insee: &str) {
+        let codes = self.table.entry(postal.to_string()).or_default();
+        if !codes.iter().any(|c| c == insee) {
+            codes.push(insee.to_string());
+        }
+    }
+
+    /// Return INSEE codes for a postal code, in the order they were loaded.
+    /// The result is empty if postal code is unknown, and can contain several codes
+    /// when a postal code is shared by many communes.
+    ///
+    /// # Arguments
+    ///
+    /// * `code` - Postal code
+    pub fn insee_for_postal(&self, code: &str) -> Vec<String> {
+        self.table.get(code.trim()).cloned().unwrap_or_default()
+    }
+
+    /// Resolve a list of postal codes into INSEE codes. Resulting codes are deduplicated.
+    ///
+    /// # Arguments
+    ///
+    /// * `codes` - Postal codes (`Vec`, slice or iterator of `&str` or `String`)
+    ///
+    /// # Errors
+    ///
+    /// * [RParifError::UnknownPostalCode](../error/enum.RParifError.html#variant.UnknownPostalCode)
+    ///   if one of the postal codes can't be resolved
+    pub fn resolve<I, S>(&self, codes: I) -> Result<Vec<String>, RParifError>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let mut result: Vec<String> = Vec::new();
+        for code in codes {
+            let code = code.as_ref();
+            let insee = self.insee_for_postal(code);
+            if insee.is_empty() {
+                return Err(RParifError::UnknownPostalCode(code.to_string()));
+            }
+            for i in insee {
+                if !result.contains(&i) {
+                    result.push(i);
+                }
+            }
+        }
+        Ok(result)
+    }
+}
+
+/// Return INSEE codes for a postal code using the embedded dataset (Paris arrondissements only).
+/// See [PostalCodes](./struct.PostalCodes.html) to use another dataset.
+///
+/// # Arguments
+///
+/// * `code` - Postal code
+pub fn insee_for_postal(code: &str) -> Vec<String> {
+    PostalCodes::embedded().insee_for_postal(code)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_embedded() {
+        assert_eq!(insee_for_postal("75001"), vec!["75101".to_string()]);
+        assert_eq!(insee_for_postal("75020"), vec!["75120".to_string()]);
+        assert_eq!(insee_for_postal("75116"), vec!["75116".to_string()]);
+        assert_eq!(insee_for_postal("75016"), vec!["75116".to_string()]);
+        assert!(insee_for_postal("99999").is_empty());
+
+        // Communes outside Paris are not embedded
+        assert!(insee_for_postal("95000").is_empty());
+        match PostalCodes::embedded().resolve(["95000"]).err().unwrap() {
+            RParifError::UnknownPostalCode(code) => assert_eq!(code, "95000"),
+            _ => panic!("Wrong error"),
+        }
+    }
+
+    #[test]
+    // One postal code shared by several communes
+    fn test_from_csv_many_communes() {
+        let table = PostalCodes::from_csv(
+            "#Code_commune_INSEE;Nom_commune;Code_postal;Libelle_acheminement\n\
+            95127;CERGY;95000;CERGY\n\
+            95450;NEUVILLE SUR OISE;95000;NEUVILLE SUR OISE\n\
+            95127;CERGY;95800;CERGY\n",
+        );
+
+        assert_eq!(
+            table.insee_for_postal("95000"),
+            vec!["95127".to_string(), "95450".to_string()]
+        );
+        assert_eq!(table.insee_for_postal("95800"), vec!["95127".to_string()]);
+    }
+
+    #[test]
+    fn test_resolve() {
+        let table = PostalCodes::from_csv(
+            "95127;CERGY;95000;CERGY\n\
+            95450;NEUVILLE SUR OISE;95000;NEUVILLE SUR OISE\n\
+            95127;CERGY;95800;CERGY\n",
+        );
+
+        assert_eq!(
+            table.resolve(["95000", "95800"]).ok(),
+            Some(vec!["95127".to_string(), "95450".to_string()])
+        );
+        match table.resolve(["95000", "75001"]).err().unwrap() {
+            RParifError::UnknownPostalCode(code) => assert_eq!(code, "75001"),
+            _ => panic!("Wrong error"),
+        }
+    }
+}
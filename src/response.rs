@@ -0,0 +1,352 @@
+//! Raw AirParif responses (requires `serde` feature)
+//!
+//! These types mirror JSON responses of each endpoint and implement `Deserialize`, so
+//! AirParif JSON can be parsed without the HTTP client (for example from a recorded file)
+//! and then converted into [Index](../objects/struct.Index.html) or
+//! [Episode](../objects/struct.Episode.html) with `TryFrom`.
+//!
+//! ```rust
+//! use std::convert::TryFrom;
+//!
+//! use rparif::objects::Index;
+//! use rparif::response::IndiceResponse;
+//!
+//! let response: IndiceResponse = serde_json::from_str(r#"[{"date":"jour","indice":35}]"#).unwrap();
+//! let indices = Vec::<Index>::try_from(response)?;
+//! # Ok::<(), rparif::error::RParifError>(())
+//! ```
+//!
+//! Conversion is the one used by [RParifClient](../client/struct.RParifClient.html) with default
+//! settings : relative dates (`hier`, `jour`, `demain`) are resolved against current date in
+//! Paris and unknown enum values are errors.
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+
+use json::JsonValue;
+use serde::{Deserialize, Serialize};
+
+use crate::client::RParifClientBuilder;
+use crate::error::RParifError;
+use crate::objects::{Episode, Index};
+
+/// Response of `indice` endpoint
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct IndiceResponse(pub Vec<IndiceEntry>);
+
+/// Global index of a day in `indice` response
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct IndiceEntry {
+    /// Relative date : `hier`, `jour` or `demain`
+    pub date: String,
+    /// Index
+    pub indice: u32,
+    /// URL to a map
+    #[serde(default)]
+    pub url_carte: Option<String>,
+    /// Comment or advisory
+    #[serde(default)]
+    pub commentaire: Option<String>,
+}
+
+/// Response of `indiceJour` endpoint
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct IndiceJourResponse {
+    /// Date in `dd/mm/yyyy` format
+    pub date: String,
+    /// Comment or advisory
+    #[serde(default)]
+    pub commentaire: Option<String>,
+    /// Index per pollutant (including `global`)
+    #[serde(flatten)]
+    pub pollutants: BTreeMap<String, IndiceJourEntry>,
+}
+
+/// Index of a pollutant in `indiceJour` response
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct IndiceJourEntry {
+    /// Index
+    pub indice: u32,
+    /// URL to a map
+    #[serde(default)]
+    pub url_carte: Option<String>,
+}
+
+/// Response of `idxville` endpoint
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct IdxVilleResponse(pub Vec<IdxVilleEntry>);
+
+/// Indices of a city in `idxville` response
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct IdxVilleEntry {
+    /// INSEE city code
+    pub ninsee: String,
+    /// Index per relative date (`hier`, `jour` or `demain`)
+    #[serde(flatten)]
+    pub days: BTreeMap<String, IdxVilleDay>,
+}
+
+/// Index of a city for a day in `idxville` response
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct IdxVilleDay {
+    /// Index
+    pub indice: u32,
+    /// Pollutants used to compute index
+    #[serde(default)]
+    pub polluants: Vec<String>,
+}
+
+/// Response of `episode` endpoint
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct EpisodeResponse(pub Vec<EpisodeEntry>);
+
+/// Alert of a day in `episode` response
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct EpisodeEntry {
+    /// Relative date : `hier`, `jour` or `demain`
+    pub date: String,
+    /// Description and advice
+    #[serde(default)]
+    pub detail: Option<String>,
+    /// Alert per pollutant
+    #[serde(flatten)]
+    pub pollutants: BTreeMap<String, EpisodePollutant>,
+}
+
+/// Alert of a pollutant in `episode` response
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct EpisodePollutant {
+    /// `prevu` or `constate`
+    #[serde(rename = "type")]
+    pub kind: String,
+    /// `normal`, `info` or `alerte`
+    pub niveau: String,
+    /// `km` and/or `pop`
+    #[serde(default)]
+    pub criteres: Vec<String>,
+}
+
+/// Serialize a response back into the JSON sent by AirParif, so it's converted exactly
+/// like responses received by [RParifClient](../client/struct.RParifClient.html)
+///
+/// # Arguments
+///
+/// * `response` - Response to serialize
+fn to_json<T: Serialize>(response: &T) -> Result<JsonValue, RParifError> {
+    let json = serde_json::to_string(response).expect("Response is always serializable");
+    Ok(json::parse(&json)?)
+}
+
+impl TryFrom<IndiceResponse> for Vec<Index> {
+    type Error = RParifError;
+
+    fn try_from(response: IndiceResponse) -> Result<Self, Self::Error> {
+        RParifClientBuilder::new("")
+            .config
+            .index_to_index(to_json(&response)?)
+    }
+}
+
+impl TryFrom<IndiceJourResponse> for Vec<Index> {
+    type Error = RParifError;
+
+    /// Indices are ordered by pollutant name
+    fn try_from(response: IndiceJourResponse) -> Result<Self, Self::Error> {
+        RParifClientBuilder::new("")
+            .config
+            .index_day_to_index(to_json(&response)?, None)
+    }
+}
+
+impl TryFrom<IdxVilleResponse> for Vec<Index> {
+    type Error = RParifError;
+
+    /// Indices are ordered by city, then by date
+    fn try_from(response: IdxVilleResponse) -> Result<Self, Self::Error> {
+        let mut result = RParifClientBuilder::new("")
+            .config
+            .idxville_to_index(to_json(&response)?)?;
+        for city in result.chunk_by_mut(|a, b| a.insee() == b.insee()) {
+            city.sort_by_key(|index| index.date());
+        }
+        Ok(result)
+    }
+}
+
+impl TryFrom<EpisodeResponse> for Vec<Episode> {
+    type Error = RParifError;
+
+    /// Pollutants of an episode are ordered by name
+    fn try_from(response: EpisodeResponse) -> Result<Self, Self::Error> {
+        RParifClientBuilder::new("")
+            .config
+            .episode_to_episode(to_json(&response)?)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::{Duration, NaiveDate};
+
+    use crate::objects::{Criteria, Level, Type};
+
+    use super::*;
+
+    #[test]
+    fn test_indice_response() {
+        let response: IndiceResponse = serde_json::from_str(
+            r#"[{"date":"hier","indice":35,"url_carte":"a"},{"date":"jour","indice":50}]"#,
+        )
+        .unwrap();
+        let today = RParifClientBuilder::new("").config.today();
+
+        let result = Vec::<Index>::try_from(response);
+
+        assert_eq!(
+            result.ok(),
+            Some(vec![
+                Index::new(
+                    today - Duration::days(1),
+                    Some("a".to_string()),
+                    vec!["global".to_string()],
+                    35,
+                    None
+                ),
+                Index::new(today, None, vec!["global".to_string()], 50, None),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_indice_jour_response() {
+        let response: IndiceJourResponse = serde_json::from_str(
+            r#"{"date":"31/12/2019","commentaire":"","o3":{"indice":40,"url_carte":"b"},"global":{"indice":35,"url_carte":""}}"#,
+        )
+        .unwrap();
+        let date = NaiveDate::from_ymd_opt(2019, 12, 31).unwrap();
+
+        let result = Vec::<Index>::try_from(response);
+        let urls: Vec<Option<String>> = result
+            .as_ref()
+            .unwrap()
+            .iter()
+            .map(|i| i.map_url())
+            .collect();
+        assert_eq!(urls, vec![None, Some("b".to_string())]);
+
+        assert_eq!(
+            result.ok(),
+            Some(vec![
+                Index::new(date, None, vec!["global".to_string()], 35, None),
+                Index::new(
+                    date,
+                    Some("b".to_string()),
+                    vec!["o3".to_string()],
+                    40,
+                    None
+                ),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_idxville_response() {
+        let response: IdxVilleResponse = serde_json::from_str(
+            r#"[{"ninsee":"75101","jour":{"indice":50,"polluants":["pm10"]},"hier":{"indice":25,"polluants":["no2"]}}]"#,
+        )
+        .unwrap();
+        let today = RParifClientBuilder::new("").config.today();
+
+        let result = Vec::<Index>::try_from(response);
+
+        assert_eq!(
+            result.ok(),
+            Some(vec![
+                Index::new(
+                    today - Duration::days(1),
+                    None,
+                    vec!["no2".to_string()],
+                    25,
+                    "75101".parse().ok()
+                ),
+                Index::new(
+                    today,
+                    None,
+                    vec!["pm10".to_string()],
+                    50,
+                    "75101".parse().ok()
+                ),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_episode_response() {
+        let response: EpisodeResponse = serde_json::from_str(
+            r#"[{"date":"jour","detail":"","so2":{"type":"constate","niveau":"alerte","criteres":["pop"]}}]"#,
+        )
+        .unwrap();
+        let mut episode = Episode::new(RParifClientBuilder::new("").config.today(), None);
+        episode.add(
+            "so2".to_string(),
+            Type::Observed,
+            Level::Alert,
+            vec![Criteria::Population],
+        );
+
+        let result = Vec::<Episode>::try_from(response);
+
+        assert_eq!(result.ok(), Some(vec![episode]));
+    }
+
+    #[test]
+    fn test_episode_response_unknown_level() {
+        let response: EpisodeResponse = serde_json::from_str(
+            r#"[{"date":"jour","so2":{"type":"constate","niveau":"urgence"}}]"#,
+        )
+        .unwrap();
+
+        match Vec::<Episode>::try_from(response).err().unwrap() {
+            RParifError::UnkownEnumValue(token) => assert_eq!(token, "urgence"),
+            _ => panic!("Wrong error"),
+        }
+    }
+
+    #[test]
+    // Raw responses must be converted like client responses
+    fn test_same_conversion_as_client() {
+        let json = r#"[{"ninsee":"75102","jour":{"indice":50,"polluants":["o3"]}},{"ninsee":"75101","demain":{"indice":30},"hier":{"indice":25,"polluants":["NO2"]}}]"#;
+        let response: IdxVilleResponse = serde_json::from_str(json).unwrap();
+        let result = Vec::<Index>::try_from(response).unwrap();
+
+        let mut expected = crate::client::parse_city_indices(json).unwrap();
+        expected.swap(1, 2);
+        assert_eq!(result, expected);
+        assert_eq!(result[1].pollutants(), vec!["no2"]);
+
+        let json = r#"[{"date":"demain","indice":35,"url_carte":"","commentaire":"a"}]"#;
+        let response: IndiceResponse = serde_json::from_str(json).unwrap();
+        assert_eq!(
+            Vec::<Index>::try_from(response).ok(),
+            crate::client::parse_indices(json).ok()
+        );
+    }
+
+    #[test]
+    fn test_normalize_pollutants() {
+        let response: IdxVilleResponse = serde_json::from_str(
+            r#"[{"ninsee":"75101","jour":{"indice":50,"polluants":["PM10","Benzene"]}}]"#,
+        )
+        .unwrap();
+        let result = Vec::<Index>::try_from(response).unwrap();
+        assert_eq!(result[0].pollutants(), vec!["pm10", "Benzene"]);
+
+        let response: EpisodeResponse =
+            serde_json::from_str(r#"[{"date":"jour","O3":{"type":"prevu","niveau":"info"}}]"#)
+                .unwrap();
+        let result = Vec::<Episode>::try_from(response).unwrap();
+        assert!(result[0].pollutant("o3").is_some());
+    }
+}
@@ -0,0 +1,268 @@
+//! HTTP transport used by [RParifClient](../client/struct.RParifClient.html) (requires `blocking` feature)
+//!
+//! [RParifClient](../client/struct.RParifClient.html) only needs to send `GET` requests, this is
+//! abstracted by the [Transport](./trait.Transport.html) trait. By default it relies on
+//! [ReqwestTransport](./struct.ReqwestTransport.html), use
+//! [`build_with_transport`](../client/struct.RParifClientBuilder.html#method.build_with_transport)
+//! to provide another implementation, e.g. [MemoryTransport](./struct.MemoryTransport.html) to
+//! test without network.
+//!
+//! ```rust
+//! use rparif::client::RParifClient;
+//! use rparif::transport::MemoryTransport;
+//!
+//! let transport = MemoryTransport::new().with_response("indice", 200, r#"[{"date":"jour","indice":35}]"#);
+//! let client = RParifClient::builder("my-api-key").build_with_transport(transport);
+//! assert_eq!(client.index()?[0].index(), 35);
+//! # Ok::<(), rparif::error::RParifError>(())
+//! ```
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use reqwest::blocking::{Client, Response};
+use reqwest::header::{RETRY_AFTER, USER_AGENT};
+use reqwest::StatusCode;
+
+use crate::client::{
+    endpoint_name, read_response, BodyBuffer, ConditionalCache, DEFAULT_MAX_RESPONSE_BYTES,
+};
+use crate::error::RParifError;
+
+/// Target of log messages
+const LOG_TARGET: &str = "rparif::transport";
+
+/// Send `GET` requests to AirParif API
+pub trait Transport: fmt::Debug + Send + Sync {
+    /// Call `url` and return HTTP status and body of the response
+    ///
+    /// # Arguments
+    ///
+    /// * `url` - URL to call, it contains the API key
+    ///
+    /// # Errors
+    ///
+    /// Any [RParifError](../error/enum.RParifError.html), non 2XX status can either be returned
+    /// as is or as an error
+    fn get(&self, url: &str) -> Result<(u16, String), RParifError>;
+}
+
+/// [Transport](./trait.Transport.html) relying on reqwest's blocking client
+#[derive(Clone, Debug)]
+pub struct ReqwestTransport {
+    /// HTTP client
+    client: Client,
+    /// User-Agent header sent with each request
+    user_agent: String,
+    /// Last responses used for conditional requests, if enabled
+    conditional: Option<Arc<ConditionalCache>>,
+    /// Maximum size of a response body, in bytes
+    max_response_bytes: usize,
+}
+
+impl ReqwestTransport {
+    /// Constructor
+    ///
+    /// # Arguments
+    ///
+    /// * `client` - HTTP client
+    ///
+    /// * `user_agent` - User-Agent header sent with each request
+    pub fn new(client: Client, user_agent: &str) -> ReqwestTransport {
+        ReqwestTransport {
+            client,
+            user_agent: user_agent.to_string(),
+            conditional: None,
+            max_response_bytes: DEFAULT_MAX_RESPONSE_BYTES,
+        }
+    }
+
+    /// Set the maximum size of a response body (see
+    /// [`RParifClientBuilder::max_response_bytes`](../client/struct.RParifClientBuilder.html#method.max_response_bytes))
+    ///
+    /// # Arguments
+    ///
+    /// * `max` - maximum size in bytes
+    pub fn max_response_bytes(mut self, max: usize) -> ReqwestTransport {
+        self.max_response_bytes = max;
+        self
+    }
+
+    /// Choose whether conditional requests are sent (see
+    /// [`RParifClientBuilder::conditional_requests`](../client/struct.RParifClientBuilder.html#method.conditional_requests)).
+    /// On `304 Not Modified` status, the last response is returned with a 200 status.
+    ///
+    /// # Arguments
+    ///
+    /// * `enabled` - `true` to send conditional requests
+    pub fn conditional_requests(mut self, enabled: bool) -> ReqwestTransport {
+        self.conditional = if enabled {
+            Some(Arc::new(ConditionalCache::default()))
+        } else {
+            None
+        };
+        self
+    }
+}
+
+impl Transport for ReqwestTransport {
+    /// # Errors
+    ///
+    /// * [RParifError::RequestError](../error/enum.RParifError.html#variant.RequestError) when reqwest lib
+    ///   fails
+    ///
+    /// * [RParifError::RateLimited](../error/enum.RParifError.html#variant.RateLimited) and
+    ///   [RParifError::CallError](../error/enum.RParifError.html#variant.CallError) for non 2XX status,
+    ///   so that `Retry-After` header is kept
+    ///
+    /// * [RParifError::ResponseTooLarge](../error/enum.RParifError.html#variant.ResponseTooLarge) when
+    ///   the body exceeds the maximum size
+    fn get(&self, url: &str) -> Result<(u16, String), RParifError> {
+        let mut request = self
+            .client
+            .get(url)
+            .header(USER_AGENT, self.user_agent.as_str());
+        if let Some(conditional) = &self.conditional {
+            for (name, value) in conditional.request_headers(url) {
+                request = request.header(name, value);
+            }
+        }
+        let mut response: Response = request.send()?;
+        let status = response.status();
+        if status == StatusCode::NOT_MODIFIED {
+            if let Some(body) = self.conditional.as_ref().and_then(|c| c.body(url)) {
+                debug!(target: LOG_TARGET, "Not modified, reusing last response");
+                return Ok((StatusCode::OK.as_u16(), body));
+            }
+        }
+        let headers = response.headers().clone();
+        let retry_after = response
+            .headers()
+            .get(RETRY_AFTER)
+            .and_then(|value| value.to_str().ok())
+            .map(|value| value.to_string());
+        let mut buffer = BodyBuffer::new(self.max_response_bytes, response.content_length());
+        if !buffer.exceeded() {
+            if let Err(err) = response.copy_to(&mut buffer) {
+                // Writing fails on purpose when the body is too large
+                if !buffer.exceeded() {
+                    return Err(err.into());
+                }
+            }
+        }
+        let body = buffer.into_text(url)?;
+        if !status.is_success() {
+            read_response(url, status.as_u16(), false, retry_after.as_deref(), &body)?;
+        }
+        if let Some(conditional) = &self.conditional {
+            conditional.store(url, &headers, &body);
+        }
+        Ok((status.as_u16(), body))
+    }
+}
+
+/// In-memory [Transport](./trait.Transport.html) returning predefined responses per endpoint
+/// (`indice`, `indiceJour`, `idxville` or `episode`), whatever the parameters.
+/// Endpoints without response get a 404 status.
+#[derive(Clone, Debug, Default)]
+pub struct MemoryTransport {
+    /// Status and body per endpoint name
+    responses: HashMap<String, (u16, String)>,
+}
+
+impl MemoryTransport {
+    /// Construct a transport without any response
+    pub fn new() -> MemoryTransport {
+        MemoryTransport::default()
+    }
+
+    /// Set the response of an endpoint
+    ///
+    /// # Arguments
+    ///
+    /// * `endpoint` - Endpoint name (`indice`, `indiceJour`, `idxville` or `episode`)
+    ///
+    /// * `status` - HTTP status
+    ///
+    /// * `body` - HTTP body
+    pub fn with_response(mut self, endpoint: &str, status: u16, body: &str) -> MemoryTransport {
+        self.responses
+            .insert(endpoint.to_string(), (status, body.to_string()));
+        self
+    }
+}
+
+impl Transport for MemoryTransport {
+    fn get(&self, url: &str) -> Result<(u16, String), RParifError> {
+        Ok(self
+            .responses
+            .get(endpoint_name(url))
+            .cloned()
+            .unwrap_or_else(|| (404, String::new())))
+    }
+}
+
+/// [Transport](./trait.Transport.html) replaying responses recorded in a directory, one
+/// `<endpoint>.json` file per endpoint (`indice.json`, `indiceJour.json`, `idxville.json`
+/// and `episode.json`), whatever the parameters. A file that can't be read gives a 404 status
+/// with the reason as body.
+#[derive(Clone, Debug)]
+pub struct FixtureTransport {
+    /// Directory containing recorded responses
+    directory: PathBuf,
+}
+
+impl FixtureTransport {
+    /// Constructor
+    ///
+    /// # Arguments
+    ///
+    /// * `directory` - Directory containing recorded responses
+    pub fn new<P: Into<PathBuf>>(directory: P) -> FixtureTransport {
+        FixtureTransport {
+            directory: directory.into(),
+        }
+    }
+}
+
+impl Transport for FixtureTransport {
+    fn get(&self, url: &str) -> Result<(u16, String), RParifError> {
+        let file = self.directory.join(format!("{}.json", endpoint_name(url)));
+        debug!(target: LOG_TARGET, "Reading fixture {}", file.display());
+        match fs::read_to_string(&file) {
+            Ok(body) => Ok((200, body)),
+            Err(err) => Ok((404, format!("{} : {}", file.display(), err))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_endpoint_name() {
+        assert_eq!(endpoint_name("http://host/1.1/indice?key=secret"), "indice");
+        assert_eq!(
+            endpoint_name("http://host/indiceJour?date=jour&key=secret"),
+            "indiceJour"
+        );
+        assert_eq!(endpoint_name("http://host/episode"), "episode");
+    }
+
+    #[test]
+    fn test_memory_transport() {
+        let transport = MemoryTransport::new().with_response("episode", 200, "[]");
+
+        assert_eq!(
+            transport.get("http://host/episode?key=secret").unwrap(),
+            (200, "[]".to_string())
+        );
+        assert_eq!(
+            transport.get("http://host/indice?key=secret").unwrap(),
+            (404, String::new())
+        );
+    }
+}
@@ -1,14 +1,18 @@
+#![cfg(feature = "blocking")]
 #[cfg(test)]
 extern crate env_logger;
 extern crate httpmock;
 extern crate rparif;
 
 use chrono::{Datelike, Duration, NaiveDate, Utc};
-use httpmock::Method::GET;
 use httpmock::prelude::*;
+use httpmock::Method::GET;
 
 use rparif::client::RParifClient;
-use rparif::objects::{Criteria, Day, Episode, Index, Level, Type};
+use rparif::clock::paris_date;
+use rparif::objects::{Criteria, Day, Episode, Index, Insee, Level, Type};
+#[cfg(feature = "postal")]
+use rparif::postal::PostalCodes;
 
 fn init() {
     let _ = env_logger::builder().is_test(true).try_init();
@@ -35,31 +39,32 @@ fn test_indice() {
 
     search_mock.assert();
     assert!(result.is_ok(), "Got an Err() : {:?}", result);
-    let today = Utc::today();
+    let today = paris_date(Utc::now());
     let yesterday = today.checked_sub_signed(Duration::days(1)).unwrap();
     let tomorrow = today.checked_add_signed(Duration::days(1)).unwrap();
-    let mut expected = Vec::new();
-    expected.push(Index::new(
-        NaiveDate::from_ymd_opt(yesterday.year(), yesterday.month(), yesterday.day()).unwrap(),
-        Some("http://localhost:5000/services/cartes/indice/date/hier".to_string()),
-        vec!["global".to_string()],
-        35,
-        None,
-    ));
-    expected.push(Index::new(
-        NaiveDate::from_ymd_opt(today.year(), today.month(), today.day()).unwrap(),
-        Some("http://localhost:5000/services/cartes/indice/date/jour".to_string()),
-        vec!["global".to_string()],
-        50,
-        None,
-    ));
-    expected.push(Index::new(
-        NaiveDate::from_ymd_opt(tomorrow.year(), tomorrow.month(), tomorrow.day()).unwrap(),
-        Some("http://localhost:5000/services/cartes/indice/date/demain".to_string()),
-        vec!["global".to_string()],
-        70,
-        None,
-    ));
+    let expected = vec![
+        Index::new(
+            NaiveDate::from_ymd_opt(yesterday.year(), yesterday.month(), yesterday.day()).unwrap(),
+            Some("http://localhost:5000/services/cartes/indice/date/hier".to_string()),
+            vec!["global".to_string()],
+            35,
+            None,
+        ),
+        Index::new(
+            NaiveDate::from_ymd_opt(today.year(), today.month(), today.day()).unwrap(),
+            Some("http://localhost:5000/services/cartes/indice/date/jour".to_string()),
+            vec!["global".to_string()],
+            50,
+            None,
+        ),
+        Index::new(
+            NaiveDate::from_ymd_opt(tomorrow.year(), tomorrow.month(), tomorrow.day()).unwrap(),
+            Some("http://localhost:5000/services/cartes/indice/date/demain".to_string()),
+            vec!["global".to_string()],
+            70,
+            None,
+        ),
+    ];
 
     assert_eq!(result.ok(), Some(expected));
 }
@@ -89,35 +94,36 @@ fn test_indice_day() {
     search_mock.assert();
     assert!(result.is_ok(), "Got an Err() : {:?}", result);
 
-    let mut expected = Vec::new();
-    expected.push(Index::new(
-        NaiveDate::from_ymd_opt(2012, 8, 9).unwrap(),
-        Some("http://localhost:5000/services/cartes/indice/date/hier".to_string()),
-        vec!["global".to_string()],
-        35,
-        None,
-    ));
-    expected.push(Index::new(
-        NaiveDate::from_ymd_opt(2012, 8, 9).unwrap(),
-        Some("http://localhost:5000/services/cartes/indice/date/hier/pol/O2".to_string()),
-        vec!["o2".to_string()],
-        20,
-        None,
-    ));
-    expected.push(Index::new(
-        NaiveDate::from_ymd_opt(2012, 8, 9).unwrap(),
-        Some("http://localhost:5000/services/cartes/indice/date/hier/pol/O3".to_string()),
-        vec!["o3".to_string()],
-        86,
-        None,
-    ));
-    expected.push(Index::new(
-        NaiveDate::from_ymd_opt(2012, 8, 9).unwrap(),
-        Some("http://localhost:5000/services/cartes/indice/date/hier/pol/PM10".to_string()),
-        vec!["pm10".to_string()],
-        125,
-        None,
-    ));
+    let expected = vec![
+        Index::new(
+            NaiveDate::from_ymd_opt(2012, 8, 9).unwrap(),
+            Some("http://localhost:5000/services/cartes/indice/date/hier".to_string()),
+            vec!["global".to_string()],
+            35,
+            None,
+        ),
+        Index::new(
+            NaiveDate::from_ymd_opt(2012, 8, 9).unwrap(),
+            Some("http://localhost:5000/services/cartes/indice/date/hier/pol/O2".to_string()),
+            vec!["o2".to_string()],
+            20,
+            None,
+        ),
+        Index::new(
+            NaiveDate::from_ymd_opt(2012, 8, 9).unwrap(),
+            Some("http://localhost:5000/services/cartes/indice/date/hier/pol/O3".to_string()),
+            vec!["o3".to_string()],
+            86,
+            None,
+        ),
+        Index::new(
+            NaiveDate::from_ymd_opt(2012, 8, 9).unwrap(),
+            Some("http://localhost:5000/services/cartes/indice/date/hier/pol/PM10".to_string()),
+            vec!["pm10".to_string()],
+            125,
+            None,
+        ),
+    ];
 
     assert_eq!(result.ok(), Some(expected));
 }
@@ -146,7 +152,7 @@ fn test_indice_city() {
     search_mock.assert();
     assert!(result.is_ok(), "Got an Err() : {:?}", result);
 
-    let today = Utc::today();
+    let today = paris_date(Utc::now());
     let yesterday = today.checked_sub_signed(Duration::days(1)).unwrap();
     let tomorrow = today.checked_add_signed(Duration::days(1)).unwrap();
 
@@ -156,42 +162,42 @@ fn test_indice_city() {
             None,
             vec!["no2".to_string(), "pm10".to_string()],
             25,
-            Some("75101".to_string()),
+            "75101".parse().ok(),
         ),
         Index::new(
             NaiveDate::from_ymd_opt(today.year(), today.month(), today.day()).unwrap(),
             None,
             vec!["pm10".to_string()],
             50,
-            Some("75101".to_string()),
+            "75101".parse().ok(),
         ),
         Index::new(
             NaiveDate::from_ymd_opt(tomorrow.year(), tomorrow.month(), tomorrow.day()).unwrap(),
             None,
             vec!["o3".to_string()],
             36,
-            Some("75101".to_string()),
+            "75101".parse().ok(),
         ),
         Index::new(
             NaiveDate::from_ymd_opt(yesterday.year(), yesterday.month(), yesterday.day()).unwrap(),
             None,
             vec!["no2".to_string()],
             100,
-            Some("94028".to_string()),
+            "94028".parse().ok(),
         ),
         Index::new(
             NaiveDate::from_ymd_opt(today.year(), today.month(), today.day()).unwrap(),
             None,
             vec!["o3".to_string()],
             40,
-            Some("94028".to_string()),
+            "94028".parse().ok(),
         ),
         Index::new(
             NaiveDate::from_ymd_opt(tomorrow.year(), tomorrow.month(), tomorrow.day()).unwrap(),
             None,
             vec!["o3".to_string(), "no2".to_string(), "pm10".to_string()],
             95,
-            Some("94028".to_string()),
+            "94028".parse().ok(),
         ),
     ];
 
@@ -222,7 +228,7 @@ fn test_episode() {
     search_mock.assert();
     assert!(result.is_ok(), "Got an Err() : {:?}", result);
 
-    let today = Utc::today();
+    let today = paris_date(Utc::now());
     let yesterday = today.checked_sub_signed(Duration::days(1)).unwrap();
     let tomorrow = today.checked_add_signed(Duration::days(1)).unwrap();
 
@@ -269,3 +275,156 @@ fn test_episode() {
 
     assert_eq!(result.ok(), Some(expected));
 }
+
+#[test]
+#[cfg(feature = "postal")]
+fn test_indice_city_by_postal() {
+    init();
+
+    let server = MockServer::start();
+    let search_mock = server.mock(|when, then| {
+        when.method(GET)
+            .path("/idxville")
+            .query_param("key", "dummy")
+            .query_param("villes", "75101,75116");
+        then.status(200).body(
+            "[{\"ninsee\":\"75101\",\"jour\":{\"indice\":50,\"polluants\":[\"pm10\"]}},\
+            {\"ninsee\":\"75116\",\"jour\":{\"indice\":40,\"polluants\":[\"o3\"]}}]",
+        );
+    });
+
+    let base_url = &server.base_url();
+    let client = RParifClient::new_test("dummy", base_url);
+    let result = client.index_city_by_postal(["75001", "75016"], &PostalCodes::embedded());
+
+    search_mock.assert();
+    assert!(result.is_ok(), "Got an Err() : {:?}", result);
+    assert_eq!(result.unwrap().len(), 2);
+}
+
+#[test]
+fn test_episode_raw() {
+    init();
+
+    let body = "[{\"date\":\"jour\",\"o3\":{\"type\":\"prevu\",\"niveau\":\"info\",\"criteres\":[\"km\"]},\
+    \"detail\":\"\"}]";
+    let server = MockServer::start();
+    let search_mock = server.mock(|when, then| {
+        when.method(GET)
+            .path("/episode")
+            .query_param("key", "dummy");
+        then.status(200).body(body);
+    });
+
+    let base_url = &server.base_url();
+    let client = RParifClient::new_test("dummy", base_url);
+    let result = client.episode_raw();
+
+    search_mock.assert();
+    assert!(result.is_ok(), "Got an Err() : {:?}", result);
+    let (_, raw) = result.unwrap();
+    assert_eq!(raw, json::parse(body).unwrap());
+    assert_eq!(raw[0]["o3"]["niveau"], "info");
+}
+
+#[test]
+fn test_indice_city_all_days() {
+    init();
+
+    let server = MockServer::start();
+    let search_mock = server.mock(|when, then| {
+        when.method(GET)
+            .path("/idxville")
+            .query_param("key", "dummy")
+            .query_param("villes", "75101,94028");
+        then.status(200)
+            .body("[{\"ninsee\":\"75101\",\"hier\":{\"indice\":25,\"polluants\":[\"no2\"]},\"jour\":\
+            {\"indice\":50,\"polluants\":[\"pm10\"]}},{\"ninsee\":\"94028\",\"jour\":{\"indice\":40,\"polluants\":[\"o3\"]}}]");
+    });
+
+    let base_url = &server.base_url();
+    let client = RParifClient::new_test("dummy", base_url);
+    let result = client.index_city_all_days(vec!["75101", "94028"]);
+
+    search_mock.assert();
+    assert!(result.is_ok(), "Got an Err() : {:?}", result);
+    let result = result.unwrap();
+    let today = paris_date(Utc::now());
+    let yesterday = today.checked_sub_signed(Duration::days(1)).unwrap();
+    assert_eq!(result.len(), 2);
+    assert_eq!(result[&yesterday].len(), 1);
+    assert_eq!(result[&today].len(), 2);
+}
+
+#[test]
+fn test_indice_city_batches() {
+    init();
+
+    let server = MockServer::start();
+    let first_mock = server.mock(|when, then| {
+        when.method(GET)
+            .path("/idxville")
+            .query_param("key", "dummy")
+            .query_param("villes", "75101,75102");
+        then.status(200).body(
+            "[{\"ninsee\":\"75101\",\"jour\":{\"indice\":50,\"polluants\":[\"pm10\"]}},\
+            {\"ninsee\":\"75102\",\"jour\":{\"indice\":40,\"polluants\":[\"o3\"]}}]",
+        );
+    });
+    let second_mock = server.mock(|when, then| {
+        when.method(GET)
+            .path("/idxville")
+            .query_param("key", "dummy")
+            .query_param("villes", "75103");
+        then.status(200)
+            .body("[{\"ninsee\":\"75103\",\"jour\":{\"indice\":30,\"polluants\":[\"no2\"]}}]");
+    });
+
+    let base_url = &server.base_url();
+    let client = RParifClient::builder("dummy")
+        .base_url(base_url)
+        .city_batch_size(2)
+        .build()
+        .unwrap();
+    let result = client.index_city_raw(vec!["75101", "75102", "75103"]);
+
+    first_mock.assert();
+    second_mock.assert();
+    assert!(result.is_ok(), "Got an Err() : {:?}", result);
+    let (result, raw) = result.unwrap();
+    let cities: Vec<Option<Insee>> = result.iter().map(|i| i.insee()).collect();
+    assert_eq!(
+        cities,
+        vec![
+            "75101".parse().ok(),
+            "75102".parse().ok(),
+            "75103".parse().ok()
+        ]
+    );
+    assert_eq!(raw.len(), 3);
+}
+
+#[test]
+fn test_from_fixtures() {
+    init();
+
+    let client =
+        RParifClient::from_fixtures(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures"));
+    let today = paris_date(Utc::now());
+
+    let result = client.index();
+    assert!(result.is_ok(), "Got an Err() : {:?}", result);
+    let indices: Vec<u32> = result.unwrap().iter().map(|i| i.index()).collect();
+    assert_eq!(indices, vec![53, 49, 49]);
+
+    let result = client.episode();
+    assert!(result.is_ok(), "Got an Err() : {:?}", result);
+    let result = result.unwrap();
+    assert_eq!(result[0].date(), today);
+    assert_eq!(result[0].pollutant("o3").unwrap().level(), Level::Info);
+
+    match client.index_day(Day::Today).err() {
+        Some(rparif::error::RParifError::CallError { status, .. }) => assert_eq!(status, 404),
+        other => panic!("Wrong result : {:?}", other),
+    }
+}